@@ -1,32 +1,229 @@
+use std::{
+    alloc::{GlobalAlloc, Layout, System},
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
-// use zas_lsif_tools::Indexer;
-use indexer::Indexer;
+use zas_lsif_tools::{
+    cli::{Opts, DEFAULT_TODO_MARKERS},
+    emitter::{
+        emitter::Emitter,
+        file_emitter::{wait_until_flushed, FileEmitter, OutputFormat, DEFAULT_BATCH_SIZE},
+    },
+    indexer::indexer::Indexer,
+    protocol::types::{Language, ResultSet, DEFAULT_LSIF_VERSION, MONIKER_SCHEME},
+};
+
+/// An allocator that keeps track of the peak and total number of allocated bytes.
+struct PeakAllocator {
+    current: AtomicUsize,
+    peak: AtomicUsize,
+    total: AtomicUsize,
+}
+
+unsafe impl GlobalAlloc for PeakAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.total.fetch_add(layout.size(), Ordering::SeqCst);
+        let current = self.current.fetch_add(layout.size(), Ordering::SeqCst) + layout.size();
+        self.peak.fetch_max(current, Ordering::SeqCst);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        self.current.fetch_sub(layout.size(), Ordering::SeqCst);
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: PeakAllocator = PeakAllocator {
+    current: AtomicUsize::new(0),
+    peak: AtomicUsize::new(0),
+    total: AtomicUsize::new(0),
+};
+
+/// Returns the project to benchmark and its language: the directory in `LSIF_BENCH_DIR`,
+/// in the language in `LSIF_BENCH_LANGUAGE` (JavaScript by default), if it is set, the
+/// TypeScript test fixtures otherwise.
+fn bench_project() -> (PathBuf, Language) {
+    match std::env::var_os("LSIF_BENCH_DIR") {
+        Some(dir) => {
+            let language = std::env::var("LSIF_BENCH_LANGUAGE")
+                .map(|language| language.parse().expect("Unknown LSIF_BENCH_LANGUAGE"))
+                .unwrap_or(Language::JavaScript);
+            (PathBuf::from(dir), language)
+        }
+        None => (
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/test_data/TypeScript"),
+            Language::TypeScript,
+        ),
+    }
+}
+
+/// Returns the options to index the given project, with the dump written to a temporary file.
+fn bench_opts(project_root: PathBuf, language: Language) -> Opts {
+    let mut opt = Opts {
+        project_root,
+        language: language.into(),
+        output: None,
+        out_dir: None,
+        output_format: OutputFormat::Ndjson,
+        buffer_size: None,
+        incremental: None,
+        lsif_version: DEFAULT_LSIF_VERSION.to_string(),
+        validate_graph: false,
+        stats: false,
+        stats_file: None,
+        report_unresolved: None,
+        emit_external_monikers: false,
+        timings: None,
+        files_from: None,
+        git_ref: None,
+        since: None,
+        follow_symlinks: false,
+        include_hidden: false,
+        exclude: vec![],
+        language_override: vec![],
+        max_file_size: None,
+        parse_timeout_ms: None,
+        threads: None,
+        dry_run: false,
+        relative_paths: false,
+        query_dir: None,
+        no_diagnostics: false,
+        todo_comments: false,
+        todo_markers: DEFAULT_TODO_MARKERS.split(',').map(String::from).collect(),
+        no_hover: false,
+        definitions_only: false,
+        emit_byte_offsets: false,
+        max_import_depth: 8,
+        treat_tests_as_local: false,
+        test_glob: vec![],
+        moniker_scheme: MONIKER_SCHEME.to_string(),
+        project_name: None,
+        append: false,
+        config: None,
+        quiet: true,
+        verbose: 0,
+        args: vec![],
+        appended_after_id: 0,
+    };
+    opt.canonicalize_paths().unwrap();
+    opt.output = Some(std::env::temp_dir().join("lsif-os-bench-dump.json"));
+    opt
+}
+
+/// Indexes the project of the given options and waits until the dump is flushed.
+fn index(opt: Opts) {
+    let output = std::fs::File::create(opt.output.as_ref().unwrap()).unwrap();
+    let (emitter, signal_receiver) = FileEmitter::new(output, opt.output_format);
+    Indexer::index(black_box(opt), black_box(emitter)).unwrap();
+    wait_until_flushed(&signal_receiver).unwrap();
+}
 
+/// Indexes the benchmarked project end to end, including flushing the dump.
 pub fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("threejs", |b| {
-        b.iter(|| {
-            let mut opt: Opts = Opts {
-                project_root: PathBuf::from("/Users/zas/Dev/three.js"),
-                language: Language::JavaScript,
-                output: None,
-            };
-            opt.canonicalize_paths();
-
-            let output = std::fs::OpenOptions::new()
-                .write(true)
-                .create(true)
-                .open(&opt.output.clone().unwrap())
-                .unwrap();
-
-            let (emitter, signal_receiver) = FileEmitter::new(output);
-
-            Indexer::index(black_box(opt), black_box(emitter)).unwrap();
-
-            // Wait until the buffer is flushed
-            signal_receiver.recv().unwrap();
-        })
+    let (project_root, language) = bench_project();
+
+    c.bench_function("index", |b| {
+        b.iter(|| index(bench_opts(project_root.clone(), language)))
+    });
+}
+
+/// Reports the peak memory used while indexing. Since files are parsed and analyzed
+/// one at a time per thread, the peak should not grow with the number of files.
+pub fn peak_memory_benchmark(_c: &mut Criterion) {
+    let (project_root, language) = bench_project();
+    let opt = bench_opts(project_root, language);
+
+    ALLOCATOR
+        .peak
+        .store(ALLOCATOR.current.load(Ordering::SeqCst), Ordering::SeqCst);
+    index(opt);
+
+    println!(
+        "peak memory: {} KiB",
+        ALLOCATOR.peak.load(Ordering::SeqCst) / 1024
+    );
+}
+
+/// Reports the number of bytes allocated while indexing the test fixtures.
+pub fn fixture_allocations_benchmark(_c: &mut Criterion) {
+    let opt = bench_opts(
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src/tests/test_data/TypeScript"),
+        Language::TypeScript,
+    );
+
+    let total = ALLOCATOR.total.load(Ordering::SeqCst);
+    index(opt);
+
+    println!(
+        "allocated: {} KiB",
+        (ALLOCATOR.total.load(Ordering::SeqCst) - total) / 1024
+    );
+}
+
+/// Writes a JavaScript project with many references to exported and local definitions
+/// and returns its root directory.
+fn reference_heavy_fixture() -> PathBuf {
+    let root = std::env::temp_dir().join("lsif-os-bench-references");
+    if root.exists() {
+        return root;
+    }
+    std::fs::create_dir_all(&root).unwrap();
+
+    for file in 0..200 {
+        let mut content = String::new();
+        for def in 0..20 {
+            content.push_str(&format!("export function f{}_{}(a) {{\n", file, def));
+            for _ in 0..20 {
+                content.push_str(&format!("    f{}_{}(a);\n", (file + 1) % 200, def));
+            }
+            content.push_str("    return a;\n}\n");
+        }
+        std::fs::write(root.join(format!("file{}.js", file)), content).unwrap();
+    }
+
+    root
+}
+
+/// Indexes a large reference-heavy project. Most of the time is spent linking references
+/// to their definitions after the analysis, so this measures the second indexing pass.
+pub fn reference_heavy_benchmark(c: &mut Criterion) {
+    let project_root = reference_heavy_fixture();
+
+    c.bench_function("reference_heavy", |b| {
+        b.iter(|| index(bench_opts(project_root.clone(), Language::JavaScript)))
     });
 }
 
-criterion_group!(benches, criterion_benchmark);
+/// Compares the throughput of emitting entries one by one and in batches.
+pub fn emitter_batching_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("emitter_batching");
+    for &batch_size in [1, DEFAULT_BATCH_SIZE].iter() {
+        group.bench_function(format!("batch_size_{}", batch_size), |b| {
+            b.iter(|| {
+                let (emitter, signal_receiver) =
+                    FileEmitter::with_writer(std::io::sink(), OutputFormat::Ndjson);
+                let mut emitter = emitter.with_batch_size(batch_size);
+                for _ in 0..100_000 {
+                    emitter.emit_vertex(black_box(ResultSet {}));
+                }
+                emitter.end();
+                wait_until_flushed(&signal_receiver).unwrap();
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    peak_memory_benchmark,
+    fixture_allocations_benchmark,
+    reference_heavy_benchmark,
+    emitter_batching_benchmark
+);
 criterion_main!(benches);
@@ -1,5 +1,10 @@
 use std::{env, fs, path::PathBuf};
 
+#[path = "build/query_check.rs"]
+mod query_check;
+
+use query_check::{check_query, Grammar};
+
 fn get_opt_level() -> u32 {
     env::var("OPT_LEVEL").unwrap().parse::<u32>().unwrap()
 }
@@ -106,7 +111,52 @@ fn build_dir(dir: &str, language: &str) {
     }
 }
 
+/// The query files and the grammars whose node types they match. The Lua grammar is not
+/// compiled in, so its query is not validated.
+const QUERIES: &[(&str, &str, &str)] = &[
+    (
+        "JavaScript",
+        "queries/javascript.scm",
+        "parsers/tree-sitter-javascript",
+    ),
+    (
+        "GraphQL",
+        "queries/graphql.scm",
+        "parsers/tree-sitter-graphql",
+    ),
+    ("Java", "queries/java.scm", "parsers/tree-sitter-java"),
+    (
+        "TypeScript",
+        "queries/typescript.scm",
+        "parsers/tree-sitter-typescript/tsx",
+    ),
+];
+
+/// Reads the node types and field names of the grammar in the given directory.
+fn read_grammar(dir: &str) -> Grammar {
+    let path = PathBuf::from(get_cwd()).join(dir).join("src/grammar.json");
+    let content = fs::read_to_string(&path).unwrap();
+    Grammar::from_json(&serde_json::from_str(&content).unwrap())
+}
+
+/// Fails the build if a query file is not valid for its grammar, so that a broken query
+/// does not ship when the tests are not run.
+fn validate_queries() {
+    for (language, query, grammar) in QUERIES {
+        let source = fs::read_to_string(PathBuf::from(get_cwd()).join(query)).unwrap();
+        if let Err(e) = check_query(&source, &read_grammar(grammar)) {
+            eprintln!(
+                "Error in the query file for the {} language: \n'\n{}\n' is not valid {}. (line {}, column {})",
+                language, e.message, e.kind, e.line, e.column
+            );
+            std::process::exit(1);
+        }
+    }
+}
+
 fn main() {
+    validate_queries();
+
     // <------- JavaScript ------->
     let dir: PathBuf = ["parsers", "tree-sitter-javascript", "src"]
         .iter()
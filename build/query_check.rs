@@ -0,0 +1,175 @@
+//! Checks the query files against the grammars when building, see `validate_queries` in
+//! `build.rs`. This file is included by the build script and by the tests, so it only
+//! depends on `std` and `serde_json`.
+
+use std::collections::HashSet;
+
+/// The node types and field names of a grammar, read from its `grammar.json`, which unlike
+/// `node-types.json` also has the extras, e.g. comments.
+pub struct Grammar {
+    pub named: HashSet<String>,
+    pub anonymous: HashSet<String>,
+    pub fields: HashSet<String>,
+}
+
+impl Grammar {
+    /// Reads the node types and field names of the given `grammar.json` content.
+    pub fn from_json(json: &serde_json::Value) -> Self {
+        let mut grammar = Grammar {
+            named: ["_", "ERROR"].iter().map(|s| s.to_string()).collect(),
+            anonymous: HashSet::new(),
+            fields: HashSet::new(),
+        };
+        if let Some(rules) = json["rules"].as_object() {
+            grammar.named.extend(rules.keys().cloned());
+        }
+        grammar.add_rule(&json["rules"]);
+        grammar.add_rule(&json["externals"]);
+        grammar
+    }
+
+    /// Adds the node types and field names of the given rule and its members.
+    fn add_rule(&mut self, rule: &serde_json::Value) {
+        let text = |key: &str| rule[key].as_str().unwrap_or_default().to_string();
+        match rule["type"].as_str() {
+            Some("STRING") => {
+                self.anonymous.insert(text("value"));
+            }
+            Some("ALIAS") if rule["named"] == true => {
+                self.named.insert(text("value"));
+            }
+            Some("ALIAS") => {
+                self.anonymous.insert(text("value"));
+            }
+            Some("SYMBOL") => {
+                self.named.insert(text("name"));
+            }
+            Some("FIELD") => {
+                self.fields.insert(text("name"));
+            }
+            _ => {}
+        }
+        match rule {
+            serde_json::Value::Array(rules) => rules.iter().for_each(|r| self.add_rule(r)),
+            serde_json::Value::Object(rule) => rule.values().for_each(|r| self.add_rule(r)),
+            _ => {}
+        }
+    }
+}
+
+/// An error in a query file, reported like the errors of `query_for_language`.
+pub struct QueryError {
+    pub message: String,
+    pub kind: &'static str,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Checks that the parentheses and brackets of the given query are balanced, and that its
+/// node types and field names exist in the given grammar. The queries can only be compiled
+/// with `Query::new` once the grammars are linked, so the rest is only checked at runtime.
+pub fn check_query(source: &str, grammar: &Grammar) -> Result<(), QueryError> {
+    let chars: Vec<char> = source.chars().collect();
+    let (mut line, mut column) = (1, 1);
+    // The open delimiters, and whether they open a predicate, e.g. `(#eq? @a @b)`
+    let mut open: Vec<(char, bool, usize, usize)> = vec![];
+    let mut after_paren = false;
+    let mut i = 0;
+    while i < chars.len() {
+        let (c, start_line, start_column) = (chars[i], line, column);
+        let in_predicate = matches!(open.last(), Some((_, true, _, _)));
+        let mut end = i + 1;
+        match c {
+            ';' => {
+                while end < chars.len() && chars[end] != '\n' {
+                    end += 1;
+                }
+            }
+            '"' => {
+                while end < chars.len() && chars[end] != '"' {
+                    end += if chars[end] == '\\' { 2 } else { 1 };
+                }
+                end = (end + 1).min(chars.len());
+                let text: String = chars[i + 1..end - 1].iter().collect();
+                if !in_predicate && !grammar.anonymous.contains(&text.replace("\\", "")) {
+                    return Err(QueryError {
+                        message: text,
+                        kind: "NodeType",
+                        line: start_line,
+                        column: start_column,
+                    });
+                }
+            }
+            '(' | '[' => {
+                let predicate = c == '(' && chars.get(end) == Some(&'#');
+                open.push((c, predicate || in_predicate, line, column));
+            }
+            ')' | ']' => match open.pop() {
+                Some((open, ..)) if (open == '(') == (c == ')') => {}
+                _ => {
+                    return Err(QueryError {
+                        message: c.to_string(),
+                        kind: "Syntax",
+                        line,
+                        column,
+                    })
+                }
+            },
+            '@' | '#' => {
+                while end < chars.len() && is_identifier_char(chars[end]) {
+                    end += 1;
+                }
+            }
+            // A quantifier, e.g. `(comment)?`, the others are not identifier characters
+            '?' => {}
+            c if is_identifier_char(c) => {
+                while end < chars.len() && is_identifier_char(chars[end]) {
+                    end += 1;
+                }
+                let word: String = chars[i..end].iter().collect();
+                let (kind, valid) = if chars.get(end) == Some(&':') {
+                    end += 1;
+                    ("Field", grammar.fields.contains(&word))
+                } else if after_paren {
+                    ("NodeType", grammar.named.contains(&word))
+                } else {
+                    ("Syntax", in_predicate)
+                };
+                if !valid && !in_predicate {
+                    return Err(QueryError {
+                        message: word,
+                        kind,
+                        line: start_line,
+                        column: start_column,
+                    });
+                }
+            }
+            _ => {}
+        }
+        if !c.is_whitespace() {
+            after_paren = c == '(';
+        }
+        for c in &chars[i..end] {
+            if *c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        i = end;
+    }
+    match open.pop() {
+        Some((c, _, line, column)) => Err(QueryError {
+            message: c.to_string(),
+            kind: "Syntax",
+            line,
+            column,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_alphanumeric() || ['_', '-', '.', '?', '!'].contains(&c)
+}
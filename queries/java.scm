@@ -13,6 +13,17 @@
 (enum_declaration
   name: (identifier) @definition.scoped)
 
+(enum_constant
+  name: (identifier) @definition.member)
+
+(enum_declaration
+  name: (identifier) @name
+  body: (enum_body) @members)
+
+(field_access
+  object: (identifier) @receiver
+  field: (identifier) @reference.member)
+
 (variable_declarator
   name: (identifier) @definition.scoped)
 
@@ -33,6 +33,19 @@
     name: (identifier) @definition.scoped)
 
 
+; Imports, the module of an imported symbol is the source of its import
+
+(import_statement
+    (import_clause
+        (named_imports
+            (import_specifier) @import))
+    source: (string) @source)
+
+(import_statement
+    (import_clause
+        (identifier) @import)
+    source: (string) @source)
+
 ; References
 
 (identifier) @reference
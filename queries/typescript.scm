@@ -6,15 +6,33 @@
     (statement_block)
 ] @scope
 
+; The parameters are in the scope of the function, but not its name
+
+(function_declaration
+    parameters: (formal_parameters) @scope.body
+    body: (statement_block) @scope.body) @scope
+
+(function
+    parameters: (formal_parameters) @scope.body
+    body: (statement_block) @scope.body) @scope
+
+(arrow_function
+    parameters: (formal_parameters) @scope.body
+    body: (_) @scope.body) @scope
+
+(method_definition
+    parameters: (formal_parameters) @scope.body
+    body: (statement_block) @scope.body) @scope
+
 ; Definitions
 
 (export_statement
     value: (_
-             name: [(type_identifier) (identifier)] @definition.exported))
+             name: [(type_identifier) (identifier)] @definition.exported) @definition.full)
 
 (export_statement
     declaration: (_
-                    name: [(type_identifier) (identifier)] @definition.exported))
+                    name: [(type_identifier) (identifier)] @definition.exported) @definition.full)
 
 (export_statement
     declaration: (_ 
@@ -25,15 +43,14 @@
     name: (identifier) @definition.scoped)
 
 (class_declaration
-    name: (type_identifier) @definition.scoped)
+    name: (type_identifier) @definition.scoped) @definition.full
 
 
 (method_definition
-    name: (property_identifier) @definition.scoped)
+    name: (property_identifier) @definition.scoped) @definition.full
 
 (variable_declarator
-    name: (_
-            (identifier) @definition.scoped))
+    name: [(object_pattern) (array_pattern)] @definition.destructured)
 
 (formal_parameters
     (_ 
@@ -49,13 +66,80 @@
 	name: (_) @definition.scoped)
 
 (type_alias_declaration
-    name: (_) @definition.scoped)
+    name: (_) @name) @definition.scoped
 
 (function_declaration
-    name: (identifier) @definition.scoped)
+    name: (identifier) @definition.scoped) @definition.full
+
+(function_signature
+    name: (identifier) @definition.declaration)
+
+; The keys of object literals are only found through the object, see `; Members`
+
+(pair
+    key: (property_identifier) @definition.member)
+
+(enum_declaration
+    name: (identifier) @definition.scoped) @definition.full
+
+(enum_body
+    [
+        (property_identifier) @definition.member
+        (enum_assignment
+            (property_identifier) @definition.member)
+    ])
+
+(import_specifier
+    name: (identifier) @original
+    alias: (identifier) @definition.alias)
+
+(export_specifier
+    name: (identifier) @original
+    alias: (identifier) @definition.reexport)
+
+; Imports, the module of an imported symbol is the source of its import
+
+(import_statement
+    (import_clause
+        (named_imports
+            (import_specifier) @import))
+    source: (string) @source)
+
+(import_statement
+    (import_clause
+        (identifier) @import)
+    source: (string) @source)
+
+; Members
+
+(variable_declarator
+    name: (identifier) @name
+    value: (object) @members)
+
+(enum_declaration
+    name: (identifier) @name
+    body: (enum_body) @members)
 
 ; References
 
+(assignment_expression
+    left: (identifier) @reference.write)
+
+(assignment_expression
+    left: (member_expression
+            property: (property_identifier) @reference.write))
+
+(augmented_assignment_expression
+    left: (identifier) @reference.write)
+
+(update_expression
+    argument: (identifier) @reference.write)
+
+(member_expression
+    object: (identifier) @receiver
+    property: (property_identifier) @reference.member)
+
+; Also the names of JSX elements, e.g. `Button` in `<Button />` and `</Button>`
 (identifier) @reference
 
 (property_identifier) @reference
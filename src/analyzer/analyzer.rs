@@ -1,7 +1,7 @@
 use std::{
     collections::HashMap,
     mem::take,
-    path::PathBuf,
+    path::Path,
     sync::{mpsc::Sender, Arc},
 };
 
@@ -9,11 +9,74 @@ use anyhow::Context;
 use smol_str::SmolStr;
 use tree_sitter::{Node, Point, Query, QueryCursor, QueryMatch, Range, Tree};
 
+use super::{containment::Contain, diagnostics::TodoComment, file_utils::is_declaration_file};
 use crate::protocol::types as protocol;
 
+/// The name of the capture that marks the name node of a definition in a pattern that
+/// captures a broader node, e.g. `(type_alias_declaration name: (_) @name) @definition.scoped`.
+/// The emitted range of the definition only spans the name node.
+pub const NAME_CAPTURE: &str = "name";
+
+/// The name of the capture that marks the receiver of a member reference, e.g. `obj` in
+/// `(member_expression object: (identifier) @receiver property: (_) @reference.member)`.
+pub const RECEIVER_CAPTURE: &str = "receiver";
+
+/// The name of the capture that marks the symbol that an alias refers to, e.g. `foo` in
+/// `(import_specifier name: (_) @original alias: (_) @definition.alias)`.
+pub const ORIGINAL_CAPTURE: &str = "original";
+
+/// The name of the captures that mark the part of a `@scope` node that is the scope, e.g.
+/// the parameters and the body of a function but not its name, with
+/// `(function_declaration parameters: (_) @scope.body body: (_) @scope.body) @scope`. The
+/// scope spans from the first to the last of these captures.
+pub const SCOPE_BODY_CAPTURE: &str = "scope.body";
+
+/// The name of the capture that marks the whole declaration of a definition, e.g. a function
+/// with its body in `(function_declaration name: (_) @definition.scoped) @definition.full`.
+/// It is emitted as the full range of the tag of the definition's range.
+pub const FULL_CAPTURE: &str = "definition.full";
+
+/// The name of the capture that marks the module an import is from, e.g. `'left-pad'` in
+/// `(import_statement (import_clause (identifier) @import) source: (string) @source)`.
+pub const SOURCE_CAPTURE: &str = "source";
+
+/// The maximum number of characters of the line of an undocumented definition in its hover,
+/// longer lines, e.g. minified code, are truncated.
+pub const MAX_HOVER_LINE_LENGTH: usize = 200;
+
+/// The captures that give the kind of what a pattern matches, a pattern's kind is its first
+/// capture other than `@name`.
+pub const PATTERN_CAPTURES: &[&str] = &[
+    "definition.scoped",
+    "definition.exported",
+    "definition.declaration",
+    "definition.alias",
+    "definition.reexport",
+    "definition.destructured",
+    "definition.member",
+    "scope",
+    "reference",
+    "reference.read",
+    "reference.write",
+    "reference.member",
+    "members",
+    "import",
+    "comment",
+];
+
 pub struct Analyzer<'sender> {
     /// The name of the file that is analysed.
     filename: String,
+    /// Whether the file only contains declarations, e.g. a `.d.ts` file.
+    is_declaration_file: bool,
+    /// Whether the exported definitions of the file are local to it, e.g. for test files,
+    /// see `--treat-tests-as-local`.
+    exports_are_local: bool,
+    /// The names of the captures of the query, by capture index.
+    capture_names: &'sender [String],
+    /// The markers of the comments that are returned as todo comments, empty if they are not
+    /// searched, see `--todo-comments`.
+    todo_markers: &'sender [String],
 
     /// The sending half of the channel for sending found definition during the analysis.
     def_sender: &'sender Sender<Arc<Definition>>,
@@ -25,52 +88,123 @@ pub struct Analyzer<'sender> {
     last_comment: Option<String>,
     /// Cache of all the definitions (name -> List of definition with that name).
     defs: HashMap<SmolStr, Vec<Arc<Definition>>>,
+    /// Cache of the definitions of members, which are only found through their receiver
+    /// (name -> List of definition with that name).
+    member_defs: HashMap<SmolStr, Vec<Arc<Definition>>>,
     /// Cache of all the references.
     refs: Vec<Reference>,
-    /// Cache of scopes.
-    /// NOTE: Using 'Vec' instead of a HashMap to store and lookup scopes might seem inefficient, but it's not.
-    /// Because scopes are stored in the same order they are defined.
+    /// Start byte -> Index of the reference in `refs`
+    ref_indices: HashMap<usize, usize>,
+    /// Start byte of a member reference -> Receiver of the reference
+    receivers: HashMap<usize, Receiver>,
+    /// Start byte of a definition -> Range of the object literal that defines its members
+    members: HashMap<usize, Range>,
+    /// Local name -> Symbol of another module that is imported with that name
+    imports: HashMap<SmolStr, Arc<Import>>,
+    /// Start byte of the name of an imported symbol in its import -> Imported symbol, e.g.
+    /// for `foo` in `import { foo as bar } from 'module'`
+    imported_names: HashMap<usize, Arc<Import>>,
+    /// Cache of scopes, in the order their query matches were found.
     scopes: Vec<Scope>,
+    /// Start byte of a `var` definition -> Range of the body of its function, whose scope is
+    /// the scope of the definition
+    hoisted: HashMap<usize, Range>,
+    /// The comments with one of the `todo_markers`, in source order.
+    todo_comments: Vec<TodoComment>,
     /// The content of the file in bytes.
     file_content_bytes: &'sender [u8],
 }
 
 impl<'sender> Analyzer<'sender> {
     /// Runs the analysis on the given file, sends the found definitions and references
-    /// via the given channels.
+    /// via the given channels. All the definitions are local if `exports_are_local` is set.
+    /// Returns the comments that have one of the given todo markers.
+    #[allow(clippy::too_many_arguments)]
     pub fn run_analysis(
         filename: String,
         tree: &Tree,
-        query: &Query,
+        query: &'sender Query,
         def_sender: &'sender Sender<Arc<Definition>>,
         ref_sender: &'sender Sender<Reference>,
-        file_content: &'sender String,
-        query_names: &Vec<String>,
-    ) {
+        file_content: &'sender [u8],
+        query_names: &[Option<String>],
+        exports_are_local: bool,
+        todo_markers: &'sender [String],
+    ) -> Vec<TodoComment> {
         let mut analyzer = Self {
             def_sender,
             reference_sender: ref_sender,
+            is_declaration_file: is_declaration_file(&filename),
+            exports_are_local,
+            capture_names: query.capture_names(),
+            todo_markers,
             filename,
-            file_content_bytes: file_content.as_bytes(),
+            file_content_bytes: file_content,
             last_comment: None,
             defs: Default::default(),
+            member_defs: Default::default(),
             refs: Default::default(),
+            ref_indices: Default::default(),
+            receivers: Default::default(),
+            members: Default::default(),
+            imports: Default::default(),
+            imported_names: Default::default(),
             scopes: Default::default(),
+            hoisted: Default::default(),
+            todo_comments: Default::default(),
         };
 
         let mut query_cursor = QueryCursor::new();
         let matches = query_cursor
             .matches(query, tree.root_node(), |_| [])
-            .map(|m| (&query_names[m.pattern_index], m));
-        for (name, qmatch) in matches {
-            match analyzer.data_from_query_match(qmatch, name) {
-                AnalysisData::Definition(it) => analyzer.handle_definition(Arc::new(it)),
-                AnalysisData::Scope(it) => analyzer.cache_scope(it),
-                AnalysisData::Comment(it) => analyzer.cache_comment(it),
-                AnalysisData::Reference(mut it) => {
-                    analyzer.try_find_def_of(&mut it);
-                    analyzer.refs.push(it)
+            .filter(|m| query_names[m.pattern_index].is_some());
+        // The match of a scope is only found once all its captures are, e.g. after the
+        // definitions of its `@scope.body`, so the other data is handled once all the scopes
+        // of the file are known.
+        // A comment after a definition on the same line is a trailing comment of the
+        // definition, e.g. `const x = 1 // the answer`, and does not document the next one.
+        let mut pending = Vec::new();
+        let mut last_definition = None;
+        for qmatch in matches {
+            for data in analyzer.data_from_query_match(qmatch) {
+                match data {
+                    AnalysisData::Scope(it) => analyzer.cache_scope(it),
+                    AnalysisData::Comment(it, range) => {
+                        analyzer.find_todo_comment(&it, range);
+                        match last_definition.map(|i| &mut pending[i]) {
+                            Some(AnalysisData::Definition(def) | AnalysisData::Member(def))
+                                if def.is_trailed_by(&range) =>
+                            {
+                                def.add_trailing_comment(it)
+                            }
+                            _ => analyzer.cache_comment(it),
+                        }
+                    }
+                    data => {
+                        if let AnalysisData::Definition(_) | AnalysisData::Member(_) = data {
+                            last_definition = Some(pending.len());
+                        }
+                        pending.push(data);
+                    }
+                }
+            }
+        }
+        for data in pending {
+            match data {
+                AnalysisData::Definition(it) => analyzer.handle_definition(it),
+                AnalysisData::Member(it) => analyzer.handle_member(it),
+                AnalysisData::Reference(it, receiver) => analyzer.cache_reference(it, receiver),
+                AnalysisData::Members(owner, range) => {
+                    analyzer.members.insert(owner, range);
+                }
+                AnalysisData::Import(local_name, name_start_byte, import) => {
+                    let import = Arc::new(import);
+                    analyzer
+                        .imported_names
+                        .insert(name_start_byte, Arc::clone(&import));
+                    analyzer.imports.insert(local_name, import);
                 }
+                AnalysisData::Scope(_) | AnalysisData::Comment(..) => unreachable!(),
             }
         }
 
@@ -78,60 +212,296 @@ impl<'sender> Analyzer<'sender> {
         analyzer.try_link_references(&mut refs);
         refs.into_iter()
             .for_each(|r| analyzer.reference_sender.send(r).unwrap());
+        analyzer.todo_comments
     }
 
-    /// Gets a query match found by treesitter and returns the `AnalysisData` extracted from it.
-    fn data_from_query_match(&mut self, qmatch: QueryMatch<'sender>, query: &str) -> AnalysisData {
+    /// Keeps the given comment with the given range if it has a todo marker.
+    fn find_todo_comment(&mut self, comment: &str, range: Range) {
+        if self.todo_markers.is_empty() {
+            return;
+        }
+        let location = Location {
+            file_path: self.filename.clone(),
+            range: utf16_range(self.file_content_bytes, range),
+        };
+        if let Some(todo) = TodoComment::find(location, comment, self.todo_markers) {
+            self.todo_comments.push(todo);
+        }
+    }
+
+    /// Gets a query match found by treesitter and returns the `AnalysisData` extracted from
+    /// each of its captures, e.g. a definition and a scope for a pattern with both a
+    /// `@definition.scoped` and a `@scope` capture.
+    ///
+    /// The scopes come first, so that the definitions of the same match can be in them.
+    /// The `@name` capture and the captures that are only used in predicates give no data.
+    fn data_from_query_match(&mut self, qmatch: QueryMatch<'sender>) -> Vec<AnalysisData> {
         use AnalysisData::*;
 
-        match query {
-            "definition.scoped" => {
-                let def = self.definition_from(qmatch, true);
-                Definition(def)
-            }
-            "definition.exported" => {
-                let def = self.definition_from(qmatch, false);
-                Definition(def)
-            }
-            "comment" => {
-                let comment = self.comment_from(qmatch);
-                Comment(comment)
-            }
-            "scope" => {
-                let scope = self.scope_from(qmatch);
-                Scope(scope)
-            }
-            "reference" => {
-                let r = self.reference_from(qmatch);
-                Reference(r)
+        let mut captures: Vec<(&str, Node<'sender>)> = qmatch
+            .captures
+            .iter()
+            .map(|c| (self.capture_names[c.index as usize].as_str(), c.node))
+            .collect();
+        captures.sort_by_key(|(name, _)| *name != "scope");
+        let captured = |capture_name: &str| {
+            captures
+                .iter()
+                .find(|(name, _)| *name == capture_name)
+                .map(|(_, node)| *node)
+        };
+        let name_capture = captured(NAME_CAPTURE);
+        let receiver = captured(RECEIVER_CAPTURE).map(|node| Receiver {
+            name: SmolStr::new(self.node_text_of(&node)),
+            range: node.range(),
+        });
+        let original =
+            captured(ORIGINAL_CAPTURE).map(|node| SmolStr::new(self.node_text_of(&node)));
+        let full_range = captured(FULL_CAPTURE).map(|node| FullRange {
+            range: utf16_range(self.file_content_bytes, node.range()),
+            kind: symbol_kind(node.kind()),
+        });
+        let source = captured(SOURCE_CAPTURE).map(|node| {
+            let text = self.node_text_of(&node);
+            SmolStr::new(text.trim_matches(|c| c == '\'' || c == '"' || c == '`'))
+        });
+        let scope_body: Vec<Range> = captures
+            .iter()
+            .filter(|(name, _)| *name == SCOPE_BODY_CAPTURE)
+            .map(|(_, node)| node.range())
+            .collect();
+
+        let mut data = Vec::with_capacity(captures.len());
+        for (name, node) in captures {
+            let name_node = name_capture.unwrap_or(node);
+            data.push(match name {
+                "definition.scoped" => Definition(self.definition_from(node, name_node, true)),
+                "definition.exported" => Definition(self.definition_from(node, name_node, false)),
+                "definition.declaration" => {
+                    let mut def = self.definition_from(node, name_node, false);
+                    def.is_declaration = true;
+                    Definition(def)
+                }
+                "definition.alias" => {
+                    let mut def = self.definition_from(node, name_node, true);
+                    def.aliased_name = original.clone();
+                    Definition(def)
+                }
+                "definition.reexport" => {
+                    let mut def = self.definition_from(node, name_node, false);
+                    def.aliased_name = original.clone();
+                    Definition(def)
+                }
+                "definition.destructured" => {
+                    let mut bindings = Vec::new();
+                    pattern_bindings(node, &mut bindings);
+                    for binding in bindings {
+                        data.push(Definition(self.definition_from(node, binding, true)));
+                    }
+                    continue;
+                }
+                "definition.member" => Member(self.definition_from(node, name_node, true)),
+                "comment" => Comment(self.comment_from(node), node.range()),
+                "scope" => Scope(self.scope_from(node, &scope_body)),
+                "reference" | "reference.read" => Reference(
+                    self.reference_from(node, protocol::ReferenceAccess::Read),
+                    None,
+                ),
+                "reference.write" => Reference(
+                    self.reference_from(node, protocol::ReferenceAccess::Write),
+                    None,
+                ),
+                "reference.member" => Reference(
+                    self.reference_from(node, protocol::ReferenceAccess::Read),
+                    receiver.clone(),
+                ),
+                "members" => match name_capture {
+                    Some(owner) => Members(owner.start_byte(), node.range()),
+                    None => continue,
+                },
+                "import" => match &source {
+                    Some(module) => self.import_from(node, module),
+                    None => continue,
+                },
+                _ => continue,
+            });
+        }
+        if let Some(full_range) = full_range {
+            for d in &mut data {
+                if let Definition(def) | Member(def) = d {
+                    def.full_range = Some(full_range);
+                }
             }
-            _ => panic!("Unknown query {}", query),
         }
+        data
     }
 
     /// Caches the definition and sends it in the channel.
-    fn handle_definition(&mut self, def: Arc<Definition>) {
+    fn handle_definition(&mut self, mut def: Definition) {
+        if self.exports_are_local || def.kind != DefinitionScope::Exported {
+            def.kind = DefinitionScope::Local(self.scope_of(&def));
+        }
+        let def = Arc::new(def);
         self.cache_definition(Arc::clone(&def));
         self.def_sender.send(def).unwrap();
     }
 
-    /// Tries to find a definition for each of the given references. If a definition is not found,
-    /// it means it is located in a different file or in a dependency library.
+    /// Caches the definition of a member and sends it in the channel. It is local to its
+    /// scope, but a reference only finds it through its receiver, e.g. `key` in
+    /// `obj.key` after `const obj = { key: 1 }`, and not as `key`.
+    fn handle_member(&mut self, mut def: Definition) {
+        def.kind = DefinitionScope::Local(self.scope_of(&def));
+        let def = Arc::new(def);
+        self.member_defs
+            .entry(SmolStr::clone(&def.node_name))
+            .or_default()
+            .push(Arc::clone(&def));
+        self.def_sender.send(def).unwrap();
+    }
+
+    /// Tries to find a definition for each of the given references, once all the definitions
+    /// of the file are known. If a definition is not found, it means it is located in a
+    /// different file or in a dependency library.
+    ///
+    /// Definitions are visible in their whole scope, even before they are declared, e.g. a
+    /// hoisted function declaration. So a reference that was linked to a definition of an
+    /// outer scope is linked again if a definition of an inner scope was declared after it.
+    ///
+    /// A reference without a definition that has the name of an import refers to the
+    /// imported symbol, and so does the name of the symbol in the import.
     fn try_link_references(&mut self, refs: &mut Vec<Reference>) {
-        for mut r in refs {
-            if !r.has_def() {
-                self.try_find_def_of(&mut r);
+        for r in refs {
+            match (&r.def, self.find_def_of(r)) {
+                (None, None) => {
+                    r.import = self
+                        .imported_names
+                        .get(&r.location.range.start_byte)
+                        .or_else(|| self.imports.get(&r.node_name))
+                        .cloned()
+                }
+                (None, def) => r.def = def,
+                (Some(current), Some(found)) if found.kind.span() < current.kind.span() => {
+                    r.def = Some(found)
+                }
+                _ => {}
             }
         }
     }
 }
 
+/// Returns the kind of symbol declared by a node of the given kind, a variable if the kind
+/// is not known.
+pub(crate) fn symbol_kind(node_kind: &str) -> protocol::SymbolKind {
+    use protocol::SymbolKind;
+    match node_kind {
+        "function_declaration" | "generator_function_declaration" | "function" => {
+            SymbolKind::FUNCTION
+        }
+        "class_declaration" | "abstract_class_declaration" | "class" => SymbolKind::CLASS,
+        "method_definition" | "method_signature" | "method_declaration" => SymbolKind::METHOD,
+        "interface_declaration" => SymbolKind::INTERFACE,
+        "enum_declaration" => SymbolKind::ENUM,
+        // The LSP has no kind for type aliases, editors show them like classes
+        "type_alias_declaration" => SymbolKind::CLASS,
+        "type_parameter" => SymbolKind::TYPE_PARAMETER,
+        "module" | "internal_module" => SymbolKind::MODULE,
+        "public_field_definition" | "property_signature" => SymbolKind::PROPERTY,
+        _ => SymbolKind::VARIABLE,
+    }
+}
+
+/// Adds the nodes of the names bound by the given destructuring pattern to the given list,
+/// in source order, e.g. `a`, `b` and `c` in `{ a, b: [b], c = 1 }`. Property keys and
+/// default values are not bindings.
+fn pattern_bindings<'tree>(node: Node<'tree>, bindings: &mut Vec<Node<'tree>>) {
+    match node.kind() {
+        "identifier" | "shorthand_property_identifier_pattern" => bindings.push(node),
+        "pair_pattern" => {
+            if let Some(value) = node.child_by_field_name("value") {
+                pattern_bindings(value, bindings);
+            }
+        }
+        "assignment_pattern" | "object_assignment_pattern" => {
+            if let Some(left) = node.child_by_field_name("left") {
+                pattern_bindings(left, bindings);
+            }
+        }
+        "object_pattern" | "array_pattern" | "rest_pattern" => {
+            for i in 0..node.named_child_count() {
+                pattern_bindings(node.named_child(i).unwrap(), bindings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Returns the body of the function whose scope is the scope of the given name of a `var`
+/// definition, or the whole program at the top level, e.g. for `x` in
+/// `function f() { if (a) { var x = 1; } }`, the body of `f`. Returns `None` if the name is
+/// not declared by `var`, e.g. by `let` or `const`, which are scoped to their block.
+fn hoisted_to(name: Node) -> Option<Node> {
+    const FUNCTIONS: &[&str] = &[
+        "function_declaration",
+        "function",
+        "generator_function_declaration",
+        "generator_function",
+        "arrow_function",
+        "method_definition",
+    ];
+
+    // The name is in the name of its declarator, e.g. in a destructuring pattern
+    let mut child = name;
+    let declarator = loop {
+        let parent = child.parent()?;
+        if parent.kind() == "variable_declarator" {
+            if parent.child_by_field_name("name") != Some(child) {
+                return None;
+            }
+            break parent;
+        }
+        if FUNCTIONS.contains(&parent.kind()) {
+            return None;
+        }
+        child = parent;
+    };
+    let declaration = declarator.parent()?;
+    if declaration.kind() != "variable_declaration" {
+        return None;
+    }
+
+    let mut ancestor = declaration;
+    while let Some(parent) = ancestor.parent() {
+        if FUNCTIONS.contains(&parent.kind()) {
+            return parent.child_by_field_name("body");
+        }
+        ancestor = parent;
+    }
+    Some(ancestor)
+}
+
 /// Represents data found (extracted) from a treesitter query match.
 enum AnalysisData {
     Definition(Definition),
+    /// A definition that is only found through its receiver, see `handle_member`.
+    Member(Definition),
     Scope(Scope),
-    Comment(String),
-    Reference(Reference),
+    /// The text of a comment and its range.
+    Comment(String, Range),
+    /// A reference, with its receiver if it is a member reference.
+    Reference(Reference, Option<Receiver>),
+    /// The start byte of a definition and the range of the object literal that defines
+    /// its members.
+    Members(usize, Range),
+    /// The local name of an imported symbol, the start byte of its name in the import and
+    /// the symbol.
+    Import(SmolStr, usize, Import),
+}
+
+/// The receiver of a member reference, e.g. `obj` in `obj.method()`.
+#[derive(Clone)]
+struct Receiver {
+    name: SmolStr,
+    range: Range,
 }
 
 /// Methods for caching and retrieving analysis data.
@@ -143,19 +513,35 @@ impl<'a> Analyzer<'a> {
             .push(def);
     }
 
+    /// Caches the given reference once its definition is looked up. A node that is captured
+    /// as a reference by several patterns is only one reference, which writes the symbol if
+    /// any of the captures does, e.g. the target of an assignment is also an identifier,
+    /// and which is a member reference if any of the captures has a receiver.
+    fn cache_reference(&mut self, mut r: Reference, receiver: Option<Receiver>) {
+        let start_byte = r.location.range.start_byte;
+        if let Some(receiver) = receiver {
+            self.receivers.insert(start_byte, receiver);
+        }
+        if let Some(&i) = self.ref_indices.get(&start_byte) {
+            if r.access == protocol::ReferenceAccess::Write {
+                self.refs[i].access = r.access;
+            }
+            self.refs[i].def = self.find_def_of(&self.refs[i]);
+            return;
+        }
+
+        self.try_find_def_of(&mut r);
+        self.ref_indices.insert(start_byte, self.refs.len());
+        self.refs.push(r);
+    }
+
     fn cache_scope(&mut self, scope: Scope) {
         self.scopes.push(scope);
     }
 
     /// Finds the innermost scope that contains the given range.
     fn find_enclosing_scope(&self, range: &Range) -> Option<Scope> {
-        self.scopes.iter().rev().find_map(|s| {
-            if s.range.contains(range) {
-                Some(*s)
-            } else {
-                None
-            }
-        })
+        Scope::innermost_containing(&self.scopes, range)
     }
 
     /// Sets the value of the last comment to the given comment.
@@ -165,126 +551,214 @@ impl<'a> Analyzer<'a> {
 
     /// Looks up all the definition that are visible from the scope of the give reference. If it finds
     /// a definition that matches that reference's name, it sets its definition value.
+    ///
+    /// When several definitions are visible, the one with the innermost scope is chosen, and
+    /// among definitions of the same scope, the one that was declared last.
+    ///
+    /// A scope with the same span as the reference is the reference itself, so it must
+    /// strictly contain the reference.
+    ///
+    /// The definition of a member reference whose receiver is defined as an object literal
+    /// is the member with the same name in the object literal, if any, e.g. `method` in
+    /// `obj.method()` after `const obj = { method() {} }`. The definitions of members are
+    /// not found otherwise, except from their own name.
     fn try_find_def_of(&self, r: &mut Reference) {
-        r.def = self.defs.get(&r.node_name).and_then(|defs| {
+        r.def = self.find_def_of(r);
+    }
+
+    /// Returns the definition of the given reference, see `try_find_def_of`.
+    fn find_def_of(&self, r: &Reference) -> Option<Arc<Definition>> {
+        let mut members = self.member_defs.get(&r.node_name).into_iter().flatten();
+        match self.members_of_receiver(r) {
+            Some(range) => self
+                .defs
+                .get(&r.node_name)
+                .into_iter()
+                .flatten()
+                .chain(members)
+                .find(|d| range.strictly_contains(&d.location.range))
+                .map(Arc::clone),
+            None => members
+                .find(|d| d.location.range == r.location.range)
+                .map(Arc::clone)
+                .or_else(|| self.find_visible_def(&r.node_name, &r.location.range)),
+        }
+    }
+
+    /// Returns the range of the object literal that defines the members of the receiver of
+    /// the given reference, if it is a member reference.
+    fn members_of_receiver(&self, r: &Reference) -> Option<&Range> {
+        let receiver = self.receivers.get(&r.location.range.start_byte)?;
+        let receiver_def = self.find_visible_def(&receiver.name, &receiver.range)?;
+        self.members.get(&receiver_def.location.range.start_byte)
+    }
+
+    /// Returns the definition with the given name that is visible from the given range.
+    fn find_visible_def(&self, name: &SmolStr, range: &Range) -> Option<Arc<Definition>> {
+        self.defs.get(name).and_then(|defs| {
             defs.iter()
                 .rev()
-                .find(|&d| {
+                .filter(|&d| {
                     let is_in_scope = match &d.kind {
                         DefinitionScope::Exported => true,
-                        DefinitionScope::Local(scope) => scope.contains(&r.location.range),
+                        DefinitionScope::Local(scope) => scope.strictly_contains(range),
                     };
 
                     is_in_scope
                 })
+                .min_by_key(|&d| d.kind.span())
                 .map(Arc::clone)
         })
     }
 }
 
 impl<'a> Analyzer<'a> {
-    /// Returns a `Scope` from the given query match. It is the reponsibility
-    /// of the caller to ensure that the query match is the result
-    /// of a 'scope' query.
-    fn scope_from(&mut self, qmatch: QueryMatch) -> Scope {
-        Scope {
-            range: qmatch.captures[0].node.range(),
-        }
+    /// Returns a `Scope` from the given node captured by a `@scope` capture, which spans the
+    /// ranges of its `@scope.body` captures if any.
+    fn scope_from(&mut self, node: Node, body: &[Range]) -> Scope {
+        let range = match (
+            body.iter().min_by_key(|r| r.start_byte),
+            body.iter().max_by_key(|r| r.end_byte),
+        ) {
+            (Some(first), Some(last)) => Range {
+                start_byte: first.start_byte,
+                end_byte: last.end_byte,
+                start_point: first.start_point,
+                end_point: last.end_point,
+            },
+            _ => node.range(),
+        };
+        Scope { range }
     }
 
-    /// Returns a `Comment` from the given query match. It is the reponsibility
-    /// of the caller to ensure that the query match is the result
-    /// of a 'comment' query.
-    fn comment_from(&mut self, qmatch: QueryMatch) -> String {
-        self.node_text_of(&qmatch.captures[0].node)
+    /// Returns a `Comment` from the given node captured by a `@comment` capture.
+    fn comment_from(&mut self, node: Node) -> String {
+        self.node_text_of(&node)
     }
 
-    /// Returns a `Reference` from the given query match. It is the reponsibility
-    /// of the caller to ensure that the query match is the result
-    /// of a 'reference' query.
-    fn reference_from(&mut self, qmatch: QueryMatch) -> Reference {
-        let capture = qmatch.captures[0];
-        let name = SmolStr::new(self.node_text_of(&capture.node));
-        let range = capture.node.range();
-
-        let def = self
-            .defs
-            .entry(SmolStr::clone(&name))
-            .or_default()
-            .iter()
-            .find(|&d| {
-                let is_in_scope = match &d.kind {
-                    DefinitionScope::Exported => true,
-                    DefinitionScope::Local(scope) => scope.contains(&range),
-                };
-
-                d.location.range != range && is_in_scope
-            })
-            .map(Arc::clone);
-
+    /// Returns a `Reference` from the given node captured by a `@reference.*` capture, with
+    /// the given access.
+    ///
+    /// The definition of the returned reference is not set, see `try_find_def_of`.
+    fn reference_from(&mut self, node: Node, access: protocol::ReferenceAccess) -> Reference {
         Reference {
-            location: self.location_of(&capture.node),
-            node_name: name,
-            def,
+            location: self.location_of(&node),
+            node_name: SmolStr::new(self.node_text_of(&node)),
+            def: None,
+            access,
+            import: None,
         }
     }
 
-    /// Returns a `Definition` from the given query match. It is the reponsibility
-    /// of the caller to ensure that the query match is the result
-    /// of a 'definition' query.
-    fn definition_from(&mut self, qmatch: QueryMatch, is_local: bool) -> Definition {
-        let capture = qmatch.captures[0];
+    /// Returns an `Import` from the given node captured by an `@import` capture, either an
+    /// import specifier, e.g. `foo as bar` in `import { foo as bar } from 'module'`, or the
+    /// name of a default import.
+    fn import_from(&self, node: Node, module: &SmolStr) -> AnalysisData {
+        let (name, local_name) = match node.child_by_field_name("name") {
+            Some(name_node) => {
+                let name = SmolStr::new(self.node_text_of(&name_node));
+                let local_name = node.child_by_field_name("alias").map_or_else(
+                    || name.clone(),
+                    |alias| SmolStr::new(self.node_text_of(&alias)),
+                );
+                (name, local_name)
+            }
+            None => (
+                SmolStr::new("default"),
+                SmolStr::new(self.node_text_of(&node)),
+            ),
+        };
+        AnalysisData::Import(
+            local_name,
+            node.start_byte(),
+            Import {
+                module: SmolStr::clone(module),
+                name,
+            },
+        )
+    }
+
+    /// Returns a `Definition` from the given node captured by a `@definition.*` capture.
+    ///
+    /// The location and name of the definition are the ones of the given name node, which
+    /// is the node of the `@name` capture of the match if it has one and the captured node
+    /// otherwise.
+    fn definition_from(&mut self, node: Node, name_node: Node, is_local: bool) -> Definition {
+        // The scope of a local definition is set once all the scopes are known, see
+        // `handle_definition`
         let kind = if is_local {
-            DefinitionScope::Local(
-                self.find_enclosing_scope(&capture.node.range())
-                    .context(format!(
-                        "Expected node at (file: {}, line: {}, column: {}) to have a scope\n
-                        This error probably means that the query file is missing scope queries",
-                        self.filename,
-                        capture.node.range().start_point.row + 1,
-                        capture.node.range().start_point.column + 1
-                    ))
-                    .unwrap_or(Scope {
-                        range: Range {
-                            start_byte: 0,
-                            end_byte: 0,
-                            start_point: Point { row: 0, column: 0 },
-                            end_point: Point { row: 0, column: 0 },
-                        },
-                    })
-                    .range,
-            )
+            DefinitionScope::Local(name_node.range())
         } else {
             DefinitionScope::Exported
         };
+        if let Some(body) = hoisted_to(name_node) {
+            self.hoisted.insert(name_node.start_byte(), body.range());
+        }
 
         Definition {
-            location: self.location_of(&capture.node),
-            node_name: SmolStr::new(self.node_text_of(&capture.node)),
-            comment: take(&mut self.last_comment).unwrap_or(self.line_of(&capture.node)),
+            location: self.location_of(&name_node),
+            node_name: SmolStr::new(self.node_text_of(&name_node)),
+            comment: take(&mut self.last_comment).unwrap_or(self.line_of(&node)),
             kind,
+            is_declaration: self.is_declaration_file,
+            aliased_name: None,
+            full_range: None,
         }
     }
 
+    /// Returns the range of the innermost scope of the given local definition, which is the
+    /// scope of the body of its function for a `var` definition.
+    fn scope_of(&self, def: &Definition) -> Range {
+        let range = &def.location.range;
+        if let Some(body) = self.hoisted.get(&range.start_byte) {
+            let scope = self
+                .scopes
+                .iter()
+                .filter(|s| s.range.contains(body))
+                .min_by_key(|s| s.range.end_byte - s.range.start_byte);
+            if let Some(scope) = scope {
+                return scope.range;
+            }
+        }
+        self.find_enclosing_scope(range)
+            .context(format!(
+                "Expected node at (file: {}, line: {}, column: {}) to have a scope\n
+                        This error probably means that the query file is missing scope queries",
+                self.filename,
+                range.start_point.row + 1,
+                range.start_point.column + 1
+            ))
+            .unwrap_or(Scope {
+                range: Range {
+                    start_byte: 0,
+                    end_byte: 0,
+                    start_point: Point { row: 0, column: 0 },
+                    end_point: Point { row: 0, column: 0 },
+                },
+            })
+            .range
+    }
+
     /// Returns the `Location` of the given node.
     fn location_of(&self, node: &Node) -> Location {
         Location {
             file_path: self.filename.clone(),
-            range: node.range(),
+            range: utf16_range(self.file_content_bytes, node.range()),
         }
     }
 
-    /// Returns the name content of the given node as a String
+    /// Returns the name content of the given node as a String, invalid UTF-8 sequences are
+    /// replaced with `U+FFFD`.
     fn node_text_of(&self, node: &Node) -> String {
         let start_byte = node.start_byte();
         let end_byte = node.end_byte();
-        std::str::from_utf8(&self.file_content_bytes[start_byte..end_byte])
-            .unwrap()
-            .to_string()
+        String::from_utf8_lossy(&self.file_content_bytes[start_byte..end_byte]).into_owned()
     }
 
     /// Returns the text of the line where the first start of the node is located. This is
     /// used for hover contents when a variable is not documented.
+    ///
+    /// The text is truncated to `MAX_HOVER_LINE_LENGTH` characters, followed by `…`.
     fn line_of(&self, node: &Node) -> String {
         let start_byte = node.start_byte();
         let end_byte = self.file_content_bytes[start_byte..]
@@ -293,13 +767,15 @@ impl<'a> Analyzer<'a> {
             .find(|(_i, c)| c == &&b'\n')
             .map(|(i, _c)| i + start_byte)
             .unwrap_or(start_byte);
-        format!(
+        let line = format!(
             "{} {}",
             node.kind().to_string(),
-            std::str::from_utf8(&self.file_content_bytes[start_byte..end_byte])
-                .unwrap()
-                .to_string()
-        )
+            String::from_utf8_lossy(&self.file_content_bytes[start_byte..end_byte])
+        );
+        match line.char_indices().nth(MAX_HOVER_LINE_LENGTH) {
+            Some((end, _)) => format!("{}…", &line[..end]),
+            None => line,
+        }
     }
 }
 
@@ -308,12 +784,54 @@ pub struct Scope {
     range: Range,
 }
 
+impl Scope {
+    #[cfg(test)]
+    pub fn new(range: Range) -> Self {
+        Self { range }
+    }
+
+    #[cfg(test)]
+    pub fn range(&self) -> Range {
+        self.range
+    }
+
+    /// Returns the smallest of the given scopes that strictly contains the given range,
+    /// a scope with the same span as the range is the node itself and does not enclose it.
+    ///
+    /// Treesitter does not guarantee that query matches are found in source order, so
+    /// this does not depend on the order of the given scopes.
+    pub fn innermost_containing(scopes: &[Scope], range: &Range) -> Option<Scope> {
+        scopes
+            .iter()
+            .filter(|s| s.range.strictly_contains(range))
+            .min_by_key(|s| s.range.end_byte - s.range.start_byte)
+            .copied()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Definition {
     pub location: Location,
     pub node_name: SmolStr,
     pub comment: String,
     pub kind: DefinitionScope,
+    /// Whether this is only a declaration, e.g. a function signature in a `.d.ts` file.
+    /// Definitions that are not declarations are their own declaration.
+    pub is_declaration: bool,
+    /// The name of the symbol that this definition is an alias of, e.g. `foo` for `bar` in
+    /// `import { foo as bar }`. Exported aliases are re-exports, e.g. `bar` in
+    /// `export { foo as bar } from './foo'`.
+    pub aliased_name: Option<SmolStr>,
+    /// The whole declaration of the definition, if its pattern captures it with
+    /// `@definition.full`.
+    pub full_range: Option<FullRange>,
+}
+
+/// The range of the whole declaration of a definition and the kind of symbol it declares.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FullRange {
+    pub range: Range,
+    pub kind: protocol::SymbolKind,
 }
 
 #[derive(Debug, Clone)]
@@ -321,6 +839,21 @@ pub struct Reference {
     pub location: Location,
     pub node_name: SmolStr,
     pub def: Option<Arc<Definition>>,
+    pub access: protocol::ReferenceAccess,
+    /// The imported symbol that the reference refers to, if it has no definition in its
+    /// file and has the name of an import, e.g. `pad` in `pad()` after
+    /// `import { pad } from 'left-pad'`.
+    pub import: Option<Arc<Import>>,
+}
+
+/// A symbol of another module that is imported, e.g. `pad` of `left-pad` in
+/// `import { pad } from 'left-pad'`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Import {
+    /// The module the symbol is imported from, as written in the import.
+    pub module: SmolStr,
+    /// The name of the symbol in its module, `default` for a default import.
+    pub name: SmolStr,
 }
 
 impl Definition {
@@ -330,6 +863,34 @@ impl Definition {
             end: protocol::Position::from_point(self.location.range.end_point),
         }
     }
+
+    /// Returns the tag of the range of this definition, if its full range is known.
+    pub fn tag(&self) -> Option<protocol::RangeTag> {
+        self.full_range.map(|full_range| protocol::RangeTag {
+            text: self.node_name.to_string(),
+            kind: full_range.kind,
+            full_range: protocol::Range {
+                start: protocol::Position::from_point(full_range.range.start_point),
+                end: protocol::Position::from_point(full_range.range.end_point),
+            },
+        })
+    }
+
+    /// Returns true if the comment with the given range starts after this definition, on
+    /// the line where it ends.
+    fn is_trailed_by(&self, comment: &Range) -> bool {
+        let range = &self.location.range;
+        comment.start_byte >= range.end_byte && comment.start_point.row == range.end_point.row
+    }
+
+    /// Adds the given trailing comment to the hover of this definition, unless the hover
+    /// already has it, e.g. the line of an undocumented definition.
+    fn add_trailing_comment(&mut self, comment: String) {
+        if !self.comment.contains(comment.as_str()) {
+            self.comment.push('\n');
+            self.comment.push_str(&comment);
+        }
+    }
 }
 
 impl Reference {
@@ -341,7 +902,8 @@ impl Reference {
     }
 }
 
-trait FromPoint {
+/// Conversion from a tree-sitter point whose column is in UTF-16 code units, see `utf16_range`.
+pub(crate) trait FromPoint {
     fn from_point(p: Point) -> Self;
 }
 
@@ -354,42 +916,67 @@ impl FromPoint for protocol::Position {
     }
 }
 
+/// Returns the given range of the given content with the columns of its points in UTF-16
+/// code units, the position encoding of the dumps, instead of bytes. The byte offsets are
+/// kept as they are.
+pub(crate) fn utf16_range(content: &[u8], range: Range) -> Range {
+    Range {
+        start_point: utf16_point(content, range.start_byte, range.start_point),
+        end_point: utf16_point(content, range.end_byte, range.end_point),
+        ..range
+    }
+}
+
+/// Returns the given point, at the given byte offset of the given content, with its column
+/// in UTF-16 code units.
+fn utf16_point(content: &[u8], byte: usize, point: Point) -> Point {
+    let line_before = &content[byte - point.column..byte];
+    Point::new(point.row, utf16_len(line_before))
+}
+
+/// Returns the number of UTF-16 code units of the given UTF-8 bytes. An invalid sequence
+/// counts as one code unit, like the `U+FFFD` that replaces it.
+pub(crate) fn utf16_len(bytes: &[u8]) -> usize {
+    if bytes.is_ascii() {
+        return bytes.len();
+    }
+    String::from_utf8_lossy(bytes).encode_utf16().count()
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum DefinitionScope {
     Exported,
     Local(Range),
 }
 
-impl Reference {
-    fn has_def(&self) -> bool {
-        self.def.is_some()
+impl DefinitionScope {
+    /// Returns the size of the scope in bytes. Exported definitions are visible
+    /// everywhere, so their scope is the largest possible.
+    pub fn span(&self) -> usize {
+        match self {
+            DefinitionScope::Exported => usize::MAX,
+            DefinitionScope::Local(range) => range.end_byte - range.start_byte,
+        }
     }
 }
 
 #[derive(Debug, Clone, Eq, PartialEq, Hash)]
 pub struct Location {
+    /// The columns of the points of the range are in UTF-16 code units, not in bytes.
     pub range: Range,
     pub file_path: String,
 }
 
 impl Location {
-    /// Returns the name of the file (the final component of the file path)
-    pub fn file_name(&self) -> String {
-        PathBuf::from(&self.file_path)
-            .file_name()
-            .unwrap()
-            .to_str()
-            .unwrap()
-            .to_string()
-    }
-}
-
-pub trait Contain {
-    fn contains(&self, o: &Self) -> bool;
-}
-
-impl Contain for Range {
-    fn contains(&self, o: &Self) -> bool {
-        self.end_byte >= o.end_byte && self.start_byte <= o.start_byte
+    /// Returns the path of the file relative to the given root directory, with `/` as
+    /// the separator. The path is returned as is if it is not inside the root.
+    pub fn relative_path(&self, root: &Path) -> String {
+        let path = Path::new(&self.file_path);
+        path.strip_prefix(root)
+            .unwrap_or(path)
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/")
     }
 }
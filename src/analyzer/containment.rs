@@ -0,0 +1,41 @@
+use tree_sitter::Range;
+
+/// Containment relations between spans of a file, e.g. to find the scopes that contain
+/// a definition.
+///
+/// Ranges are compared by their byte offsets. The start byte is inclusive and the end
+/// byte is exclusive, like in tree-sitter.
+pub trait Contain {
+    /// Returns true if `o` is within this span. A span contains itself, use
+    /// `strictly_contains` when a node must not be considered to enclose itself.
+    fn contains(&self, o: &Self) -> bool;
+
+    /// Returns true if `o` is within this span and the spans are not equal, e.g. the scope
+    /// that encloses a node cannot be the node itself.
+    fn strictly_contains(&self, o: &Self) -> bool;
+
+    /// Returns true if the given byte offset is within this span.
+    fn contains_point(&self, byte: usize) -> bool;
+
+    /// Returns true if this span and `other` share at least one byte. Adjacent spans,
+    /// where one ends where the other starts, do not overlap.
+    fn overlaps(&self, other: &Self) -> bool;
+}
+
+impl Contain for Range {
+    fn contains(&self, o: &Self) -> bool {
+        self.end_byte >= o.end_byte && self.start_byte <= o.start_byte
+    }
+
+    fn strictly_contains(&self, o: &Self) -> bool {
+        self.contains(o) && (self.start_byte, self.end_byte) != (o.start_byte, o.end_byte)
+    }
+
+    fn contains_point(&self, byte: usize) -> bool {
+        self.start_byte <= byte && byte < self.end_byte
+    }
+
+    fn overlaps(&self, other: &Self) -> bool {
+        self.start_byte < other.end_byte && other.start_byte < self.end_byte
+    }
+}
@@ -0,0 +1,120 @@
+use tree_sitter::{Node, Tree};
+
+use super::analyzer::{utf16_range, FromPoint, Location};
+use crate::protocol::types as protocol;
+
+/// The longest text of an unexpected node that is quoted in the message of its error.
+const MAX_QUOTED_LEN: usize = 30;
+
+/// A syntax error found by the parser in a file, i.e. an `ERROR` or a `MISSING` node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SyntaxError {
+    pub location: Location,
+    pub message: String,
+}
+
+impl SyntaxError {
+    pub fn diagnostic(&self) -> protocol::Diagnostic {
+        protocol::Diagnostic {
+            range: protocol::Range {
+                start: protocol::Position::from_point(self.location.range.start_point),
+                end: protocol::Position::from_point(self.location.range.end_point),
+            },
+            severity: Some(protocol::DiagnosticSeverity::Error),
+            source: Some("tree-sitter".to_string()),
+            message: self.message.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A comment with a marker of outstanding work, e.g. `// TODO: fix`, see `--todo-comments`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TodoComment {
+    pub location: Location,
+    /// The comment from its marker to the end of the line, e.g. `TODO: fix`.
+    pub message: String,
+}
+
+impl TodoComment {
+    /// Returns the todo comment of the given comment text at the given location, if it has
+    /// one of the given markers as a whole word, e.g. `TODO` but not `TODOS`.
+    pub fn find(location: Location, text: &str, markers: &[String]) -> Option<Self> {
+        let is_word_char =
+            |c: Option<char>| matches!(c, Some(c) if c.is_alphanumeric() || c == '_');
+        let (start, _) = markers
+            .iter()
+            .filter(|marker| !marker.is_empty())
+            .flat_map(|marker| text.match_indices(marker.as_str()))
+            .filter(|(start, marker)| {
+                let before = text[..*start].chars().next_back();
+                let after = text[start + marker.len()..].chars().next();
+                !is_word_char(before) && !is_word_char(after)
+            })
+            .min_by_key(|(start, _)| *start)?;
+        let line = text[start..].lines().next().unwrap_or_default();
+        let message = line.trim_end_matches("*/").trim().to_string();
+        Some(TodoComment { location, message })
+    }
+
+    pub fn diagnostic(&self) -> protocol::Diagnostic {
+        protocol::Diagnostic {
+            range: protocol::Range {
+                start: protocol::Position::from_point(self.location.range.start_point),
+                end: protocol::Position::from_point(self.location.range.end_point),
+            },
+            severity: Some(protocol::DiagnosticSeverity::Information),
+            source: Some("todo".to_string()),
+            message: self.message.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Returns the syntax errors in the given tree of the given file, in source order.
+///
+/// Only the subtrees that contain errors are visited, and the errors inside an `ERROR`
+/// node are not reported apart since the parser could not make sense of that node anyway.
+pub fn syntax_errors(filename: &str, tree: &Tree, source: &[u8]) -> Vec<SyntaxError> {
+    let mut errors = Vec::new();
+    if tree.root_node().has_error() {
+        collect_errors(filename, tree.root_node(), source, &mut errors);
+    }
+    errors
+}
+
+fn collect_errors(filename: &str, node: Node, source: &[u8], errors: &mut Vec<SyntaxError>) {
+    let message = if node.is_error() {
+        Some(unexpected_message(node, source))
+    } else if node.is_missing() {
+        Some(format!("Missing `{}`", node.kind()))
+    } else {
+        None
+    };
+    if let Some(message) = message {
+        errors.push(SyntaxError {
+            location: Location {
+                range: utf16_range(source, node.range()),
+                file_path: filename.to_string(),
+            },
+            message,
+        });
+        return;
+    }
+
+    let mut cursor = node.walk();
+    for child in node.children(&mut cursor) {
+        if child.has_error() || child.is_missing() {
+            collect_errors(filename, child, source, errors);
+        }
+    }
+}
+
+fn unexpected_message(node: Node, source: &[u8]) -> String {
+    let text = node.utf8_text(source).unwrap_or_default().trim();
+    if text.is_empty() || text.len() > MAX_QUOTED_LEN || text.contains('\n') {
+        "Syntax error".to_string()
+    } else {
+        format!("Unexpected `{}`", text)
+    }
+}
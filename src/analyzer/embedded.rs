@@ -0,0 +1,85 @@
+//! Files that embed code in another language, e.g. the `<script>` blocks of Vue single-file
+//! components. Only the embedded code is parsed, with the positions of the whole file, so
+//! that nothing needs to be translated afterwards.
+use std::path::Path;
+
+use tree_sitter::{Point, Range};
+
+/// Returns true if the file at the given path is a single-file component whose `<script>`
+/// blocks are the only code to parse, e.g. a `.vue` file.
+pub fn is_single_file_component(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("vue"))
+}
+
+/// Returns the ranges of the content of the `<script>` blocks of the given component, in
+/// order. The content of a block that is not closed extends to the end of the file. The
+/// blocks in HTML comments are skipped.
+pub fn script_ranges(content: &[u8]) -> Vec<Range> {
+    let mut ranges = vec![];
+    let mut offset = 0;
+    while let Some(tag_start) = find(content, offset, b"<") {
+        if content[tag_start..].starts_with(b"<!--") {
+            offset = find(content, tag_start, b"-->").map_or(content.len(), |end| end + 3);
+            continue;
+        }
+        let after_name = tag_start + b"<script".len();
+        // e.g. `<scripts>` is another tag
+        if !content[tag_start..].starts_with(b"<script")
+            || !matches!(content.get(after_name), Some(c) if c.is_ascii_whitespace() || *c == b'>')
+        {
+            offset = tag_start + 1;
+            continue;
+        }
+        let start = match tag_end(content, after_name) {
+            Some(tag_end) => tag_end + 1,
+            None => break,
+        };
+        let end = find(content, start, b"</script").unwrap_or(content.len());
+        ranges.push(Range {
+            start_byte: start,
+            end_byte: end,
+            start_point: point_at(content, start),
+            end_point: point_at(content, end),
+        });
+        offset = end;
+    }
+    ranges
+}
+
+/// Returns the offset of the `>` that ends the tag whose attributes start at the given
+/// offset, skipping the ones in quoted attribute values, e.g. `<script data-x="a>b">`.
+fn tag_end(content: &[u8], from: usize) -> Option<usize> {
+    let mut quote = None;
+    for (i, &c) in content.iter().enumerate().skip(from) {
+        match quote {
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if c == b'"' || c == b'\'' => quote = Some(c),
+            None if c == b'>' => return Some(i),
+            None => {}
+        }
+    }
+    None
+}
+
+/// Returns the offset of the first occurrence of the given needle in the given content,
+/// from the given offset.
+fn find(content: &[u8], from: usize, needle: &[u8]) -> Option<usize> {
+    content[from..]
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|i| from + i)
+}
+
+/// Returns the tree-sitter point, whose column is in bytes, of the given offset.
+fn point_at(content: &[u8], offset: usize) -> Point {
+    let before = &content[..offset];
+    let row = before.iter().filter(|&&b| b == b'\n').count();
+    let line_start = before
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |i| i + 1);
+    Point::new(row, offset - line_start)
+}
@@ -1,7 +1,13 @@
-use anyhow::{anyhow as error, Result};
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use anyhow::{Context, Result};
 use tree_sitter::{LanguageError, Parser, Query};
 
-use crate::protocol::types::Language;
+use crate::{error::LsifError, protocol::types::Language};
 
 extern "C" {
     fn tree_sitter_javascript() -> tree_sitter::Language;
@@ -16,14 +22,51 @@ extern "C" {
     // fn tree_sitter_lua() -> tree_sitter::Language;
 }
 
-pub fn query_for_language(language: &Language) -> Result<Query> {
-    let query_src = language.get_query_source();
-    let query = Query::new(ts_language_from(&language), &query_src).map_err(|e| {
-        error!(
-            "\n\nError in the query file for the {:?} language: \n'\n{}\n' is not valid {:?}. (line {}, column {})\n",
-            language, e.message, e.kind, e.row + 1, e.column + 1,
-        )
-    })?;
+/// Returns the source of the query of the given language. If a query directory is given
+/// and contains a `<language>.scm` file, e.g. `typescript.scm`, it is used instead of the
+/// query embedded in the binary.
+pub fn query_source_for_language(language: &Language, query_dir: Option<&Path>) -> Result<String> {
+    if let Some(query_dir) = query_dir {
+        let path = query_dir.join(format!("{}.scm", language.to_string().to_lowercase()));
+        if path.is_file() {
+            return std::fs::read_to_string(&path)
+                .with_context(|| format!("Could not read the query file {:?}", path));
+        }
+    }
+    Ok(language.get_query_source())
+}
+
+/// The compiled queries, by language and query source.
+type QueryCache = Mutex<BTreeMap<(Language, String), Arc<Query>>>;
+
+/// Returns the compiled query of the given language with the given source. Queries are
+/// compiled once per process and shared afterwards, e.g. by consecutive runs of a library
+/// user. Queries that fail to compile are not cached.
+pub fn query_for_language(language: &Language, query_src: &str) -> Result<Arc<Query>> {
+    static QUERIES: OnceLock<QueryCache> = OnceLock::new();
+    let mut queries = QUERIES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    let key = (*language, query_src.to_string());
+    if let Some(query) = queries.get(&key) {
+        return Ok(Arc::clone(query));
+    }
+
+    let query = Arc::new(compile_query(language, query_src)?);
+    queries.insert(key, Arc::clone(&query));
+    Ok(query)
+}
+
+fn compile_query(language: &Language, query_src: &str) -> Result<Query> {
+    let query =
+        Query::new(ts_language_from(&language)?, query_src).map_err(|e| LsifError::Query {
+            language: *language,
+            message: e.message,
+            kind: format!("{:?}", e.kind),
+            line: e.row + 1,
+            column: e.column + 1,
+        })?;
     Ok(query)
 }
 
@@ -33,17 +76,24 @@ pub fn parser_for_language(language: tree_sitter::Language) -> Result<Parser, La
     Ok(parser)
 }
 
-/// Returns the corresponding treesitter language.
+/// Returns the corresponding treesitter language, or an error if the grammar of the
+/// language is not compiled in.
 ///
 /// This function uses unsafe code to interface with the treesitter parsers.
-pub fn ts_language_from(language: &Language) -> tree_sitter::Language {
-    match language {
+pub fn ts_language_from(language: &Language) -> Result<tree_sitter::Language> {
+    Ok(match language {
         Language::JavaScript => unsafe { tree_sitter_javascript() },
         Language::GraphQL => unsafe { tree_sitter_graphql() },
         Language::Java => unsafe { tree_sitter_java() },
-        Language::Lua => unsafe { panic!() },
+        Language::Lua => {
+            return Err(LsifError::UnsupportedLanguage {
+                language: *language,
+                grammar: language.grammar_name(),
+            }
+            .into())
+        }
         // TODO: the tsx parser is used for all typescript files which might
         // cause performance degradation
         Language::TypeScript => unsafe { tree_sitter_tsx() },
-    }
+    })
 }
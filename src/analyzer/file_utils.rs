@@ -1,9 +1,29 @@
 use std::{fmt::Debug, path::Path};
 
-use anyhow::{Context, Result};
+use anyhow::Result;
 
-pub fn read_file<P: AsRef<Path> + Debug>(path: P) -> Result<String> {
-    let res = std::fs::read_to_string(&path)
-        .with_context(|| format!("Could not read file {:?}", path))?;
-    Ok(res)
+use crate::error::LsifError;
+
+/// Reads the raw content of the file at the given path. The content is not required to be
+/// valid UTF-8, e.g. files encoded in Latin-1 can be parsed, only the text that is emitted
+/// needs to be decoded.
+pub fn read_file<P: AsRef<Path> + Debug>(path: P) -> Result<Vec<u8>> {
+    std::fs::read(&path).map_err(|source| {
+        LsifError::Io {
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+        .into()
+    })
+}
+
+/// Returns true if the file with the given path is a TypeScript declaration file.
+pub fn is_declaration_file(path: &str) -> bool {
+    path.ends_with(".d.ts")
+}
+
+/// Returns the path of the file that implements the declarations of the given `.d.ts`
+/// file, e.g. `foo.ts` for `foo.d.ts`.
+pub fn implementation_path_of(path: &str) -> Option<String> {
+    path.strip_suffix(".d.ts").map(|p| format!("{}.ts", p))
 }
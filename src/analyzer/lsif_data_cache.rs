@@ -1,25 +1,39 @@
-use std::{collections::HashMap, sync::Arc};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::Arc,
+};
 
 use smol_str::SmolStr;
 
 use super::analyzer::{Definition, Location, Reference};
-use crate::{analyzer::analyzer::DefinitionScope, protocol::types::ID};
+use crate::{
+    analyzer::analyzer::DefinitionScope,
+    protocol::types::{Contents, ReferenceAccess, ID},
+};
 
 #[derive(Default)]
 pub struct LsifDataCache {
     /// Filename -> Info
     documents: HashMap<String, DocumentInfo>,
-    /// Filename -> Offset -> Range ID
-    ranges: HashMap<String, HashMap<usize, ID>>,
+    /// Filename -> (Start byte, End byte) -> Range ID
+    ranges: HashMap<String, HashMap<(usize, usize), ID>>,
     /// Definition Info Cache
     def_infos: HashMap<Location, DefinitionInfo>,
     /// Exported definitions Cache (Name -> Definition)
     exported_defs: HashMap<SmolStr, Arc<Definition>>,
+    /// Serialized hover contents -> Hover result ID
+    hover_results: HashMap<String, ID>,
 }
 
 /// Methods for caching and retrieving documents
 impl LsifDataCache {
-    pub fn cache_document(&mut self, filename: String, document_id: ID) {
+    /// Caches the document with the given filename and identifier. Returns the identifier
+    /// of the cached document, which is the existing one if the filename was already cached.
+    pub fn cache_document(&mut self, filename: String, document_id: ID) -> ID {
+        if let Some(existing) = self.documents.get(&filename) {
+            return existing.id;
+        }
+
         self.documents.insert(
             filename.clone(),
             DocumentInfo {
@@ -29,6 +43,7 @@ impl LsifDataCache {
             },
         );
         self.ranges.insert(filename, Default::default());
+        document_id
     }
 
     pub fn get_document_id(&self, filename: &str) -> Option<ID> {
@@ -43,8 +58,27 @@ impl LsifDataCache {
         self.documents.iter().map(|(_p, d)| d)
     }
 
-    pub fn get_range_id(&self, filename: &str, offset: usize) -> Option<ID> {
-        self.ranges.get(filename).unwrap().get(&offset).map(|v| *v)
+    /// Returns the identifier of the range emitted for the span of the given location, if
+    /// any, whether it is the range of a definition or of a reference.
+    pub fn get_range_id(&self, location: &Location) -> Option<ID> {
+        self.ranges
+            .get(&location.file_path)
+            .unwrap()
+            .get(&span(location))
+            .copied()
+    }
+
+    /// Caches the given range identifier for the span of the given location. Returns false,
+    /// without caching anything, if a range was already cached for that span.
+    fn cache_range(&mut self, location: &Location, range_id: ID) -> bool {
+        let file_ranges = self.ranges.get_mut(&location.file_path).unwrap();
+        match file_ranges.entry(span(location)) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(entry) => {
+                entry.insert(range_id);
+                true
+            }
+        }
     }
 
     pub fn get_document(&self, filename: &str) -> Option<&DocumentInfo> {
@@ -69,9 +103,10 @@ impl LsifDataCache {
         range_id: ID,
         result_set_id: ID,
     ) {
-        let file_ranges = self.ranges.get_mut(&def.location.file_path).unwrap();
-        file_ranges.insert(def.location.range.start_byte, range_id);
-
+        // Several patterns can define the same node, e.g. an exported function declaration
+        // is also a definition of its scope. Each of them has its own range, and references
+        // at that span reuse the range of the first one
+        self.cache_range(&def.location, range_id);
         let document_info = self.get_mut_document(&def.location.file_path).unwrap();
         document_info.definition_range_ids.push(range_id);
 
@@ -83,6 +118,11 @@ impl LsifDataCache {
         };
         self.def_infos
             .insert(def.location.clone(), def_info.clone());
+        self.cache_exported_definition(def);
+    }
+
+    /// Caches the given definition to be found by name, if it is exported.
+    pub fn cache_exported_definition(&mut self, def: &Arc<Definition>) {
         if def.kind == DefinitionScope::Exported {
             self.exported_defs
                 .insert(SmolStr::clone(&def.node_name), Arc::clone(def));
@@ -92,29 +132,64 @@ impl LsifDataCache {
     pub fn defs_with_name(&self, name: &SmolStr) -> Option<&Arc<Definition>> {
         self.exported_defs.get(name)
     }
+
+    /// Forgets the exported definitions cached so far, so that they are not found by name,
+    /// e.g. before indexing the files of another language.
+    pub fn clear_exported_definitions(&mut self) {
+        self.exported_defs.clear();
+    }
+}
+
+/// Methods for sharing hover results between definitions with the same hover contents
+impl LsifDataCache {
+    pub fn get_hover_result_id(&self, contents: &Contents) -> Option<ID> {
+        self.hover_results.get(&hover_key(contents)).copied()
+    }
+
+    pub fn cache_hover_result(&mut self, contents: &Contents, hover_result_id: ID) {
+        self.hover_results
+            .insert(hover_key(contents), hover_result_id);
+    }
+}
+
+/// Returns the key of the given hover contents in the cache.
+fn hover_key(contents: &Contents) -> String {
+    serde_json::to_string(contents).unwrap()
 }
 
 /// Methods for caching and retrieving references
 impl LsifDataCache {
-    pub fn cache_reference(&mut self, def: &Definition, r: &Reference, range_id: ID) {
-        {
-            let id = self.get_mut_document(&def.location.file_path).unwrap().id;
-            let def_info = self.def_infos.get_mut(&def.location).unwrap();
-            def_info.reference_range_ids.entry(id).or_default();
-            let def_range_ids = def_info.reference_range_ids.get_mut(&id).unwrap();
-            def_range_ids.push(range_id);
+    /// Caches the given reference range as a reference to the given definition. Returns
+    /// false, without caching anything, if the range was already cached for that definition.
+    pub fn cache_reference(&mut self, def: &Definition, r: &Reference, range_id: ID) -> bool {
+        let id = self.get_mut_document(&def.location.file_path).unwrap().id;
+        let def_info = self.def_infos.get_mut(&def.location).unwrap();
+        let def_range_ids = def_info
+            .reference_range_ids
+            .entry((id, r.access))
+            .or_default();
+        if def_range_ids.contains(&range_id) {
+            return false;
         }
-
-        let document_info = self.get_mut_document(&r.location.file_path).unwrap();
-        document_info.reference_range_ids.push(range_id);
+        def_range_ids.push(range_id);
+        true
     }
 
+    /// Caches the given range, that was emitted for the given reference, as a range of the
+    /// document of the reference.
     pub fn cache_reference_range(&mut self, r: &Reference, range_id: ID) {
-        let file_ranges = self.ranges.get_mut(&r.location.file_path).unwrap();
-        file_ranges.insert(r.location.range.start_byte, range_id);
+        if self.cache_range(&r.location, range_id) {
+            let document_info = self.get_mut_document(&r.location.file_path).unwrap();
+            document_info.reference_range_ids.push(range_id);
+        }
     }
 }
 
+/// Returns the span of the given location in its file, which identifies its range.
+fn span(location: &Location) -> (usize, usize) {
+    (location.range.start_byte, location.range.end_byte)
+}
+
 pub struct DocumentInfo {
     pub id: ID,
     pub definition_range_ids: Vec<ID>,
@@ -126,6 +201,6 @@ pub struct DefinitionInfo {
     pub document_id: ID,
     pub range_id: ID,
     pub result_set_id: ID,
-    /// Document ID -> Range ID
-    pub reference_range_ids: HashMap<ID, Vec<ID>>,
+    /// (Document ID, Access) -> Range ID
+    pub reference_range_ids: HashMap<(ID, ReferenceAccess), Vec<ID>>,
 }
@@ -1,4 +1,7 @@
 pub(crate) mod analyzer;
+pub mod containment;
+pub(crate) mod diagnostics;
+pub(crate) mod embedded;
 pub(crate) mod ffi;
 pub(crate) mod file_utils;
 pub(crate) mod lsif_data_cache;
@@ -1,33 +1,440 @@
-use std::path::{Component, Path, PathBuf};
+use std::{
+    ffi::OsString,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
 
+use anyhow::{bail, Context, Result};
+use log::LevelFilter;
 use structopt::StructOpt;
 
-use crate::protocol::types::Language;
+use crate::{
+    analyzer::ffi::ts_language_from,
+    config::{Config, CONFIG_FILE},
+    emitter::file_emitter::OutputFormat,
+    protocol::types::{
+        Language, AUTO_LANGUAGES, DEFAULT_LSIF_VERSION, ID, MONIKER_SCHEME, SUPPORTED_LSIF_VERSIONS,
+    },
+};
+
+/// The markers of the comments that `--todo-comments` emits by default.
+pub const DEFAULT_TODO_MARKERS: &str = "TODO,FIXME,HACK";
 
 /// Represents options received from the command line
 #[derive(Clone, Debug, StructOpt)]
 #[structopt(
     name = "zas-lsif-tools",
-    about = "An extremely fast, parallelized and (mostly) language-agnostic LSIF indexer (use --langs to see supported languages).\n\n"
+    about = "An extremely fast, parallelized and (mostly) language-agnostic LSIF indexer (use `languages` to see supported languages, `schema` to print the JSON Schema of the output, and `merge` to combine dumps).\n\n"
 )]
 pub struct Opts {
     /// Specifies the directory to index.
     #[structopt(parse(from_os_str))]
     pub project_root: PathBuf,
-    /// Specifies the language (use --langs to see supported languages)
-    pub language: Language,
+    /// Specifies the language (use `languages` to see supported languages), or `auto` to index
+    /// the files of all the supported languages.
+    #[structopt(default_value = "auto")]
+    pub language: LanguageSelection,
     /// The output file, `dump.json` if not present.
     #[structopt(short, long, parse(from_os_str))]
     pub output: Option<PathBuf>,
+    /// Writes one dump per package to the given directory instead of a single dump, where
+    /// the packages are the top-level directories of the project root, e.g. for monorepos
+    /// that upload LSIF data per package. See `indexer::shards`.
+    #[structopt(long, parse(from_os_str))]
+    pub out_dir: Option<PathBuf>,
+    /// The format of the output file, either `ndjson` (one entry per line) or
+    /// `array` (a single JSON array).
+    #[structopt(long, default_value = "ndjson")]
+    pub output_format: OutputFormat,
+    /// The number of bytes buffered before writing to the output file, 64 KiB if not
+    /// present. A larger buffer makes fewer writes for huge dumps.
+    #[structopt(long)]
+    pub buffer_size: Option<usize>,
+    /// A file in which the analysis results are cached between runs, so that only the
+    /// files that changed since the last run are analyzed again.
+    #[structopt(long, parse(from_os_str))]
+    pub incremental: Option<PathBuf>,
+    /// The LSIF version declared in the metadata vertex.
+    #[structopt(long, default_value = DEFAULT_LSIF_VERSION)]
+    pub lsif_version: String,
+    /// Checks that every emitted edge references emitted vertices, and exits with
+    /// an error if it does not.
+    #[structopt(long = "validate")]
+    pub validate_graph: bool,
+    /// Prints counts of what was indexed to stderr, see `--stats-file` to write them to a
+    /// file instead.
+    #[structopt(long)]
+    pub stats: bool,
+    /// Writes counts of what was indexed as JSON to the given file.
+    #[structopt(long, parse(from_os_str))]
+    pub stats_file: Option<PathBuf>,
+    /// Writes the references for which no definition was found to the given file, one
+    /// JSON object per line.
+    #[structopt(long, parse(from_os_str))]
+    pub report_unresolved: Option<PathBuf>,
+    /// Emits an `import` moniker, identified by the symbol name, for each reference for
+    /// which no definition was found, so that it can be linked to the dumps of other
+    /// repositories. References to the dependencies of the package manifest always get one.
+    #[structopt(long)]
+    pub emit_external_monikers: bool,
+    /// Writes the time taken to parse and analyze each file to the given file, one JSON
+    /// object per line, the slowest file first.
+    #[structopt(long, parse(from_os_str))]
+    pub timings: Option<PathBuf>,
+    /// A file listing the files to index, one path per line, instead of traversing the
+    /// project root. Relative paths are relative to the project root. Use `-` for stdin.
+    #[structopt(long, parse(from_os_str))]
+    pub files_from: Option<PathBuf>,
+    /// Indexes the files at the given git revision, e.g. a commit hash or a tag, instead of
+    /// the files of the working tree. The project root can be a bare repository.
+    #[structopt(long)]
+    pub git_ref: Option<String>,
+    /// Only emits the files that were added or changed since the given git revision, e.g.
+    /// the base branch of a pull request, in the working tree or in `--git-ref` if given.
+    /// The unchanged files are still analyzed, and the references to their exported
+    /// definitions get `import` monikers with the identifiers of the definitions.
+    #[structopt(long)]
+    pub since: Option<String>,
+    /// Follows symbolic links to files and directories when traversing the project root. A
+    /// file that is reached through several links is indexed once.
+    #[structopt(long)]
+    pub follow_symlinks: bool,
+    /// Indexes hidden files and the files of hidden directories, e.g. `.storybook/`, when
+    /// traversing the project root. They are skipped by default.
+    #[structopt(long)]
+    pub include_hidden: bool,
+    /// Skips the files matching the given glob, relative to the project root, e.g.
+    /// `generated/**`. Can be given several times.
+    #[structopt(long, number_of_values = 1)]
+    pub exclude: Vec<String>,
+    /// Gives the language to the files matching the glob, relative to the project root,
+    /// instead of the language of their extension, e.g. `flow/**/*.js=typescript`. Only the
+    /// files with the extension of a supported language are overridden. Can be given several
+    /// times, the last glob that matches a file wins.
+    #[structopt(long, number_of_values = 1)]
+    pub language_override: Vec<LanguageOverride>,
+    /// Skips the files larger than the given number of bytes, e.g. minified bundles.
+    #[structopt(long)]
+    pub max_file_size: Option<u64>,
+    /// Skips the files that take longer than the given number of milliseconds to parse, e.g.
+    /// pathological inputs that would stall the whole run.
+    #[structopt(long)]
+    pub parse_timeout_ms: Option<u64>,
+    /// Parses and analyzes the files with the given number of threads instead of one per
+    /// CPU, e.g. to leave CPUs to the other jobs of a CI runner.
+    #[structopt(long)]
+    pub threads: Option<usize>,
+    /// Prints the files that would be indexed, without indexing them.
+    #[structopt(long)]
+    pub dry_run: bool,
+    /// Emits document URIs relative to the project root, for dumps shared across machines.
+    #[structopt(long)]
+    pub relative_paths: bool,
+    /// A directory with custom query files, named after the language, e.g. `typescript.scm`.
+    /// The embedded query is used for the languages without a file in it.
+    #[structopt(long, parse(from_os_str))]
+    pub query_dir: Option<PathBuf>,
+    /// Skips reporting the syntax errors of the files as diagnostics.
+    #[structopt(long)]
+    pub no_diagnostics: bool,
+    /// Emits the comments with a todo marker, e.g. `// TODO: fix`, as information
+    /// diagnostics of their document, to keep track of outstanding work.
+    #[structopt(long)]
+    pub todo_comments: bool,
+    /// The comma-separated markers of the todo comments, see `--todo-comments`.
+    #[structopt(long, use_delimiter = true, default_value = DEFAULT_TODO_MARKERS)]
+    pub todo_markers: Vec<String>,
+    /// Skips the hover results of the definitions, to shrink the dump when hovers are not
+    /// needed.
+    #[structopt(long)]
+    pub no_hover: bool,
+    /// Only emits the definitions, without the references and the reference results, for
+    /// consumers that only need symbol outlines. References are not even analyzed.
+    #[structopt(long)]
+    pub definitions_only: bool,
+    /// Adds the byte offsets of each range in its file, as `startByte` and `endByte`, to the
+    /// range vertices, for tools that patch the text.
+    #[structopt(long)]
+    pub emit_byte_offsets: bool,
+    /// The maximum number of re-exports, e.g. `export { a as b } from './a'` in index
+    /// files, followed to resolve a reference. References through more re-exports are
+    /// unresolved.
+    #[structopt(long, default_value = "8")]
+    pub max_import_depth: usize,
+    /// Makes the definitions of test files local to their file, so that test helpers are
+    /// not resolved from other files instead of the real exports with the same name.
+    #[structopt(long)]
+    pub treat_tests_as_local: bool,
+    /// The globs of the test files, `*.test.*` and `*.spec.*` if none is given. Can be
+    /// given several times.
+    #[structopt(long, number_of_values = 1)]
+    pub test_glob: Vec<String>,
+    /// The scheme of the monikers of the definitions, for consumers that filter monikers by
+    /// scheme. The monikers of the package of the project use the scheme of its package
+    /// manager, e.g. `npm`.
+    #[structopt(long, default_value = MONIKER_SCHEME)]
+    pub moniker_scheme: String,
+    /// The name of the project vertex, the name of the project root directory by default.
+    #[structopt(long)]
+    pub project_name: Option<String>,
+    /// Appends the entries to the output file instead of overwriting it, e.g. to combine
+    /// the dumps of several languages. The identifiers continue after the largest one of
+    /// the existing dump, and the metadata vertex is only emitted if the file is empty, so
+    /// each run adds its own project. Only supported with the `ndjson` output format.
+    #[structopt(long)]
+    pub append: bool,
+    /// Reads default options from the given config file instead of the `lsif-os.toml` file
+    /// of the project root, see `Opts::from_config_and_args`.
+    #[structopt(long, parse(from_os_str))]
+    pub config: Option<PathBuf>,
+    /// Hides the progress bars and only logs errors, e.g. in CI logs.
+    #[structopt(short, long)]
+    pub quiet: bool,
+    /// Logs the parsing and analysis time of each file, and each unresolved reference
+    /// if repeated (`-vv`).
+    #[structopt(short, long, parse(from_occurrences))]
+    pub verbose: u8,
+    /// The raw command-line arguments, recorded in the tool info of the dump.
+    #[structopt(skip)]
+    pub args: Vec<String>,
+    /// The largest identifier of the dump that the entries are appended to, 0 if there
+    /// are no entries before them, see `append`.
+    #[structopt(skip)]
+    pub appended_after_id: ID,
 }
 
 impl Opts {
-    pub fn canonicalize_paths(&mut self) {
-        self.project_root = self.project_root.canonicalize().unwrap();
+    /// Parses the given command-line arguments, and sets the options that are not given
+    /// to the ones of the config file. The config file is the one given with `--config`, or
+    /// the `lsif-os.toml` file of the project root if it exists.
+    pub fn from_config_and_args<I>(args: I) -> Result<Self>
+    where
+        I: IntoIterator,
+        I::Item: Into<OsString> + Clone,
+    {
+        let matches = Opts::clap().get_matches_from(args);
+        let mut opt = Opts::from_clap(&matches);
+        let path = match &opt.config {
+            Some(path) => path.clone(),
+            None => opt.project_root.join(CONFIG_FILE),
+        };
+        if opt.config.is_some() || path.is_file() {
+            Config::read(&path)?.apply(&mut opt, &matches)?;
+        }
+        Ok(opt)
+    }
+
+    /// Makes the project root absolute and sets the default output path, unless the dumps
+    /// are written to an output directory. Returns an error if the project root does not
+    /// exist or is not a directory.
+    pub fn canonicalize_paths(&mut self) -> Result<()> {
+        let not_a_directory = format!(
+            "The project root {:?} does not exist or is not a directory",
+            self.project_root
+        );
+        self.project_root = self
+            .project_root
+            .canonicalize()
+            .context(not_a_directory.clone())?;
+        if !self.project_root.is_dir() {
+            bail!(not_a_directory);
+        }
+        if self.out_dir.is_some() {
+            return Ok(());
+        }
         self.output = Some(self.output.as_ref().map_or(
             normalize_path(&self.project_root.join(PathBuf::from("dump.json"))),
             |p| normalize_path(p),
         ));
+        Ok(())
+    }
+
+    /// Returns the most verbose level of the messages to log.
+    pub fn log_level(&self) -> LevelFilter {
+        match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        }
+    }
+
+    /// Returns an error if the options cannot be used to produce a valid dump.
+    pub fn validate(&self) -> Result<()> {
+        if !SUPPORTED_LSIF_VERSIONS.contains(&self.lsif_version.as_str()) {
+            bail!(
+                "Unsupported LSIF version {}, supported versions are: {}",
+                self.lsif_version,
+                SUPPORTED_LSIF_VERSIONS.join(", ")
+            );
+        }
+        if self.git_ref.is_some() && self.files_from.is_some() {
+            bail!("--git-ref cannot be used with --files-from");
+        }
+        if self.threads == Some(0) {
+            bail!("--threads must be at least 1");
+        }
+        if self.todo_comments && self.no_diagnostics {
+            bail!("--todo-comments cannot be used with --no-diagnostics");
+        }
+        if self.append && self.output_format == OutputFormat::Array {
+            bail!("--append cannot be used with the array output format");
+        }
+        if self.out_dir.is_some() {
+            let per_dump_options = [
+                ("--output", self.output.is_some()),
+                ("--append", self.append),
+                ("--incremental", self.incremental.is_some()),
+                ("--files-from", self.files_from.is_some()),
+                ("--git-ref", self.git_ref.is_some()),
+                ("--stats", self.stats),
+                ("--stats-file", self.stats_file.is_some()),
+                ("--timings", self.timings.is_some()),
+                ("--report-unresolved", self.report_unresolved.is_some()),
+            ];
+            if let Some((flag, _)) = per_dump_options.iter().find(|(_, given)| *given) {
+                bail!("--out-dir cannot be used with {}", flag);
+            }
+        }
+        if self.language == LanguageSelection::Auto && self.incremental.is_some() {
+            bail!("--incremental cannot be used with the auto language");
+        }
+        Ok(())
+    }
+}
+
+/// The subcommands, given as the first argument instead of the project root, e.g.
+/// `zas-lsif-tools merge a.json b.json -o dump.json`.
+#[derive(Clone, Debug, StructOpt)]
+#[structopt(name = "zas-lsif-tools")]
+pub enum Command {
+    /// Combines dumps that were indexed separately, e.g. the dumps of `--out-dir`, into one.
+    Merge(MergeOpts),
+    /// Lists the supported languages.
+    Languages,
+    /// Prints the JSON Schema of the emitted entries.
+    Schema,
+}
+
+impl Command {
+    /// The names of the subcommands.
+    pub const NAMES: &'static [&'static str] = &["merge", "languages", "schema"];
+
+    /// Parses the given command-line arguments if their first argument is the name of a
+    /// subcommand, returns `None` otherwise.
+    pub fn from_args_if_any(args: &[String]) -> Option<Self> {
+        let name = args.get(1)?;
+        if Self::NAMES.contains(&name.as_str()) {
+            Some(Self::from_iter(args))
+        } else {
+            None
+        }
+    }
+}
+
+/// Returns the list of the languages printed by the `languages` subcommand, one per line.
+/// The languages whose grammar is not compiled in are listed as such.
+pub fn languages_list() -> String {
+    let mut list = "Currently supported languages:\n".to_string();
+    for language in Language::all() {
+        list.push_str(&format!("\t- {}", language.to_string()));
+        if ts_language_from(language).is_err() {
+            list.push_str(" (not yet wired up)");
+        }
+        list.push('\n');
+    }
+    list.push_str("Use `auto` to index the files of all of them.");
+    list
+}
+
+/// The options of the `merge` subcommand.
+#[derive(Clone, Debug, StructOpt)]
+pub struct MergeOpts {
+    /// The dumps to merge, in the `ndjson` or the `array` output format.
+    #[structopt(parse(from_os_str), required = true, min_values = 2)]
+    pub dumps: Vec<PathBuf>,
+    /// The output file.
+    #[structopt(short, long, parse(from_os_str))]
+    pub output: PathBuf,
+    /// The format of the output file, either `ndjson` (one entry per line) or
+    /// `array` (a single JSON array).
+    #[structopt(long, default_value = "ndjson")]
+    pub output_format: OutputFormat,
+}
+
+/// The languages of the files to index.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LanguageSelection {
+    /// The language of each file is inferred from its extension, see `Language::from_path`.
+    Auto,
+    Single(Language),
+}
+
+impl LanguageSelection {
+    /// Returns the language of the file at the given path, if it is one to index.
+    pub fn language_of(&self, path: &Path) -> Option<Language> {
+        match self {
+            LanguageSelection::Auto => Language::from_path(path),
+            LanguageSelection::Single(language) => {
+                Language::from_extension(path.extension()?.to_str()?)
+                    .filter(|found| found == language)
+            }
+        }
+    }
+
+    /// Returns true if the files of the given language are to index.
+    pub fn selects(&self, language: Language) -> bool {
+        match self {
+            LanguageSelection::Auto => true,
+            LanguageSelection::Single(selected) => *selected == language,
+        }
+    }
+}
+
+/// The language of the files matching a glob, see `--language-override`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LanguageOverride {
+    pub glob: String,
+    pub language: Language,
+}
+
+impl FromStr for LanguageOverride {
+    type Err = String;
+
+    /// Parses `<glob>=<language>`, e.g. `flow/**/*.js=typescript`. The language must be one
+    /// of `AUTO_LANGUAGES`, the others cannot be parsed.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (glob, language) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("Expected <glob>=<language>, found {:?}", s))?;
+        let language: Language = language.parse()?;
+        if !AUTO_LANGUAGES.contains(&language) {
+            return Err(format!(
+                "{} files cannot be indexed yet",
+                language.to_string()
+            ));
+        }
+        Ok(LanguageOverride {
+            glob: glob.to_string(),
+            language,
+        })
+    }
+}
+
+impl From<Language> for LanguageSelection {
+    fn from(language: Language) -> Self {
+        LanguageSelection::Single(language)
+    }
+}
+
+impl FromStr for LanguageSelection {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(LanguageSelection::Auto);
+        }
+        s.parse().map(LanguageSelection::Single)
     }
 }
 
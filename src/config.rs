@@ -0,0 +1,92 @@
+//! The `lsif-os.toml` config file, that sets default options for the projects of a team so
+//! that CI command lines stay short. Options given on the command line win over the file,
+//! except the boolean flags, which have no negation: a flag set to true in the file cannot
+//! be turned off on the command line.
+//!
+//! ```toml
+//! language = "typescript"
+//! exclude = ["**/*.test.ts", "generated/**"]
+//! output-format = "array"
+//! ```
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+use structopt::clap::ArgMatches;
+
+use crate::cli::Opts;
+
+/// The name of the config file that is read from the project root if no config file is
+/// given explicitly.
+pub const CONFIG_FILE: &str = "lsif-os.toml";
+
+/// The options that can be set in a config file, named like their command-line flags.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+    pub language: Option<String>,
+    pub exclude: Vec<String>,
+    pub test_glob: Vec<String>,
+    pub output_format: Option<String>,
+    pub max_file_size: Option<u64>,
+    pub threads: Option<usize>,
+    pub moniker_scheme: Option<String>,
+    pub project_name: Option<String>,
+    pub follow_symlinks: bool,
+    pub include_hidden: bool,
+    pub no_diagnostics: bool,
+    pub no_hover: bool,
+    pub todo_comments: bool,
+    pub todo_markers: Option<Vec<String>>,
+    pub treat_tests_as_local: bool,
+}
+
+impl Config {
+    /// Reads the config file at the given path.
+    pub fn read(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the config file {:?}", path))?;
+        toml::from_str(&content).with_context(|| format!("Invalid config file {:?}", path))
+    }
+
+    /// Sets the options of the config that were not given on the command line, as told by
+    /// the given matches, to the given options. Excluded globs are added to the ones of the
+    /// command line, and the booleans set to true turn their flag on.
+    pub fn apply(self, opt: &mut Opts, matches: &ArgMatches) -> Result<()> {
+        let is_given = |name: &str| matches.occurrences_of(name) > 0;
+        if let (Some(language), false) = (self.language, is_given("language")) {
+            opt.language = language
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid language in the config file: {}", e))?;
+        }
+        if let (Some(format), false) = (self.output_format, is_given("output-format")) {
+            opt.output_format = format
+                .parse()
+                .map_err(|e| anyhow::anyhow!("Invalid output format in the config file: {}", e))?;
+        }
+        if !is_given("max-file-size") {
+            opt.max_file_size = self.max_file_size.or(opt.max_file_size);
+        }
+        if !is_given("threads") {
+            opt.threads = self.threads.or(opt.threads);
+        }
+        if let (Some(scheme), false) = (self.moniker_scheme, is_given("moniker-scheme")) {
+            opt.moniker_scheme = scheme;
+        }
+        if let (Some(markers), false) = (self.todo_markers, is_given("todo-markers")) {
+            opt.todo_markers = markers;
+        }
+        if !is_given("project-name") {
+            opt.project_name = self.project_name.or_else(|| opt.project_name.take());
+        }
+        opt.exclude.extend(self.exclude);
+        opt.test_glob.extend(self.test_glob);
+        opt.follow_symlinks |= self.follow_symlinks;
+        opt.include_hidden |= self.include_hidden;
+        opt.no_diagnostics |= self.no_diagnostics;
+        opt.no_hover |= self.no_hover;
+        opt.todo_comments |= self.todo_comments;
+        opt.treat_tests_as_local |= self.treat_tests_as_local;
+        Ok(())
+    }
+}
@@ -1,4 +1,4 @@
-use crate::protocol::types::{Edge, Vertex, ID};
+use crate::protocol::types::{Edge, Element, Entry, Vertex, ID};
 
 /// An abstractions for an LSIF data emitter.
 pub trait Emitter {
@@ -6,6 +6,17 @@ pub trait Emitter {
 
     fn emit_edge<E: Into<Edge>>(&mut self, e: E) -> ID;
 
+    /// Emits the element of an entry that was already serialized, e.g. an entry of another
+    /// dump. The entry gets a new identifier from the emitter, which is returned, so the
+    /// identifiers stay in order. The vertices referenced by an edge are not remapped, the
+    /// caller must replace them with the identifiers of the re-emitted vertices.
+    fn emit_raw(&mut self, entry: Entry) -> ID {
+        match entry.data {
+            Element::Vertex(v) => self.emit_vertex(v),
+            Element::Edge(e) => self.emit_edge(e),
+        }
+    }
+
     /// This method needs to be called to ensure that all items
     /// have been emitted.
     fn end(&mut self);
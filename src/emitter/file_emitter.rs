@@ -1,20 +1,82 @@
 use std::{
     fs::File,
-    io::{BufWriter, Write},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    str::FromStr,
     sync::mpsc::{channel, Receiver, Sender},
 };
 
+use serde::Deserialize;
+
 use crate::{
     emitter::emitter::Emitter,
     protocol::types::{Edge, Element, Entry, NumberOrString, Vertex, ID},
 };
 
-const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+/// The capacity in bytes of the buffer of the written file of a `FileEmitter`, see
+/// `FileEmitter::with_capacity`.
+pub const DEFAULT_BUF_SIZE: usize = 64 * 1024;
+/// The number of entries sent at once to the thread of a `FileEmitter`.
+pub const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// The format in which a `FileEmitter` writes entries.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// One JSON object per line.
+    Ndjson,
+    /// A single JSON array containing all the entries.
+    Array,
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match &s.to_lowercase()[..] {
+            "ndjson" => Ok(OutputFormat::Ndjson),
+            "array" => Ok(OutputFormat::Array),
+            _ => Err("Output format not supported, expected 'ndjson' or 'array'".to_string()),
+        }
+    }
+}
 
 /// An `Emitter` that emits data to a file, in a new OS thread.
 pub struct FileEmitter {
     id: ID,
-    entry_sender: Sender<Entry>,
+    entry_sender: Sender<Message>,
+    /// The entries that were emitted but not sent yet, in order.
+    batch: Vec<Entry>,
+    batch_size: usize,
+}
+
+/// A message sent to the thread of a `FileEmitter`.
+enum Message {
+    Entries(Vec<Entry>),
+    /// There are no more entries to write.
+    End,
+    /// Stops writing entries before the end, the given sender is used to acknowledge
+    /// that the written entries were flushed.
+    Shutdown(Sender<()>),
+}
+
+/// A handle to stop a `FileEmitter` before `end` is called, e.g. when the process
+/// is interrupted.
+#[derive(Clone)]
+pub struct ShutdownHandle {
+    sender: Sender<Message>,
+}
+
+impl ShutdownHandle {
+    /// Makes the emitter stop writing entries and blocks until the entries written so
+    /// far are flushed, so that the file ends with a complete entry. The entries of the
+    /// batch that was not sent yet, and the entries emitted afterwards, are dropped, and
+    /// the flush signal is an error.
+    pub fn shutdown(&self) {
+        let (ack_sender, ack_receiver) = channel();
+        if self.sender.send(Message::Shutdown(ack_sender)).is_ok() {
+            let _ = ack_receiver.recv();
+        }
+    }
 }
 
 impl FileEmitter {
@@ -25,14 +87,44 @@ impl FileEmitter {
 
     /// Creates and return a new `FileEmitter` and a `Receiver` that should be used
     /// to receive a signal indicating that the emitter has finished emitting all
-    /// the data.
+    /// the data, or the IO error that made it stop.
     ///
     /// This method spawn a new thread that waits for data to emit until the `end` method
     /// is called.
     ///
     /// It is the reponsibiliy of the user of this struct to call `end` when there is
-    /// no more data to be emitted and then wait for the flush signal.
-    pub(crate) fn new(file: File) -> (Self, Receiver<()>) {
+    /// no more data to be emitted and then wait for the flush signal, see
+    /// `wait_until_flushed`. The signal is only a success once all the entries are written
+    /// and flushed, so a dump is complete if and only if the signal is a success.
+    pub fn new(file: File, format: OutputFormat) -> (Self, Receiver<io::Result<()>>) {
+        Self::with_capacity(file, format, DEFAULT_BUF_SIZE)
+    }
+
+    /// Same as `new`, but buffers the given number of bytes before writing to the file
+    /// instead of `DEFAULT_BUF_SIZE`, e.g. a larger buffer for huge dumps on fast disks.
+    pub fn with_capacity(
+        file: File,
+        format: OutputFormat,
+        capacity: usize,
+    ) -> (Self, Receiver<io::Result<()>>) {
+        Self::spawn(file, format, capacity)
+    }
+
+    /// Same as `new`, but emits data to the given writer instead of a file.
+    pub fn with_writer<W: Write + Send + 'static>(
+        writer: W,
+        format: OutputFormat,
+    ) -> (Self, Receiver<io::Result<()>>) {
+        Self::spawn(writer, format, DEFAULT_BUF_SIZE)
+    }
+
+    /// Spawns the thread that writes the entries to the given writer through a buffer with
+    /// the given capacity.
+    fn spawn<W: Write + Send + 'static>(
+        writer: W,
+        format: OutputFormat,
+        capacity: usize,
+    ) -> (Self, Receiver<io::Result<()>>) {
         let (signal_sender, signal_receiver) = channel();
         let (entry_sender, entry_receiver) = channel();
 
@@ -40,7 +132,8 @@ impl FileEmitter {
             Self::run(
                 entry_receiver,
                 signal_sender,
-                BufWriter::with_capacity(DEFAULT_BUF_SIZE, file),
+                format,
+                BufWriter::with_capacity(capacity, writer),
             );
         });
 
@@ -48,25 +141,181 @@ impl FileEmitter {
             Self {
                 id: 0,
                 entry_sender,
+                batch: Vec::with_capacity(DEFAULT_BATCH_SIZE),
+                batch_size: DEFAULT_BATCH_SIZE,
             },
             signal_receiver,
         )
     }
 
-    fn run(
-        entry_receiver: Receiver<Entry>,
-        signal_sender: Sender<()>,
-        mut buf_writer: BufWriter<File>,
+    /// Sets the number of entries sent at once to the emitter thread, 1 disables batching.
+    pub fn with_batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    /// Makes the identifiers of the emitted entries continue after the given one, e.g. the
+    /// last identifier of the dump that the entries are appended to, see `last_id`.
+    pub fn with_first_id_after(mut self, id: ID) -> Self {
+        self.id = id;
+        self
+    }
+
+    /// Returns a handle that can be used to stop this emitter from another thread.
+    pub fn shutdown_handle(&self) -> ShutdownHandle {
+        ShutdownHandle {
+            sender: self.entry_sender.clone(),
+        }
+    }
+
+    /// Writes the received entries until the end, a shutdown or an IO error, and sends
+    /// the result with the given sender. The entries written so far are flushed in any
+    /// case, but the result is only a success if the end was reached, so a shutdown or an
+    /// emitter dropped without calling `end` is an error.
+    ///
+    /// After an error, the receiver is dropped so the entries that are still emitted
+    /// are dropped.
+    fn run<W: Write>(
+        entry_receiver: Receiver<Message>,
+        signal_sender: Sender<io::Result<()>>,
+        format: OutputFormat,
+        mut buf_writer: BufWriter<W>,
     ) {
-        for entry in entry_receiver {
-            let line = serde_json::to_vec(&entry).unwrap();
-            buf_writer.write(&line).unwrap();
-            buf_writer.write(b"\n").unwrap();
+        let mut ack_sender = None;
+        let result = Self::write_entries(&entry_receiver, format, &mut buf_writer, &mut ack_sender)
+            .and_then(|reached_end| {
+                buf_writer.flush()?;
+                reached_end.map_err(io::Error::other)
+            });
+
+        // The shutdown is acknowledged first, so that whoever shut the emitter down, e.g. on
+        // an interrupt, can exit before the error of the partial dump is reported
+        if let Some(ack_sender) = ack_sender {
+            let _ = ack_sender.send(());
+        }
+        // Nobody may wait for the result of an emitter that was shut down
+        let _ = signal_sender.send(result);
+    }
+
+    /// Writes the received entries in the given format. If a shutdown message is received,
+    /// its acknowledgement sender is stored in `ack_sender`. Returns why the end was not
+    /// reached, if the entries stopped before the end message.
+    fn write_entries<W: Write>(
+        entry_receiver: &Receiver<Message>,
+        format: OutputFormat,
+        buf_writer: &mut BufWriter<W>,
+        ack_sender: &mut Option<Sender<()>>,
+    ) -> io::Result<Result<(), &'static str>> {
+        if format == OutputFormat::Array {
+            buf_writer.write_all(b"[")?;
         }
 
-        buf_writer.flush().unwrap();
-        signal_sender.send(()).unwrap();
+        let mut is_first = true;
+        // The receiver is disconnected if the emitter is dropped without calling `end`, and
+        // the entries of its last batch were never sent
+        let mut reached_end = Err("The emitter was dropped before the end of the entries");
+        for message in entry_receiver {
+            let entries = match message {
+                Message::Entries(entries) => entries,
+                Message::End => {
+                    reached_end = Ok(());
+                    break;
+                }
+                Message::Shutdown(sender) => {
+                    *ack_sender = Some(sender);
+                    reached_end = Err("The emitter was shut down before the end of the entries");
+                    break;
+                }
+            };
+            for entry in entries {
+                match format {
+                    OutputFormat::Ndjson => {
+                        serde_json::to_writer(&mut *buf_writer, &entry)?;
+                        buf_writer.write_all(b"\n")?;
+                    }
+                    OutputFormat::Array => {
+                        if !is_first {
+                            buf_writer.write_all(b",\n")?;
+                        }
+                        serde_json::to_writer(&mut *buf_writer, &entry)?;
+                    }
+                }
+                is_first = false;
+            }
+        }
+
+        if format == OutputFormat::Array {
+            buf_writer.write_all(b"]\n")?;
+        }
+
+        Ok(reached_end)
+    }
+
+    /// Adds the given entry to the batch, and sends the batch to the emitter thread once
+    /// it is full.
+    fn send(&mut self, entry: Entry) {
+        self.batch.push(entry);
+        if self.batch.len() >= self.batch_size {
+            self.send_batch();
+        }
+    }
+
+    /// Sends the entries of the batch to the emitter thread. The entries are dropped if
+    /// the emitter was shut down.
+    fn send_batch(&mut self) {
+        if self.batch.is_empty() {
+            return;
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(self.batch_size));
+        let _ = self.entry_sender.send(Message::Entries(batch));
+    }
+}
+
+/// Blocks until the emitter of the given signal receiver, see `FileEmitter::new`, has
+/// flushed all the entries. Returns the IO error that made the emitter stop, or an error if
+/// its thread stopped without sending a signal, e.g. because it panicked.
+pub fn wait_until_flushed(signal_receiver: &Receiver<io::Result<()>>) -> io::Result<()> {
+    signal_receiver.recv().unwrap_or_else(|_| {
+        Err(io::Error::other(
+            "The emitter thread stopped before flushing the output",
+        ))
+    })
+}
+
+/// The identifier of an entry of a dump, the rest of the entry is ignored.
+#[derive(Deserialize)]
+struct EntryId {
+    id: NumberOrString,
+}
+
+/// Returns the largest identifier of the entries of the `ndjson` dump at the given path,
+/// or 0 if the file is missing or empty.
+///
+/// Entries appended to the dump must have larger identifiers so that identifiers stay
+/// unique, consumers do not rely on them being contiguous.
+pub fn last_id(path: &Path) -> io::Result<ID> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    let mut last_id = 0;
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let entry: EntryId = serde_json::from_str(&line)?;
+        let id = match entry.id {
+            NumberOrString::Number(id) => id,
+            NumberOrString::String(id) => id
+                .parse()
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?,
+        };
+        last_id = last_id.max(id);
     }
+    Ok(last_id)
 }
 
 impl Emitter for FileEmitter {
@@ -77,7 +326,7 @@ impl Emitter for FileEmitter {
             data: Element::Vertex(v.into()),
         };
 
-        self.entry_sender.send(entry).unwrap();
+        self.send(entry);
 
         id
     }
@@ -89,15 +338,15 @@ impl Emitter for FileEmitter {
             data: Element::Edge(e.into()),
         };
 
-        self.entry_sender.send(entry).unwrap();
+        self.send(entry);
 
         id
     }
 
     fn end(&mut self) {
-        // to close the channel we need to take it and drop it
-        let mut entry_sender = channel().0;
-        std::mem::swap(&mut entry_sender, &mut self.entry_sender);
-        drop(entry_sender);
+        self.send_batch();
+        // The channel is not closed by dropping the sender, shutdown handles may still
+        // hold senders
+        let _ = self.entry_sender.send(Message::End);
     }
 }
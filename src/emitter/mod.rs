@@ -1,2 +1,3 @@
 pub mod emitter;
 pub mod file_emitter;
+pub mod validating_emitter;
@@ -0,0 +1,149 @@
+use std::{
+    collections::HashSet,
+    sync::mpsc::{channel, Receiver, Sender},
+};
+
+use anyhow::{anyhow, Result};
+
+use crate::{
+    emitter::emitter::Emitter,
+    protocol::types::{Edge, Item, NumberOrString, Vertex, ID},
+};
+
+/// An `Emitter` that wraps another emitter and checks the integrity of the emitted
+/// graph: every edge must reference vertices that were emitted, and `contains` edges
+/// must only point to documents or ranges.
+pub struct ValidatingEmitter<E: Emitter> {
+    inner: E,
+    result_sender: Sender<Result<()>>,
+
+    vertex_ids: HashSet<ID>,
+    /// Identifiers of the document and range vertices.
+    containable_ids: HashSet<ID>,
+    /// Edges are checked when `end` is called, so that the order in which
+    /// vertices and edges are emitted does not matter.
+    edges: Vec<(ID, Edge)>,
+}
+
+impl<E: Emitter> ValidatingEmitter<E> {
+    /// Creates and returns a new `ValidatingEmitter` and a `Receiver` that receives
+    /// the result of the validation once `end` is called.
+    pub fn new(inner: E) -> (Self, Receiver<Result<()>>) {
+        let (result_sender, result_receiver) = channel();
+
+        (
+            Self {
+                inner,
+                result_sender,
+                vertex_ids: Default::default(),
+                containable_ids: Default::default(),
+                edges: Default::default(),
+            },
+            result_receiver,
+        )
+    }
+
+    /// Returns an error listing all the invalid edges that were emitted.
+    fn validate(&self) -> Result<()> {
+        let mut violations = Vec::new();
+        for (id, edge) in &self.edges {
+            let (out_v, in_vs) = vertex_ids_of(edge);
+            for v in std::iter::once(out_v).chain(in_vs.iter().copied()) {
+                if !self.vertex_ids.contains(&v) {
+                    violations.push(format!("edge {} references unknown vertex {}", id, v));
+                }
+            }
+            if let Edge::Contains(_) = edge {
+                for v in in_vs {
+                    if self.vertex_ids.contains(&v) && !self.containable_ids.contains(&v) {
+                        violations.push(format!(
+                            "contains edge {} references vertex {} which is neither a document nor a range",
+                            id, v
+                        ));
+                    }
+                }
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Found {} invalid edges in the emitted graph:\n{}",
+                violations.len(),
+                violations.join("\n")
+            ))
+        }
+    }
+}
+
+impl<E: Emitter> Emitter for ValidatingEmitter<E> {
+    fn emit_vertex<V: Into<Vertex>>(&mut self, v: V) -> ID {
+        let vertex = v.into();
+        let is_containable = matches!(vertex, Vertex::Document(_) | Vertex::Range(_));
+
+        let id = self.inner.emit_vertex(vertex);
+        self.vertex_ids.insert(id);
+        if is_containable {
+            self.containable_ids.insert(id);
+        }
+
+        id
+    }
+
+    fn emit_edge<T: Into<Edge>>(&mut self, e: T) -> ID {
+        let edge = e.into();
+        let id = self.inner.emit_edge(edge.clone());
+        self.edges.push((id, edge));
+
+        id
+    }
+
+    fn end(&mut self) {
+        self.inner.end();
+        self.result_sender.send(self.validate()).unwrap();
+    }
+}
+
+/// Returns the identifiers of the outgoing vertex and the incoming vertices of the
+/// given edge.
+fn vertex_ids_of(edge: &Edge) -> (ID, Vec<ID>) {
+    match edge {
+        Edge::Contains(data) => (to_id(&data.out_v), data.in_vs.iter().map(to_id).collect()),
+        Edge::Item(item) => {
+            let data = match item {
+                Item::Definition(data) | Item::Reference(data) | Item::Neither(data) => data,
+            };
+            (
+                to_id(&data.out_v),
+                data.in_vs
+                    .iter()
+                    .map(to_id)
+                    .chain(std::iter::once(data.document))
+                    .collect(),
+            )
+        }
+        Edge::RefersTo(data)
+        | Edge::Next(data)
+        | Edge::Moniker(data)
+        | Edge::PackageInformation(data)
+        | Edge::Definition(data)
+        | Edge::Declaration(data)
+        | Edge::Hover(data)
+        | Edge::References(data)
+        | Edge::Implementation(data)
+        | Edge::TypeDefinition(data)
+        | Edge::FoldingRange(data)
+        | Edge::DocumentLink(data)
+        | Edge::DocumentSymbol(data)
+        | Edge::Diagnostic(data) => (to_id(&data.out_v), vec![to_id(&data.in_v)]),
+    }
+}
+
+fn to_id(n: &NumberOrString) -> ID {
+    match n {
+        NumberOrString::Number(n) => *n,
+        // The emitters in this crate only produce numeric identifiers.
+        NumberOrString::String(s) => s.parse().unwrap_or(0),
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use thiserror::Error;
+
+use crate::protocol::types::Language;
+
+/// The errors returned by `Indexer::index`, so that library users can tell what failed.
+/// The errors of the other kinds are kept as they were reported in `Other`.
+#[derive(Debug, Error)]
+pub enum LsifError {
+    /// The query of a language does not compile, e.g. a custom query of `--query-dir` that
+    /// uses a node the grammar does not have.
+    #[error("Error in the query file for the {language:?} language: \n'\n{message}\n' is not valid {kind}. (line {line}, column {column})")]
+    Query {
+        language: Language,
+        message: String,
+        /// What is not valid, e.g. `NodeType` for a node the grammar does not have.
+        kind: String,
+        line: usize,
+        column: usize,
+    },
+    /// A file to index could not be read.
+    #[error("Could not read file {path:?}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    /// The grammar of a language is not compiled in.
+    #[error("The {language:?} language is not yet wired up: the tree-sitter-{grammar} grammar is not compiled in")]
+    UnsupportedLanguage {
+        language: Language,
+        grammar: &'static str,
+    },
+    #[error(transparent)]
+    Other(anyhow::Error),
+}
+
+impl From<anyhow::Error> for LsifError {
+    /// Returns the `LsifError` that the given error was created from, if any, without the
+    /// context added to it, or `Other` otherwise.
+    fn from(error: anyhow::Error) -> Self {
+        match error.downcast::<LsifError>() {
+            Ok(error) => error,
+            Err(error) => LsifError::Other(error),
+        }
+    }
+}
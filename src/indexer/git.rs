@@ -0,0 +1,226 @@
+use std::{
+    collections::HashSet,
+    io::{BufRead, BufReader, Read, Write},
+    path::{Path, PathBuf},
+    process::{Child, ChildStdin, ChildStdout, Command, Stdio},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{bail, Context, Result};
+
+/// The files of a git repository at a given revision, read from the object database of the
+/// repository instead of the working tree, so that a bare repository or a commit other than
+/// the checked out one can be indexed.
+///
+/// The paths of the files are under the project root, like the paths of the working tree,
+/// so that the emitted documents are the same whether the files are checked out or not.
+#[derive(Clone, Debug)]
+pub struct GitTree {
+    /// The project root, the repository itself or one of the directories of its working tree.
+    root: PathBuf,
+    /// The hash of the commit that the revision resolved to.
+    commit: String,
+    /// The path of the root in the repository, e.g. `src/`, empty for the top-level directory.
+    prefix: String,
+    /// The process that reads the files, started by the first read and shared by the clones
+    /// of the tree.
+    cat_file: Arc<Mutex<Option<CatFile>>>,
+}
+
+impl GitTree {
+    /// Resolves the given revision, e.g. a branch, a tag or a commit hash, in the repository
+    /// that contains the given project root.
+    pub fn open(root: &Path, rev: &str) -> Result<Self> {
+        let commit = git(
+            root,
+            &["rev-parse", "--verify", &format!("{}^{{commit}}", rev)],
+        )
+        .with_context(|| format!("Could not resolve the git revision {:?}", rev))?;
+        let prefix = git(root, &["rev-parse", "--show-prefix"])?;
+
+        Ok(Self {
+            root: root.to_path_buf(),
+            commit: String::from_utf8(commit)?.trim().to_string(),
+            prefix: String::from_utf8(prefix)
+                .context("The path of the project root is not valid UTF-8")?
+                .trim()
+                .to_string(),
+            cat_file: Default::default(),
+        })
+    }
+
+    /// Returns the paths and the sizes in bytes of the files under the project root at the
+    /// revision. Symbolic links and submodules are not files of the revision.
+    pub fn files(&self) -> Result<Vec<(PathBuf, u64)>> {
+        let output = git(
+            &self.root,
+            &["ls-tree", "-r", "-z", "--long", "--full-name", &self.commit],
+        )?;
+        let output = String::from_utf8(output).context("The file paths are not valid UTF-8")?;
+
+        Ok(output
+            .split('\0')
+            .filter_map(|entry| {
+                // `<mode> <type> <object> <size>\t<path>`
+                let (info, path) = entry.split_once('\t')?;
+                let mut fields = info.split_whitespace();
+                let (mode, kind, size) = (fields.next()?, fields.next()?, fields.nth(1)?);
+                if kind != "blob" || mode == "120000" {
+                    return None;
+                }
+                let path = path.strip_prefix(self.prefix.as_str())?;
+                Some((self.root.join(path), size.parse().ok()?))
+            })
+            .collect())
+    }
+
+    /// Reads the content of the file at the given path, which must be under the project root.
+    pub fn read(&self, path: &Path) -> Result<Vec<u8>> {
+        self.read_if_exists(path)?
+            .with_context(|| format!("Could not find file {:?} at commit {}", path, self.commit))
+    }
+
+    /// Reads the content of the file at the given path, which must be under the project root,
+    /// or returns `None` if the revision has no such file.
+    ///
+    /// The files are read by a single `git cat-file --batch` process, one at a time.
+    pub fn read_if_exists(&self, path: &Path) -> Result<Option<Vec<u8>>> {
+        let relative = path
+            .strip_prefix(&self.root)
+            .with_context(|| format!("{:?} is not in the git repository", path))?;
+        let object = format!(
+            "{}:{}{}",
+            self.commit,
+            self.prefix,
+            relative.to_string_lossy().replace('\\', "/")
+        );
+        // The objects that the batch process reads are separated by new lines
+        if object.contains('\n') {
+            return git(&self.root, &["cat-file", "blob", &object])
+                .map(Some)
+                .with_context(|| {
+                    format!("Could not read file {:?} at commit {}", path, self.commit)
+                });
+        }
+
+        let mut cat_file = self.cat_file.lock().unwrap_or_else(|e| e.into_inner());
+        if cat_file.is_none() {
+            *cat_file = Some(CatFile::spawn(&self.root)?);
+        }
+        let result = cat_file.as_mut().unwrap().read(&object);
+        if result.is_err() {
+            // The output of the process can no longer be trusted, the next read starts a new one
+            *cat_file = None;
+        }
+        result.with_context(|| format!("Could not read file {:?} at commit {}", path, self.commit))
+    }
+}
+
+/// A `git cat-file --batch` process, which prints the objects whose names it is given.
+#[derive(Debug)]
+struct CatFile {
+    child: Child,
+    stdin: ChildStdin,
+    stdout: BufReader<ChildStdout>,
+}
+
+impl CatFile {
+    fn spawn(root: &Path) -> Result<Self> {
+        let mut child = Command::new("git")
+            .arg("-C")
+            .arg(root)
+            .args(["cat-file", "--batch"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .context("Could not run git, is it installed?")?;
+        let stdin = child.stdin.take().unwrap();
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        Ok(Self {
+            child,
+            stdin,
+            stdout,
+        })
+    }
+
+    /// Returns the content of the blob with the given name, or `None` if there is no such
+    /// object.
+    fn read(&mut self, object: &str) -> Result<Option<Vec<u8>>> {
+        writeln!(self.stdin, "{}", object)?;
+        self.stdin.flush()?;
+
+        // `<object> <type> <size>`, or `<name> missing`
+        let mut header = String::new();
+        self.stdout.read_line(&mut header)?;
+        let fields: Vec<&str> = header.split_whitespace().collect();
+        let size: usize = match fields[..] {
+            [_, "missing"] => return Ok(None),
+            [_, "blob", size] => size.parse()?,
+            [_, kind, _] => bail!("{} is a {}, not a file", object, kind),
+            _ => bail!("Unexpected output of git cat-file: {:?}", header.trim()),
+        };
+
+        // The content is followed by a new line
+        let mut content = vec![0; size + 1];
+        self.stdout.read_exact(&mut content)?;
+        content.truncate(size);
+        Ok(Some(content))
+    }
+}
+
+impl Drop for CatFile {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+/// Returns the paths of the files under the given project root that were added or changed
+/// since the given revision, in the given revision if any or in the working tree otherwise.
+/// The untracked files of the working tree that are not ignored are added files. Deleted
+/// files are not returned, as they have nothing to index.
+pub fn changed_files(root: &Path, since: &str, until: Option<&str>) -> Result<HashSet<PathBuf>> {
+    let mut args = vec![
+        "diff",
+        "--name-only",
+        "-z",
+        "--relative",
+        "--diff-filter=d",
+        since,
+    ];
+    args.extend(until);
+    let mut output = git(root, &args)
+        .with_context(|| format!("Could not find the files changed since {:?}", since))?;
+    if until.is_none() {
+        output.extend(git(
+            root,
+            &["ls-files", "--others", "--exclude-standard", "-z"],
+        )?);
+    }
+    let output = String::from_utf8(output).context("The file paths are not valid UTF-8")?;
+
+    Ok(output
+        .split('\0')
+        .filter(|path| !path.is_empty())
+        .map(|path| root.join(path))
+        .collect())
+}
+
+/// Runs git in the given directory with the given arguments and returns its stdout.
+fn git(dir: &Path, args: &[&str]) -> Result<Vec<u8>> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .context("Could not run git, is it installed?")?;
+    if !output.status.success() {
+        bail!(
+            "git {} failed: {}",
+            args.join(" "),
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,400 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use smol_str::SmolStr;
+use tree_sitter::{Point, Range};
+
+use crate::{
+    analyzer::{
+        analyzer::{Definition, DefinitionScope, FullRange, Import, Location, Reference},
+        diagnostics::{SyntaxError, TodoComment},
+    },
+    cli::Opts,
+    protocol::types::{Language, ReferenceAccess, SymbolKind},
+};
+
+/// A cache of the analysis results of each file, keyed by the hash of the file content.
+///
+/// Files whose content did not change since the cache was written do not need to be
+/// parsed and analyzed again. The LSIF graph is still emitted for all the files, so
+/// the identifiers of the emitted vertices and edges do not depend on the cache.
+pub struct IncrementalCache {
+    /// The path of the cache file.
+    path: PathBuf,
+    /// The cache read from the cache file, empty if it could not be used.
+    previous: CacheData,
+    /// The cache that will be written to the cache file.
+    next: CacheData,
+    /// Location -> Index of the definition in the `definitions` of its `CachedFile`.
+    def_indices: HashMap<Location, usize>,
+}
+
+/// The analysis results of the files that did not change.
+#[derive(Default)]
+pub struct CachedAnalysis {
+    pub definitions: Vec<Arc<Definition>>,
+    pub references: Vec<Reference>,
+    pub syntax_errors: Vec<SyntaxError>,
+    pub todo_comments: Vec<TodoComment>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheData {
+    /// The version of the tool that wrote the cache.
+    version: String,
+    language: Language,
+    /// The fingerprint of the options and the query that the files were analyzed with, see
+    /// `analysis_fingerprint`.
+    #[serde(default)]
+    fingerprint: u64,
+    /// Filename -> Analysis results
+    files: HashMap<String, CachedFile>,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct CachedFile {
+    hash: u64,
+    definitions: Vec<CachedDefinition>,
+    references: Vec<CachedReference>,
+    #[serde(default)]
+    syntax_errors: Vec<CachedDiagnostic>,
+    #[serde(default)]
+    todo_comments: Vec<CachedDiagnostic>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedDefinition {
+    range: CachedRange,
+    node_name: String,
+    comment: String,
+    /// The range of the scope of the definition, `None` if it is exported.
+    scope: Option<CachedRange>,
+    #[serde(default)]
+    is_declaration: bool,
+    #[serde(default)]
+    aliased_name: Option<String>,
+    /// The range of the whole declaration of the definition and its symbol kind, if known.
+    #[serde(default)]
+    full_range: Option<(CachedRange, u8)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CachedReference {
+    range: CachedRange,
+    node_name: String,
+    /// The index of the definition of the reference in the same file, if any.
+    def: Option<usize>,
+    #[serde(default)]
+    access: ReferenceAccess,
+    /// The module and the name of the imported symbol that the reference refers to, if any.
+    #[serde(default)]
+    import: Option<(String, String)>,
+}
+
+/// A syntax error or a todo comment.
+#[derive(Serialize, Deserialize)]
+struct CachedDiagnostic {
+    range: CachedRange,
+    message: String,
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+struct CachedRange {
+    start_byte: usize,
+    end_byte: usize,
+    start: (usize, usize),
+    end: (usize, usize),
+}
+
+impl CacheData {
+    fn new(language: Language, fingerprint: u64) -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            language,
+            fingerprint,
+            files: Default::default(),
+        }
+    }
+}
+
+/// Returns the fingerprint of the analysis of the files with the given options and query
+/// source, i.e. of everything but the content of the files that changes the cached results.
+/// The options of the config file are part of the given options.
+pub fn analysis_fingerprint(opt: &Opts, query_src: &str) -> u64 {
+    let options = format!(
+        "{:?}",
+        (
+            opt.definitions_only,
+            opt.no_diagnostics,
+            opt.todo_comments,
+            &opt.todo_markers,
+            opt.treat_tests_as_local,
+            &opt.test_glob,
+            &opt.language_override,
+        )
+    );
+    stable_hash(&[options.as_bytes(), query_src.as_bytes()])
+}
+
+impl IncrementalCache {
+    /// Reads the cache file at the given path. If the file does not exist or was
+    /// written by a different version, for a different language or with a different
+    /// fingerprint, see `analysis_fingerprint`, the cache is discarded and all the files
+    /// are analyzed.
+    pub fn load(path: &Path, language: Language, fingerprint: u64) -> Self {
+        let previous = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CacheData>(&content).ok())
+            .filter(|data| {
+                data.version == env!("CARGO_PKG_VERSION")
+                    && data.language == language
+                    && data.fingerprint == fingerprint
+            })
+            .unwrap_or_else(|| CacheData::new(language, fingerprint));
+
+        Self {
+            path: path.to_path_buf(),
+            previous,
+            next: CacheData::new(language, fingerprint),
+            def_indices: Default::default(),
+        }
+    }
+
+    /// Returns true if the analysis results of the file with the given path and
+    /// content are cached.
+    pub fn is_fresh(&self, filename: &str, content: &[u8]) -> bool {
+        self.previous
+            .files
+            .get(filename)
+            .is_some_and(|f| f.hash == hash_content(content))
+    }
+
+    /// Splits the given files into the files that need to be analyzed, and the cached
+    /// analysis results of the files that did not change. The content of the files is read
+    /// with the given function.
+    pub fn partition(
+        &mut self,
+        files: Vec<PathBuf>,
+        read: impl Fn(&Path) -> Result<Vec<u8>>,
+    ) -> Result<(Vec<PathBuf>, CachedAnalysis)> {
+        let mut changed_files = Vec::new();
+        let mut analysis = CachedAnalysis::default();
+
+        for path in files {
+            let content = read(&path)?;
+            let filename = path.to_str().unwrap().to_string();
+            let hash = hash_content(&content);
+
+            match self.previous.files.get(&filename) {
+                Some(cached) if cached.hash == hash => {
+                    cached.restore(&filename, &mut analysis);
+                }
+                _ => changed_files.push(path),
+            }
+
+            self.next.files.insert(
+                filename,
+                CachedFile {
+                    hash,
+                    ..Default::default()
+                },
+            );
+        }
+
+        Ok((changed_files, analysis))
+    }
+
+    /// Removes the file with the given name from the cache that will be written, so that it
+    /// is analyzed again by the next run, e.g. after its parsing timed out.
+    pub fn forget(&mut self, filename: &str) {
+        self.next.files.remove(filename);
+    }
+
+    /// Adds the given definition to the cache that will be written.
+    pub fn record_definition(&mut self, def: &Definition) {
+        let file = self
+            .next
+            .files
+            .entry(def.location.file_path.clone())
+            .or_default();
+        self.def_indices
+            .insert(def.location.clone(), file.definitions.len());
+        file.definitions.push(CachedDefinition {
+            range: CachedRange::from(def.location.range),
+            node_name: def.node_name.to_string(),
+            comment: def.comment.clone(),
+            scope: match def.kind {
+                DefinitionScope::Exported => None,
+                DefinitionScope::Local(scope) => Some(CachedRange::from(scope)),
+            },
+            is_declaration: def.is_declaration,
+            aliased_name: def.aliased_name.as_ref().map(SmolStr::to_string),
+            full_range: def
+                .full_range
+                .map(|full_range| (CachedRange::from(full_range.range), full_range.kind.0)),
+        });
+    }
+
+    /// Adds the given reference to the cache that will be written. The definition of the
+    /// reference, if any, must have been recorded before.
+    pub fn record_reference(&mut self, r: &Reference) {
+        let def = r
+            .def
+            .as_ref()
+            .and_then(|def| self.def_indices.get(&def.location))
+            .copied();
+        let file = self
+            .next
+            .files
+            .entry(r.location.file_path.clone())
+            .or_default();
+        file.references.push(CachedReference {
+            range: CachedRange::from(r.location.range),
+            node_name: r.node_name.to_string(),
+            def,
+            access: r.access,
+            import: r
+                .import
+                .as_ref()
+                .map(|import| (import.module.to_string(), import.name.to_string())),
+        });
+    }
+
+    /// Adds the given syntax error to the cache that will be written.
+    pub fn record_syntax_error(&mut self, error: &SyntaxError) {
+        let file = self
+            .next
+            .files
+            .entry(error.location.file_path.clone())
+            .or_default();
+        file.syntax_errors.push(CachedDiagnostic {
+            range: CachedRange::from(error.location.range),
+            message: error.message.clone(),
+        });
+    }
+
+    /// Adds the given todo comment to the cache that will be written.
+    pub fn record_todo_comment(&mut self, todo: &TodoComment) {
+        let file = self
+            .next
+            .files
+            .entry(todo.location.file_path.clone())
+            .or_default();
+        file.todo_comments.push(CachedDiagnostic {
+            range: CachedRange::from(todo.location.range),
+            message: todo.message.clone(),
+        });
+    }
+
+    /// Writes the recorded analysis results to the cache file.
+    pub fn save(&self) -> Result<()> {
+        let content = serde_json::to_vec(&self.next)?;
+        std::fs::write(&self.path, content)
+            .with_context(|| format!("Could not write the cache file {:?}", self.path))
+    }
+}
+
+impl CachedFile {
+    /// Adds the cached definitions, references and syntax errors of this file to the given
+    /// analysis.
+    fn restore(&self, filename: &str, analysis: &mut CachedAnalysis) {
+        let location = |range: CachedRange| Location {
+            file_path: filename.to_string(),
+            range: range.into(),
+        };
+
+        let definitions: Vec<Arc<Definition>> = self
+            .definitions
+            .iter()
+            .map(|d| {
+                Arc::new(Definition {
+                    location: location(d.range),
+                    node_name: SmolStr::new(&d.node_name),
+                    comment: d.comment.clone(),
+                    kind: d.scope.map_or(DefinitionScope::Exported, |s| {
+                        DefinitionScope::Local(s.into())
+                    }),
+                    is_declaration: d.is_declaration,
+                    aliased_name: d.aliased_name.as_deref().map(SmolStr::new),
+                    full_range: d.full_range.map(|(range, kind)| FullRange {
+                        range: range.into(),
+                        kind: SymbolKind(kind),
+                    }),
+                })
+            })
+            .collect();
+
+        analysis
+            .references
+            .extend(self.references.iter().map(|r| Reference {
+                location: location(r.range),
+                node_name: SmolStr::new(&r.node_name),
+                def: r.def.map(|i| Arc::clone(&definitions[i])),
+                access: r.access,
+                import: r.import.as_ref().map(|(module, name)| {
+                    Arc::new(Import {
+                        module: SmolStr::new(module),
+                        name: SmolStr::new(name),
+                    })
+                }),
+            }));
+        analysis.definitions.extend(definitions);
+        analysis
+            .syntax_errors
+            .extend(self.syntax_errors.iter().map(|e| SyntaxError {
+                location: location(e.range),
+                message: e.message.clone(),
+            }));
+        analysis
+            .todo_comments
+            .extend(self.todo_comments.iter().map(|todo| TodoComment {
+                location: location(todo.range),
+                message: todo.message.clone(),
+            }));
+    }
+}
+
+impl From<Range> for CachedRange {
+    fn from(range: Range) -> Self {
+        Self {
+            start_byte: range.start_byte,
+            end_byte: range.end_byte,
+            start: (range.start_point.row, range.start_point.column),
+            end: (range.end_point.row, range.end_point.column),
+        }
+    }
+}
+
+impl From<CachedRange> for Range {
+    fn from(range: CachedRange) -> Self {
+        Self {
+            start_byte: range.start_byte,
+            end_byte: range.end_byte,
+            start_point: Point::new(range.start.0, range.start.1),
+            end_point: Point::new(range.end.0, range.end.1),
+        }
+    }
+}
+
+fn hash_content(content: &[u8]) -> u64 {
+    stable_hash(&[content])
+}
+
+/// Returns the 64-bit FNV-1a hash of the given byte strings, each followed by a zero byte
+/// so that moving bytes between them changes the hash. Unlike the hash of `DefaultHasher`,
+/// it does not change between builds, so it can be written to the cache file.
+fn stable_hash(parts: &[&[u8]]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+    parts
+        .iter()
+        .flat_map(|part| part.iter().chain(&[0]))
+        .fold(OFFSET_BASIS, |hash, byte| {
+            (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+        })
+}
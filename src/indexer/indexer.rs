@@ -1,29 +1,56 @@
 use std::{
-    collections::HashMap,
-    path::PathBuf,
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet},
+    io::Read,
+    path::{Path, PathBuf},
     sync::{mpsc::channel, Arc},
+    time::Instant,
 };
 
-use anyhow::Result;
-use ignore::{DirEntry, Walk};
+use anyhow::{anyhow, bail, Context, Result};
+use ignore::{
+    overrides::{Override, OverrideBuilder},
+    WalkBuilder,
+};
 use indicatif::{ProgressBar, ProgressStyle};
 use languageserver_types::{NumberOrString, Url};
-use rayon::prelude::*;
-use tree_sitter::{Parser, Query, Tree};
+use log::{debug, info, trace, warn};
+use rayon::{prelude::*, ThreadPool, ThreadPoolBuilder};
+use smol_str::SmolStr;
+use tree_sitter::{Query, Tree};
 
 use crate::{
     analyzer::{
-        analyzer::{Analyzer, Definition, DefinitionScope, Reference},
-        ffi::{parser_for_language, query_for_language, ts_language_from},
-        file_utils::read_file,
+        analyzer::{
+            Analyzer, Definition, DefinitionScope, Location, Reference, FULL_CAPTURE, NAME_CAPTURE,
+            ORIGINAL_CAPTURE, PATTERN_CAPTURES, RECEIVER_CAPTURE, SCOPE_BODY_CAPTURE,
+            SOURCE_CAPTURE,
+        },
+        diagnostics::{syntax_errors, SyntaxError, TodoComment},
+        embedded::{is_single_file_component, script_ranges},
+        ffi::{
+            parser_for_language, query_for_language, query_source_for_language, ts_language_from,
+        },
+        file_utils::{implementation_path_of, read_file},
         lsif_data_cache::{DefinitionInfo, LsifDataCache},
     },
-    cli::Opts,
+    cli::{LanguageSelection, Opts},
     edge,
     emitter::emitter::Emitter,
+    error::LsifError,
+    indexer::{
+        git::{changed_files, GitTree},
+        incremental::{analysis_fingerprint, CachedAnalysis, IncrementalCache},
+        package::Package,
+        stats::{IndexStats, IndexSummary},
+        timings::{self, FileTiming},
+        unresolved,
+    },
     protocol::types::{
-        Contents, DefinitionResult, Document, Edge, EdgeData, HoverResult, LSIFMarkedString,
-        Language, MetaData, Moniker, ReferenceResult, ResultSet, ToolInfo, ID,
+        Contents, DeclarationResult, DefinitionResult, Diagnostic, DiagnosticResult, Document,
+        Edge, EdgeData, HoverResult, ImplementationResult, LSIFMarkedString, Language, MetaData,
+        Moniker, PackageInformation, Project, Range, RangeVertex, ReferenceResult, ResultSet,
+        ToolInfo, AUTO_LANGUAGES, ID,
     },
 };
 
@@ -39,7 +66,30 @@ where
 
     cache: LsifDataCache,
 
-    cached_file_paths: Option<Vec<PathBuf>>,
+    cached_file_paths: Option<BTreeMap<Language, Vec<PathBuf>>>,
+    /// The revision that the files are read from, if it is not the working tree.
+    git_tree: Option<GitTree>,
+    /// The files that were added or changed since the revision of `--since`, if any. Only
+    /// their documents are emitted, the other files are only analyzed.
+    changed_files: Option<HashSet<PathBuf>>,
+    /// Location -> Exported definition of a file that is analyzed but not emitted
+    unchanged_exports: HashMap<Location, UnchangedExport>,
+
+    /// The package of the project, if it has a `package.json` or a `pom.xml`.
+    package: Option<Package>,
+    /// Package name -> Package information vertex ID
+    package_ids: HashMap<String, ID>,
+    /// (Dependency name, if known, Identifier) -> ID of the result set of the import moniker
+    import_result_sets: HashMap<(Option<String>, SmolStr), ID>,
+
+    stats: IndexStats,
+    /// The references for which no definition was found, only kept if they are reported.
+    unresolved: Vec<Reference>,
+    /// The time taken by each analyzed file, written if the options ask for it.
+    timings: Vec<FileTiming>,
+    /// The threads that parse and analyze the files if `--threads` is given, the global
+    /// pool of rayon otherwise.
+    thread_pool: Option<ThreadPool>,
 }
 
 impl<E> Indexer<E>
@@ -47,34 +97,162 @@ where
     E: Emitter,
 {
     /// Generates an LSIF dump from a project by traversing through files of the given language
-    /// and emitting the LSIF equivalent using the given emitter.
-    pub fn index(opt: Opts, emitter: E) -> Result<()> {
+    /// and emitting the LSIF equivalent using the given emitter. Returns what was indexed, or
+    /// an error that tells what failed, e.g. `LsifError::Query` for an invalid custom query.
+    ///
+    /// ```
+    /// use zas_lsif_tools::{
+    ///     cli::Opts,
+    ///     emitter::file_emitter::{wait_until_flushed, FileEmitter, OutputFormat},
+    ///     indexer::indexer::Indexer,
+    /// };
+    ///
+    /// # let root = std::env::temp_dir().join(format!("lsif-os-doctest-{}", std::process::id()));
+    /// # std::fs::create_dir_all(&root)?;
+    /// std::fs::write(root.join("index.ts"), "let x = 1;\nconsole.log(x);\n")?;
+    /// let mut opt = Opts::from_config_and_args(&["zas-lsif-tools", root.to_str().unwrap()])?;
+    /// opt.canonicalize_paths()?;
+    ///
+    /// let (emitter, signal) = FileEmitter::with_writer(std::io::sink(), OutputFormat::Ndjson);
+    /// let summary = Indexer::index(opt, emitter)?;
+    /// wait_until_flushed(&signal)?;
+    /// assert_eq!(summary.documents, 1);
+    /// assert_eq!(summary.definitions, 1);
+    /// // `x`, `console` and `log`
+    /// assert_eq!(summary.references, 3);
+    /// // `console` and `log` are not defined in the project
+    /// assert_eq!(summary.unresolved_references, 2);
+    /// # std::fs::remove_dir_all(&root)?;
+    /// # Ok::<(), anyhow::Error>(())
+    /// ```
+    pub fn index(opt: Opts, emitter: E) -> Result<IndexSummary, LsifError> {
+        Self::index_with_tool_info(opt, emitter, ToolInfo::default())
+    }
+
+    /// Same as `index`, but the metadata of the dump names the given tool instead of
+    /// lsif-os.
+    pub fn index_with_tool_info(
+        opt: Opts,
+        emitter: E,
+        tool_info: ToolInfo,
+    ) -> Result<IndexSummary, LsifError> {
+        let start = Instant::now();
+        let stats = Self::run(opt, emitter, tool_info)?;
+        Ok(IndexSummary::new(&stats, start.elapsed()))
+    }
+
+    /// Same as `index`, but returns all the counts of what was indexed.
+    pub fn index_with_stats(opt: Opts, emitter: E) -> Result<IndexStats, LsifError> {
+        Ok(Self::run(opt, emitter, ToolInfo::default())?)
+    }
+
+    fn run(opt: Opts, emitter: E, tool_info: ToolInfo) -> Result<IndexStats> {
+        let git_tree = match &opt.git_ref {
+            Some(git_ref) => Some(GitTree::open(&opt.project_root, git_ref)?),
+            None => None,
+        };
         let mut indexer = Self {
             emitter,
-            tool_info: ToolInfo::default(),
+            tool_info: ToolInfo {
+                args: tool_info
+                    .args
+                    .or_else(|| Some(opt.args.clone()).filter(|args| !args.is_empty())),
+                ..tool_info
+            },
             opt: opt.clone(),
             project_id: 0,
             cache: LsifDataCache::default(),
             cached_file_paths: Default::default(),
+            stats: IndexStats::default(),
+            unresolved: Vec::new(),
+            timings: Vec::new(),
+            package: Package::read(&opt.project_root, git_tree.as_ref())?,
+            package_ids: HashMap::new(),
+            import_result_sets: HashMap::new(),
+            git_tree,
+            changed_files: changed_files_of(&opt)?,
+            unchanged_exports: HashMap::new(),
+            thread_pool: opt
+                .threads
+                .map(|threads| ThreadPoolBuilder::new().num_threads(threads).build())
+                .transpose()
+                .context("Could not start the threads")?,
         };
 
-        indexer.emit_metadata_and_project_vertex();
-        indexer.emit_documents();
-        {
-            let query = query_for_language(&opt.language)?;
-            let files = indexer.file_paths();
-            let files = parse_files(&opt.language, files)?;
-            indexer.emit_definitions(files, &query);
+        indexer.emit_metadata_and_project_vertex()?;
+        if indexer.files_by_language()?.is_empty() {
+            warn!(
+                "No matching files found in {}, only the metadata and the project are emitted",
+                opt.project_root.display()
+            );
+        }
+        indexer.emit_documents()?;
+        // The cache is for a single language, `validate` rejects it with the auto language
+        let mut incremental_cache = match (&opt.incremental, opt.language) {
+            (Some(path), LanguageSelection::Single(language)) => {
+                let query_src = query_source_for_language(&language, opt.query_dir.as_deref())?;
+                let fingerprint = analysis_fingerprint(&opt, &query_src);
+                Some(IncrementalCache::load(path, language, fingerprint))
+            }
+            _ => None,
+        };
+        for (language, files) in indexer.files_by_language()? {
+            let query_src = query_source_for_language(&language, opt.query_dir.as_deref())?;
+            let query = query_for_language(&language, &query_src)?;
+            let mut capture_names = get_capture_names(&query, &query_src)?;
+            if opt.definitions_only {
+                // The matches of the patterns without a capture name are skipped
+                for name in &mut capture_names {
+                    if name
+                        .as_ref()
+                        .is_some_and(|name| name.starts_with("reference"))
+                    {
+                        *name = None;
+                    }
+                }
+            }
+            let (files, cached_analysis) = match &mut incremental_cache {
+                Some(incremental_cache) => {
+                    let git_tree = indexer.git_tree.as_ref();
+                    incremental_cache.partition(files, |path| read_source(path, git_tree))?
+                }
+                None => (files, CachedAnalysis::default()),
+            };
+            indexer.emit_definitions(
+                language,
+                files,
+                cached_analysis,
+                &query,
+                &capture_names,
+                incremental_cache.as_mut(),
+            )?;
+        }
+        if !opt.definitions_only {
+            indexer.link_reference_results_to_ranges();
         }
-        indexer.link_reference_results_to_ranges();
         indexer.emit_contains();
 
         indexer.emitter.end();
 
-        Ok(())
+        if let Some(incremental_cache) = incremental_cache {
+            incremental_cache.save()?;
+        }
+
+        if let Some(path) = &opt.report_unresolved {
+            unresolved::write_report(path, &indexer.unresolved)?;
+        }
+        if let Some(path) = &opt.timings {
+            timings::write_report(path, &mut indexer.timings)?;
+        }
+
+        Ok(indexer.stats)
     }
 
     /// Emits the contains relationship for all documents and the ranges that they contain.
+    ///
+    /// Documents without ranges have no contains edge, as an edge needs at least one
+    /// in-vertex, but they are still contained in the project: they are documents of the
+    /// project, and they can have results of their own, e.g. diagnostics.
     fn emit_contains(&mut self) {
         let documents = self.cache.get_documents();
         for d in documents {
@@ -86,11 +264,13 @@ where
         self.emit_contains_for_project();
     }
 
-    /// Emits a contains edge between a document and its ranges.
+    /// Emits a contains edge between the project and its documents, if it has any.
     fn emit_contains_for_project(&mut self) {
-        let document_ids = self.cache.get_documents().map(|d| d.id).collect();
-        self.emitter
-            .emit_edge(Edge::contains(self.project_id, document_ids));
+        let document_ids: Vec<ID> = self.cache.get_documents().map(|d| d.id).collect();
+        if !document_ids.is_empty() {
+            self.emitter
+                .emit_edge(Edge::contains(self.project_id, document_ids));
+        }
     }
 
     /// Emits item relations for each indexed definition result value.
@@ -112,76 +292,423 @@ where
                 d.document_id,
             ));
 
-            for (document_id, range_ids) in &d.reference_range_ids {
+            for ((document_id, access), range_ids) in &d.reference_range_ids {
                 emitter.emit_edge(Edge::ref_item(
                     ref_result_id,
                     range_ids.clone(),
                     *document_id,
+                    *access,
                 ));
             }
         }
     }
 
-    fn emit_definitions(&mut self, files: HashMap<String, ParseResult>, query: &Query) {
+    /// Analyzes the given files and emits data for the definitions and references found,
+    /// along with the given analysis results of the files that did not need to be analyzed.
+    ///
+    /// Files are parsed and analyzed in parallel, and the syntax tree and content of a file
+    /// are dropped as soon as its analysis is done, so only the definitions and references
+    /// of all the files are kept in memory at the same time.
+    ///
+    /// If an incremental cache is given, the analysis results of all the files are recorded
+    /// in it.
+    ///
+    /// The files must be of the given language. References are only resolved to the
+    /// definitions of files of the same language.
+    ///
+    /// Returns an error, without emitting anything, if one of the files cannot be read.
+    fn emit_definitions(
+        &mut self,
+        language: Language,
+        files: Vec<PathBuf>,
+        cached_analysis: CachedAnalysis,
+        query: &Query,
+        capture_names: &[Option<String>],
+        mut incremental_cache: Option<&mut IncrementalCache>,
+    ) -> Result<()> {
         let (def_sender, def_receiver) = channel();
         let (ref_sender, ref_receiver) = channel();
+        let (error_sender, error_receiver) = channel();
+        let (todo_sender, todo_receiver) = channel();
+        let (timeout_sender, timeout_receiver) = channel();
+        let (timing_sender, timing_receiver) = channel();
+
+        for def in cached_analysis.definitions {
+            def_sender.send(def).unwrap();
+        }
+        for r in cached_analysis.references {
+            ref_sender.send(r).unwrap();
+        }
+        for error in cached_analysis.syntax_errors {
+            error_sender.send(error).unwrap();
+        }
+        for todo in cached_analysis.todo_comments {
+            todo_sender.send(todo).unwrap();
+        }
 
-        let capture_names = get_capture_names(&query, self.opt.language.get_query_source());
+        let bar = self.progress_bar(files.len(), "files parsed");
+        let lang = ts_language_from(&language)?;
+        let no_diagnostics = self.opt.no_diagnostics;
+        let todo_markers: &[String] = if self.opt.todo_comments && !no_diagnostics {
+            &self.opt.todo_markers
+        } else {
+            &[]
+        };
+        let git_tree = self.git_tree.as_ref();
+        let parse_timeout_ms = self.opt.parse_timeout_ms;
+        let test_files = if self.opt.treat_tests_as_local {
+            Some(test_files_of(&self.opt)?)
+        } else {
+            None
+        };
+        in_thread_pool(self.thread_pool.as_ref(), || {
+            files.into_par_iter().try_for_each_with(
+                (
+                    def_sender,
+                    ref_sender,
+                    error_sender,
+                    todo_sender,
+                    timeout_sender,
+                    timing_sender,
+                ),
+                |(d, r, e, todo, t, timing), path| -> Result<()> {
+                    let start = Instant::now();
+                    // The pattern is exhaustive so that nothing else, like the parser, is kept
+                    // alive with the tree.
+                    let ParseResult { tree, file_content } =
+                        match parse_file(lang, &path, git_tree, parse_timeout_ms)? {
+                            Some(result) => result,
+                            None => {
+                                warn!(
+                                    "Skipping {}: parsing took longer than {}ms",
+                                    path.display(),
+                                    parse_timeout_ms.unwrap_or_default()
+                                );
+                                t.send(path).unwrap();
+                                bar.inc(1);
+                                return Ok(());
+                            }
+                        };
+                    let parsed = Instant::now();
+                    let filename = path.to_str().unwrap().to_string();
+                    if !no_diagnostics {
+                        for error in syntax_errors(&filename, &tree, &file_content) {
+                            e.send(error).unwrap();
+                        }
+                    }
+                    let todo_comments = Analyzer::run_analysis(
+                        filename.clone(),
+                        &tree,
+                        query,
+                        d,
+                        r,
+                        &file_content,
+                        capture_names,
+                        test_files
+                            .as_ref()
+                            .is_some_and(|globs| globs.matched(&path, false).is_whitelist()),
+                        todo_markers,
+                    );
+                    for todo_comment in todo_comments {
+                        todo.send(todo_comment).unwrap();
+                    }
+                    debug!(
+                        "{}: parsed in {:?}, analyzed in {:?}",
+                        path.display(),
+                        parsed - start,
+                        parsed.elapsed()
+                    );
+                    timing
+                        .send(FileTiming::new(filename, parsed - start, parsed.elapsed()))
+                        .unwrap();
+                    bar.inc(1);
+                    Ok(())
+                },
+            )
+        })?;
+        bar.finish_and_clear();
 
-        let bar = ProgressBar::new(files.len() as u64);
+        let definitions: Vec<Arc<Definition>> = def_receiver.into_iter().collect();
+        let references: Vec<Reference> = ref_receiver.into_iter().collect();
+        let syntax_errors: Vec<SyntaxError> = error_receiver.into_iter().collect();
+        let todo_comments: Vec<TodoComment> = todo_receiver.into_iter().collect();
+        let mut timed_out_files: Vec<PathBuf> = timeout_receiver.into_iter().collect();
+        self.timings.extend(timing_receiver);
+        timed_out_files.sort();
+
+        if let Some(incremental_cache) = &mut incremental_cache {
+            for path in &timed_out_files {
+                incremental_cache.forget(path.to_str().unwrap());
+            }
+            for def in &definitions {
+                incremental_cache.record_definition(def);
+            }
+            for r in &references {
+                incremental_cache.record_reference(r);
+            }
+            for error in &syntax_errors {
+                incremental_cache.record_syntax_error(error);
+            }
+            for todo in &todo_comments {
+                incremental_cache.record_todo_comment(todo);
+            }
+        }
+
+        self.stats.timed_out_files.extend(timed_out_files);
+        self.cache.clear_exported_definitions();
+        self.index_definitions(language, definitions);
+        if !self.opt.definitions_only {
+            self.index_references(references);
+        }
+        self.emit_diagnostics(syntax_errors, todo_comments);
+        Ok(())
+    }
+
+    /// Emits a diagnostic result with the given syntax errors and todo comments for each
+    /// document that has any. The documents are emitted in the order of their paths, and
+    /// their diagnostics in source order, so that the output does not depend on the order of
+    /// the analysis.
+    fn emit_diagnostics(&mut self, syntax_errors: Vec<SyntaxError>, todos: Vec<TodoComment>) {
+        // Filename -> (Start byte, Diagnostic)
+        let mut diagnostics_by_file: BTreeMap<String, Vec<(usize, Diagnostic)>> = BTreeMap::new();
+        let diagnostics = syntax_errors
+            .iter()
+            .map(|error| (&error.location, error.diagnostic()))
+            .chain(todos.iter().map(|todo| (&todo.location, todo.diagnostic())));
+        for (location, diagnostic) in diagnostics {
+            diagnostics_by_file
+                .entry(location.file_path.clone())
+                .or_default()
+                .push((location.range.start_byte, diagnostic));
+        }
+
+        for (filename, mut diagnostics) in diagnostics_by_file {
+            let document_id = match self.cache.get_document_id(&filename) {
+                Some(document_id) => document_id,
+                None => continue,
+            };
+            diagnostics.sort_by_key(|(start_byte, _)| *start_byte);
+            let result = diagnostics
+                .into_iter()
+                .map(|(_, diagnostic)| diagnostic)
+                .collect();
+            let result_id = self.emitter.emit_vertex(DiagnosticResult { result });
+            self.emitter
+                .emit_edge(edge!(Diagnostic, document_id -> result_id));
+        }
+    }
+
+    /// Returns a progress bar of a step with the given number of items, or a hidden one if
+    /// `quiet` is set or if `verbose` is set, as the logged messages would be drawn over it.
+    /// Steps clear their bar when they are done, so that only one bar is drawn at a time.
+    fn progress_bar(&self, len: usize, items: &str) -> ProgressBar {
+        if self.opt.quiet || self.opt.verbose > 0 {
+            return ProgressBar::hidden();
+        }
+
+        let bar = ProgressBar::new(len as u64);
         bar.set_style(
             ProgressStyle::default_bar()
-                .template("{bar:40.cyan/blue} {pos}/{len} files indexed")
+                .template(&format!("{{bar:40.cyan/blue}} {{pos}}/{{len}} {}", items))
                 .progress_chars("==>"),
         );
-        files.into_par_iter().for_each_with(
-            (def_sender, ref_sender),
-            |(d, r),
-             (
-                filename,
-                ParseResult {
-                    tree, file_content, ..
-                },
-            )| {
-                Analyzer::run_analysis(filename, &tree, query, d, r, &file_content, &capture_names);
-                bar.inc(1);
-            },
-        );
+        bar
+    }
 
-        for def in def_receiver {
-            self.index_definition(def);
-        }
+    /// Emits data for the given definitions and caches them for emitting 'contains' later.
+    ///
+    /// The vertices of the definitions are built in parallel, but they are emitted on the
+    /// current thread because the emitter assigns identifiers sequentially.
+    fn index_definitions(&mut self, language: Language, defs: Vec<Arc<Definition>>) {
+        let opt = &self.opt;
+        let package = self.package.as_ref();
+        let vertices: Vec<DefinitionVertices> = in_thread_pool(self.thread_pool.as_ref(), || {
+            defs.par_iter()
+                .map(|def| DefinitionVertices::new(def, language, opt, package))
+                .collect()
+        });
 
-        for r in ref_receiver {
-            self.index_reference(r);
+        let bar = self.progress_bar(defs.len(), "definitions emitted");
+        for (def, vertices) in defs.iter().cloned().zip(vertices) {
+            self.index_definition(def, vertices);
+            bar.inc(1);
         }
         bar.finish_and_clear();
+
+        self.link_declarations_to_implementations(&defs);
+        self.link_aliases_to_originals(&defs);
     }
 
-    /// Emits data for the given reference object and caches it for emitting 'contains' later.
-    fn index_reference(&mut self, r: Reference) {
-        match &r.def {
-            Some(def) => self.index_reference_to_definition(&def, &r),
-            None => {
-                if let Some(def) = self.cache.defs_with_name(&r.node_name).map(Arc::clone) {
-                    self.index_reference_to_definition(&def, &r);
-                } else {
+    /// Emits `refersTo` edges from the ranges of aliases to the result sets of the exported
+    /// definitions that they are aliases of, e.g. from `bar` in `import { foo as bar }` to
+    /// `foo`. Aliases of symbols that are not defined in the project are skipped, and so are
+    /// the aliases and the symbols of files that are not emitted, see `--since`. The
+    /// definitions must have been indexed before.
+    fn link_aliases_to_originals(&mut self, defs: &[Arc<Definition>]) {
+        for alias in defs {
+            let original = match alias
+                .aliased_name
+                .as_ref()
+                .and_then(|name| self.cache.defs_with_name(name))
+            {
+                Some(original) => original,
+                None => continue,
+            };
+
+            let (original_result_set_id, alias_range_id) = match (
+                self.cache.get_definition_info(&original.location),
+                self.cache.get_definition_info(&alias.location),
+            ) {
+                (Some(original_info), Some(alias_info)) => {
+                    (original_info.result_set_id, alias_info.range_id)
+                }
+                _ => continue,
+            };
+            self.emitter
+                .emit_edge(edge!(RefersTo, alias_range_id -> original_result_set_id));
+        }
+    }
+
+    /// Emits implementation results that link the exported declarations of `.d.ts` files
+    /// to the exported definitions with the same name in the corresponding `.ts` files,
+    /// e.g. `add` in `math.d.ts` to `add` in `math.ts`, unless one of them is in a file that
+    /// is not emitted, see `--since`. The definitions must have been indexed before.
+    fn link_declarations_to_implementations(&mut self, defs: &[Arc<Definition>]) {
+        let implementations: HashMap<(&str, &SmolStr), &Definition> = defs
+            .iter()
+            .filter(|d| !d.is_declaration && d.kind == DefinitionScope::Exported)
+            .map(|d| ((d.location.file_path.as_str(), &d.node_name), &**d))
+            .collect();
+
+        for decl in defs
+            .iter()
+            .filter(|d| d.is_declaration && d.kind == DefinitionScope::Exported)
+        {
+            let implementation =
+                match implementation_path_of(&decl.location.file_path).and_then(|path| {
+                    implementations
+                        .get(&(path.as_str(), &decl.node_name))
+                        .copied()
+                }) {
+                    Some(implementation) => implementation,
+                    None => continue,
+                };
+
+            let (decl_result_set_id, impl_range_id, impl_document_id) = match (
+                self.cache.get_definition_info(&decl.location),
+                self.cache.get_definition_info(&implementation.location),
+            ) {
+                (Some(decl_info), Some(impl_info)) => (
+                    decl_info.result_set_id,
+                    impl_info.range_id,
+                    impl_info.document_id,
+                ),
+                _ => continue,
+            };
+
+            let impl_result_id = self.emitter.emit_vertex(ImplementationResult {});
+            self.emitter
+                .emit_edge(edge!(Implementation, decl_result_set_id -> impl_result_id));
+            self.emitter.emit_edge(Edge::item(
+                impl_result_id,
+                vec![impl_range_id],
+                impl_document_id,
+            ));
+        }
+    }
+
+    /// Emits data for the given references and caches them for emitting 'contains' later.
+    ///
+    /// All the definitions must have been indexed before. From then on, resolving a
+    /// reference only reads the cache, so the references are resolved to the result sets
+    /// of their definitions in parallel. The ranges and edges are then emitted on the
+    /// current thread, in the order of the given references. References that could not be
+    /// resolved are counted, and kept for the report if one was requested.
+    ///
+    /// The references of the files that are not emitted, see `--since`, are skipped.
+    fn index_references(&mut self, refs: Vec<Reference>) {
+        let cache = &self.cache;
+        let unchanged_exports = &self.unchanged_exports;
+        let max_import_depth = self.opt.max_import_depth;
+        let thread_pool = self.thread_pool.as_ref();
+        let resolved: Vec<Result<_, Reference>> = in_thread_pool(thread_pool, || {
+            refs.into_par_iter()
+                .filter(|r| cache.get_document_id(&r.location.file_path).is_some())
+                .map(|r| {
                     // TODO: Find the definition which might be a dependency
+                    let def = match &r.def {
+                        Some(def) => Arc::clone(def),
+                        None => match cache.defs_with_name(&r.node_name) {
+                            Some(def) => Arc::clone(def),
+                            None => return Err(r),
+                        },
+                    };
+                    let def = match follow_reexports(cache, def, max_import_depth) {
+                        Some(def) => def,
+                        None => return Err(r),
+                    };
+                    match cache.get_definition_info(&def.location) {
+                        Some(def_info) => Ok((r, def, Some(def_info.result_set_id))),
+                        None if unchanged_exports.contains_key(&def.location) => Ok((r, def, None)),
+                        None => Err(r),
+                    }
+                })
+                .collect()
+        });
+
+        let bar = self.progress_bar(resolved.len(), "references emitted");
+        for result in resolved {
+            bar.inc(1);
+            match result {
+                // The name of a definition is also captured as a reference to it, e.g. by
+                // `(identifier) @reference`, and its range is already the range of the
+                // definition
+                Ok((r, def, _)) if def.location == r.location => {}
+                Ok((r, def, Some(result_set_id))) => {
+                    self.stats.references += 1;
+                    self.index_reference_to_definition(&def, &r, result_set_id)
+                }
+                Ok((r, def, None)) => {
+                    self.stats.references += 1;
+                    self.index_reference_to_unchanged_export(&r, &def)
+                }
+                Err(r) => {
+                    self.stats.references += 1;
+                    self.stats.unresolved_references += 1;
+                    trace!(
+                        "Unresolved reference {} at {}:{}:{}",
+                        r.node_name,
+                        r.location.file_path,
+                        r.location.range.start_point.row + 1,
+                        r.location.range.start_point.column + 1
+                    );
+                    let dependency = self.package.as_ref().zip(r.import.as_ref()).and_then(
+                        |(package, import)| {
+                            let (name, version) = package.dependency_of(&import.module)?;
+                            Some((name.clone(), version.clone()))
+                        },
+                    );
+                    if dependency.is_some() || self.opt.emit_external_monikers {
+                        self.index_import(&r, dependency);
+                    }
+                    if self.opt.report_unresolved.is_some() {
+                        self.unresolved.push(r);
+                    }
                 }
             }
         }
+        bar.finish_and_clear();
     }
 
-    /// Returns a range identifier for the given reference. If a range for the object has
-    /// not been emitted, a new vertex is created.
+    /// Returns a range identifier for the given reference. If a range for its span has not
+    /// been emitted, neither for a definition nor for a reference, a new vertex is created.
     fn ensure_range_for(&mut self, r: &Reference) -> ID {
-        match self
-            .cache
-            .get_range_id(&r.location.file_path, r.location.range.start_byte)
-        {
+        match self.cache.get_range_id(&r.location) {
             Some(range_id) => range_id,
             None => {
-                let range_id = self.emitter.emit_vertex(r.range());
+                let range = range_vertex(r.range(), &r.location, self.opt.emit_byte_offsets);
+                let range_id = self.emitter.emit_vertex(range);
+                self.stats.ranges += 1;
                 self.cache.cache_reference_range(r, range_id);
                 range_id
             }
@@ -189,70 +716,211 @@ where
     }
 
     /// Emits data for the given reference object that is defined within
-    /// an index target package.
-    fn index_reference_to_definition(&mut self, def: &Definition, r: &Reference) {
+    /// an index target package. `def_result_set_id` is the identifier of the result set
+    /// of the given definition.
+    fn index_reference_to_definition(
+        &mut self,
+        def: &Definition,
+        r: &Reference,
+        def_result_set_id: ID,
+    ) {
         // 1. Emit/Get vertices(s)
         let range_id = self.ensure_range_for(r);
 
-        // 2. Connect the emitted vertices
-        let next_edge = {
-            let def_result_set_id = self
-                .cache
-                .get_definition_info(&def.location)
-                .unwrap()
-                .result_set_id;
-            edge!(Next, range_id -> def_result_set_id)
+        // 2. Cache the result, the range is already connected if it was cached before
+        if !self.cache.cache_reference(&def, &r, range_id) {
+            return;
+        }
+
+        // 3. Connect the emitted vertices, unless the range is the range of another
+        // definition, which is already connected to its own result set
+        if self.cache.get_definition_info(&r.location).is_none() {
+            self.emitter
+                .emit_edge(edge!(Next, range_id -> def_result_set_id));
+        }
+    }
+
+    /// Emits data for the given reference to an exported definition of a file that is not
+    /// emitted, see `--since`. The references to the definition share a result set with an
+    /// `import` moniker that has the scheme and the identifier of the moniker of the
+    /// definition, so that they can be linked to the definition in a dump of the whole
+    /// project.
+    fn index_reference_to_unchanged_export(&mut self, r: &Reference, def: &Definition) {
+        if self.cache.get_range_id(&r.location).is_some() {
+            return;
+        }
+        let range_id = self.ensure_range_for(r);
+
+        let export = &self.unchanged_exports[&def.location];
+        let result_set_id = match export.result_set_id {
+            Some(result_set_id) => result_set_id,
+            None => {
+                let moniker = Moniker {
+                    kind: "import".to_string(),
+                    ..export.moniker.clone()
+                };
+                let is_export = export.is_export;
+                let result_set_id = self.emitter.emit_vertex(ResultSet {});
+                let moniker_id = self.emitter.emit_vertex(moniker);
+                self.emitter
+                    .emit_edge(edge!(Moniker, result_set_id -> moniker_id));
+                if is_export {
+                    let package = self.package.as_ref().unwrap();
+                    let (name, version) = (package.name.clone(), package.version.clone());
+                    let package_id = self.package_information_id(&name, version);
+                    self.emitter
+                        .emit_edge(edge!(PackageInformation, moniker_id -> package_id));
+                }
+                let export = self.unchanged_exports.get_mut(&def.location).unwrap();
+                export.result_set_id = Some(result_set_id);
+                result_set_id
+            }
         };
-        self.emitter.emit_edge(next_edge);
 
-        // 3. Cache the result
-        self.cache.cache_reference(&def, &r, range_id);
+        self.emitter
+            .emit_edge(edge!(Next, range_id -> result_set_id));
+    }
+
+    /// Emits an `import` moniker for the given unresolved reference, which refers to a
+    /// symbol of the dependency with the given name and version, or of an unknown external
+    /// package if there is none. The symbol of a dependency is identified by its name in
+    /// the dependency, which is the name of the reference unless it was renamed by its
+    /// import. References to the same symbol of a dependency share a result set.
+    fn index_import(&mut self, r: &Reference, dependency: Option<(String, Option<String>)>) {
+        if self.cache.get_range_id(&r.location).is_some() {
+            return;
+        }
+        let range_id = self.ensure_range_for(r);
+
+        let key = match (dependency, &r.import) {
+            (Some((name, version)), Some(import)) => {
+                (Some(name), SmolStr::clone(&import.name), version)
+            }
+            _ => (None, SmolStr::clone(&r.node_name), None),
+        };
+        let (dependency, name, version) = key;
+        let key = (dependency, name);
+        let result_set_id = match self.import_result_sets.get(&key) {
+            Some(result_set_id) => *result_set_id,
+            None => {
+                let result_set_id = self.emitter.emit_vertex(ResultSet {});
+                // The symbols of unknown packages are only identified by their name
+                let moniker = match &key {
+                    (Some(dependency), node_name) => Moniker {
+                        kind: "import".to_string(),
+                        scheme: self.package.as_ref().unwrap().manager.scheme().to_string(),
+                        identifier: format!("{}:{}", dependency, node_name),
+                    },
+                    (None, node_name) => Moniker {
+                        kind: "import".to_string(),
+                        scheme: self.opt.moniker_scheme.clone(),
+                        identifier: node_name.to_string(),
+                    },
+                };
+                let moniker_id = self.emitter.emit_vertex(moniker);
+                self.emitter
+                    .emit_edge(edge!(Moniker, result_set_id -> moniker_id));
+                if let Some(dependency) = &key.0 {
+                    let package_id = self.package_information_id(dependency, version);
+                    self.emitter
+                        .emit_edge(edge!(PackageInformation, moniker_id -> package_id));
+                }
+                self.import_result_sets.insert(key, result_set_id);
+                result_set_id
+            }
+        };
+
+        self.emitter
+            .emit_edge(edge!(Next, range_id -> result_set_id));
+    }
+
+    /// Returns the identifier of the package information vertex of the package with the
+    /// given name, which is emitted the first time. The package has the same manager as
+    /// the indexed project.
+    fn package_information_id(&mut self, name: &str, version: Option<String>) -> ID {
+        if let Some(id) = self.package_ids.get(name) {
+            return *id;
+        }
+
+        let id = self.emitter.emit_vertex(PackageInformation {
+            name: name.to_string(),
+            manager: self.package.as_ref().unwrap().manager,
+            version,
+        });
+        self.package_ids.insert(name.to_string(), id);
+        id
+    }
+
+    /// Returns the identifier of a hover result with the contents of the given one, which is
+    /// only emitted if no definition had the same contents before, e.g. one-line signatures
+    /// like `const x = 1;` in many files.
+    fn hover_result_id(&mut self, hover_result: HoverResult) -> ID {
+        if let Some(id) = self.cache.get_hover_result_id(&hover_result.result) {
+            return id;
+        }
+
+        let contents = hover_result.result.clone();
+        let id = self.emitter.emit_vertex(hover_result);
+        self.cache.cache_hover_result(&contents, id);
+        id
     }
 
     /// Emits data for the given definition object and caches it for
     /// emitting 'contains' later.
-    fn index_definition(&mut self, def: Arc<Definition>) {
-        let document_id = self.cache.get_document_id(&def.location.file_path).unwrap();
+    fn index_definition(&mut self, def: Arc<Definition>, vertices: DefinitionVertices) {
+        let document_id = match self.cache.get_document_id(&def.location.file_path) {
+            Some(document_id) => document_id,
+            None => {
+                self.cache_unchanged_export(def, vertices);
+                return;
+            }
+        };
+        let DefinitionVertices {
+            range,
+            hover_result,
+            moniker,
+            is_export,
+        } = vertices;
 
         // 1. Emit Vertices
-        let range_id = self.emitter.emit_vertex(def.range());
+        let range_id = self.emitter.emit_vertex(range);
         let result_set_id = self.emitter.emit_vertex(ResultSet {});
-        let def_result_id = self.emitter.emit_vertex(DefinitionResult {});
-        let hover_result_id = self.emitter.emit_vertex(HoverResult {
-            result: Contents {
-                contents: vec![LSIFMarkedString {
-                    language: self.opt.language.to_string(),
-                    value: def.comment.clone(),
-                    is_raw_string: true,
-                }],
-            },
-        });
-        let moniker_id = self.emitter.emit_vertex(Moniker {
-            kind: if def.kind == DefinitionScope::Exported {
-                "exported".to_string()
-            } else {
-                "local".to_string()
-            },
-            scheme: "zas".to_string(),
-            identifier: format!("{}:{}", def.location.file_name(), def.node_name.clone()),
-        });
+        // A declaration has no definition, other definitions are their own declaration
+        let def_result_id = if def.is_declaration {
+            None
+        } else {
+            Some(self.emitter.emit_vertex(DefinitionResult {}))
+        };
+        let decl_result_id = self.emitter.emit_vertex(DeclarationResult {});
+        let hover_result_id = hover_result.map(|hover_result| self.hover_result_id(hover_result));
+        let moniker_id = self.emitter.emit_vertex(moniker);
+        let package_id = if is_export {
+            let package = self.package.as_ref().unwrap();
+            let (name, version) = (package.name.clone(), package.version.clone());
+            Some(self.package_information_id(&name, version))
+        } else {
+            None
+        };
+        self.stats.definitions += 1;
+        self.stats.ranges += 1;
 
         // 2. Connect the emitted vertices
-        let next_edge = edge!(Next, range_id -> result_set_id);
-        let definition_edge = edge!(Definition, result_set_id -> def_result_id);
-        let item_edge = Edge::item(def_result_id, vec![range_id], document_id);
-        let moniker_edge = edge!(Moniker, result_set_id -> moniker_id);
-        let hover_edge = edge!(Hover, result_set_id -> hover_result_id);
-
-        for edge in vec![
-            next_edge,
-            definition_edge,
-            item_edge,
-            moniker_edge,
-            hover_edge,
-        ]
-        .into_iter()
-        {
+        let mut edges = vec![edge!(Next, range_id -> result_set_id)];
+        if let Some(def_result_id) = def_result_id {
+            edges.push(edge!(Definition, result_set_id -> def_result_id));
+            edges.push(Edge::item(def_result_id, vec![range_id], document_id));
+        }
+        edges.push(edge!(Declaration, result_set_id -> decl_result_id));
+        edges.push(Edge::item(decl_result_id, vec![range_id], document_id));
+        edges.push(edge!(Moniker, result_set_id -> moniker_id));
+        if let Some(package_id) = package_id {
+            edges.push(edge!(PackageInformation, moniker_id -> package_id));
+        }
+        if let Some(hover_result_id) = hover_result_id {
+            edges.push(edge!(Hover, result_set_id -> hover_result_id));
+        }
+
+        for edge in edges {
             self.emitter.emit_edge(edge);
         }
 
@@ -261,128 +929,616 @@ where
             .cache_definition(&def, document_id, range_id, result_set_id);
     }
 
+    /// Keeps the given definition of a file that is analyzed but not emitted, see `--since`,
+    /// if it is exported, so that the references of the emitted files to it are resolved.
+    fn cache_unchanged_export(&mut self, def: Arc<Definition>, vertices: DefinitionVertices) {
+        if def.kind != DefinitionScope::Exported {
+            return;
+        }
+        self.cache.cache_exported_definition(&def);
+        self.unchanged_exports
+            .entry(def.location.clone())
+            .or_insert(UnchangedExport {
+                moniker: vertices.moniker,
+                is_export: vertices.is_export,
+                result_set_id: None,
+            });
+    }
+
     /// Emits a metadata and project vertex. This method caches the identifier of the project
     /// vertex, which is needed to construct the project/document contains relation later.
-    fn emit_metadata_and_project_vertex(&mut self) {
-        self.project_id = self.emitter.emit_vertex(MetaData {
-            version: "0.1".into(),
-            position_encoding: "utf-16".into(),
-            tool_info: Some(self.tool_info.clone()),
-            project_root: Url::from_directory_path(&self.opt.project_root).unwrap(),
+    ///
+    /// When appending to a dump that has entries, the metadata vertex was already emitted
+    /// by a previous run and only the project vertex is emitted.
+    ///
+    /// The language of the project is the language of the options, or the language of most
+    /// documents with the auto language.
+    fn emit_metadata_and_project_vertex(&mut self) -> Result<()> {
+        let language_id = match self.opt.language {
+            LanguageSelection::Single(language) => language,
+            LanguageSelection::Auto => self
+                .files_by_language()?
+                .into_iter()
+                .max_by_key(|(language, files)| (files.len(), Reverse(*language)))
+                .map_or(AUTO_LANGUAGES[0], |(language, _)| language),
+        };
+        if self.opt.appended_after_id == 0 {
+            self.emitter.emit_vertex(MetaData {
+                version: self.opt.lsif_version.clone(),
+                position_encoding: "utf-16".into(),
+                tool_info: Some(self.tool_info.clone()),
+                project_root: self.project_root_uri(),
+            });
+        }
+        self.project_id = self.emitter.emit_vertex(Project {
+            language_id,
+            name: self.project_name(),
         });
+        Ok(())
+    }
+
+    /// Returns the name of the project vertex, which is the name of the project root
+    /// directory unless the options give one.
+    fn project_name(&self) -> String {
+        match &self.opt.project_name {
+            Some(name) => name.clone(),
+            None => self
+                .opt
+                .project_root
+                .file_name()
+                .unwrap_or_else(|| self.opt.project_root.as_os_str())
+                .to_string_lossy()
+                .into_owned(),
+        }
+    }
+
+    /// Returns the project root of the metadata, which is `.` if document URIs are relative.
+    fn project_root_uri(&self) -> String {
+        if self.opt.relative_paths {
+            return ".".to_string();
+        }
+        Url::from_directory_path(&self.opt.project_root)
+            .unwrap()
+            .to_string()
+    }
+
+    /// Returns the URI of the document at the given path. If `relative_paths` is set, this
+    /// is the percent-encoded path relative to the project root, e.g. `src/index.ts`.
+    fn document_uri(&self, filename: &Path) -> String {
+        let uri = Url::from_file_path(filename).unwrap();
+        if !self.opt.relative_paths {
+            return uri.to_string();
+        }
+
+        let root = Url::from_directory_path(&self.opt.project_root).unwrap();
+        match uri.path().strip_prefix(root.path()) {
+            Some(relative) => relative.to_string(),
+            // Files outside of the project root, e.g. from `--files-from`, stay absolute
+            None => uri.to_string(),
+        }
     }
 
-    fn emit_documents(&mut self) {
-        self.file_paths().iter().for_each(|filename| {
+    fn emit_documents(&mut self) -> Result<()> {
+        let files = self.files_by_language()?;
+        let files = files
+            .iter()
+            .flat_map(|(language, files)| files.iter().map(move |file| (*language, file)));
+        files.for_each(|(language, filename)| {
+            if self
+                .cache
+                .get_document_id(filename.to_str().unwrap())
+                .is_some()
+                || self
+                    .changed_files
+                    .as_ref()
+                    .is_some_and(|changed_files| !changed_files.contains(filename))
+            {
+                return;
+            }
+
             let document_id = self.emitter.emit_vertex(Document {
-                uri: Url::from_file_path(&filename).unwrap(),
-                language_id: self.opt.language,
+                uri: self.document_uri(filename),
+                language_id: language.lsif_language_id(filename).to_string(),
             });
             self.cache
                 .cache_document(filename.to_str().unwrap().to_string(), document_id);
+            self.stats.documents += 1;
+            *self
+                .stats
+                .languages
+                .entry(language.to_string())
+                .or_default() += 1;
         });
+        Ok(())
     }
 
-    /// Returns a `Vec` of of paths of all the files that have the same format as this
-    /// indexer's language.
-    fn file_paths(&mut self) -> Vec<PathBuf> {
+    /// Returns the paths of all the files to analyze, see `discover_analyzed_files`, grouped
+    /// by their language. The groups are in the order of their language, and the files of a
+    /// group in the order they were discovered.
+    fn files_by_language(&mut self) -> Result<BTreeMap<Language, Vec<PathBuf>>> {
         if let Some(res) = &self.cached_file_paths {
-            return res.clone();
+            return Ok(res.clone());
         }
 
-        let exs = self.opt.language.get_extensions();
-        let res: Vec<PathBuf> = Walk::new(PathBuf::from(&self.opt.project_root))
-            .into_iter()
-            .filter_map(Result::ok)
-            .filter(move |entry| {
-                entry.metadata().unwrap().is_file() && check_extensions(entry, exs.clone())
-            })
-            .map(DirEntry::into_path)
-            .collect();
+        let mut res: BTreeMap<Language, Vec<PathBuf>> = BTreeMap::new();
+        for (path, language) in discover_analyzed_files(&self.opt)? {
+            res.entry(language).or_default().push(path);
+        }
         self.cached_file_paths = Some(res.clone());
-        res
+        Ok(res)
+    }
+}
+
+/// The vertices of a definition that do not depend on any identifier, so they can be
+/// built before the definition is emitted.
+struct DefinitionVertices {
+    range: RangeVertex,
+    /// The hover result, unless the options skip hovers.
+    hover_result: Option<HoverResult>,
+    moniker: Moniker,
+    /// Whether the moniker is an `export` moniker of the package of the project.
+    is_export: bool,
+}
+
+impl DefinitionVertices {
+    /// Creates the vertices of the given definition of a project with the given options and
+    /// package. The range has byte offsets if `emit_byte_offsets` is set.
+    ///
+    /// The moniker identifier is `<package>:<path>:<name>`, where the path is relative to
+    /// the project root, so that definitions with the same name in files with the same name
+    /// do not collide. Exported definitions of a project with a package get an `export`
+    /// moniker with the scheme of its package manager, other definitions get a moniker with
+    /// the scheme of the options (`zas` by default) where the package is the name of the
+    /// project root directory.
+    fn new(def: &Definition, language: Language, opt: &Opts, package: Option<&Package>) -> Self {
+        let project_root = &opt.project_root;
+        let is_exported = def.kind == DefinitionScope::Exported;
+        let (kind, scheme, package_name) = match package {
+            Some(package) if is_exported => {
+                ("export", package.manager.scheme(), package.name.clone())
+            }
+            _ => (
+                if is_exported { "exported" } else { "local" },
+                opt.moniker_scheme.as_str(),
+                project_root
+                    .file_name()
+                    .map_or(String::new(), |name| name.to_string_lossy().to_string()),
+            ),
+        };
+
+        Self {
+            range: RangeVertex {
+                tag: def.tag(),
+                ..range_vertex(def.range(), &def.location, opt.emit_byte_offsets)
+            },
+            hover_result: Some(HoverResult {
+                result: Contents {
+                    contents: vec![LSIFMarkedString {
+                        language: language.to_string(),
+                        value: def.comment.clone(),
+                        is_raw_string: true,
+                    }],
+                },
+            })
+            .filter(|_| !opt.no_hover),
+            moniker: Moniker {
+                kind: kind.to_string(),
+                scheme: scheme.to_string(),
+                identifier: format!(
+                    "{}:{}:{}",
+                    package_name,
+                    def.location.relative_path(project_root),
+                    def.node_name
+                ),
+            },
+            is_export: package.is_some() && is_exported,
+        }
     }
 }
 
+/// An exported definition of a file that is analyzed but not emitted, see `--since`.
+struct UnchangedExport {
+    /// The moniker that the definition has when its file is emitted.
+    moniker: Moniker,
+    /// Whether the moniker is an `export` moniker of the package of the project.
+    is_export: bool,
+    /// The result set of the references to the definition, once one is emitted.
+    result_set_id: Option<ID>,
+}
+
 /// Represents the result of parse operation on a file.
+///
+/// The parser is not part of the result, trees do not borrow it and it holds
+/// grammar state that is not needed after parsing.
 struct ParseResult {
-    parser: Parser,
     tree: Tree,
-    file_content: String,
+    file_content: Vec<u8>,
 }
 
-/// Parses the given files with the given language's parser in parallel.
-/// Returns a `HashMap` of filepath (as `String`) to `ParseResult`.
+// Does not compile if a field, e.g. the parser, is added to the result without being
+// considered here
+const _: fn(ParseResult) = |ParseResult {
+                                tree: _,
+                                file_content: _,
+                            }| {};
+
+/// Parses the given file with the given language's parser. Returns an error if the file
+/// cannot be read, or `None` if parsing takes longer than the given number of milliseconds.
 ///
-/// # Panics
-/// Panics if it fails to parse a file.
-fn parse_files(
-    lang: &Language,
-    files: Vec<PathBuf>,
-) -> anyhow::Result<HashMap<String, ParseResult>> {
-    let lang = ts_language_from(lang);
-    let parsers = files
-        .into_par_iter()
-        .map(|path| {
-            let mut parser = parser_for_language(lang).unwrap();
-            let file_content = read_file(&path).unwrap();
-            let tree = parser.parse(file_content.clone(), None).unwrap();
-            (
-                path.to_str().unwrap().to_string(),
-                ParseResult {
-                    parser,
-                    tree,
-                    file_content,
-                },
-            )
+/// Only the `<script>` blocks of single-file components are parsed, the positions of the
+/// nodes are still the ones of the whole file.
+fn parse_file(
+    lang: tree_sitter::Language,
+    path: &Path,
+    git_tree: Option<&GitTree>,
+    timeout_ms: Option<u64>,
+) -> Result<Option<ParseResult>> {
+    let mut parser = parser_for_language(lang).unwrap();
+    // A timeout of 0 disables it
+    parser.set_timeout_micros(timeout_ms.map_or(0, |ms| ms.saturating_mul(1000).max(1)));
+    let file_content = read_source(path, git_tree)?;
+    if std::str::from_utf8(&file_content).is_err() {
+        warn!(
+            "{} is not valid UTF-8, invalid bytes are replaced in the emitted text",
+            path.display()
+        );
+    }
+    if is_single_file_component(path) {
+        let ranges = script_ranges(&file_content);
+        // No included ranges means the whole file
+        if ranges.is_empty() {
+            return Ok(parser
+                .parse(b"", None)
+                .map(|tree| ParseResult { tree, file_content }));
+        }
+        parser
+            .set_included_ranges(&ranges)
+            .map_err(|_| anyhow!("Could not parse the script blocks of {:?}", path))?;
+    }
+    Ok(parser
+        .parse(&file_content, None)
+        .map(|tree| ParseResult { tree, file_content }))
+}
+
+/// Follows the re-exports from the given definition, e.g. from `b` in
+/// `export { a as b } from './a'` to the exported definition of `a`, and returns the
+/// definition at the end of the chain. A re-export of a symbol that is not exported by the
+/// project is the end of the chain. Returns `None` if the chain has more than the given
+/// number of re-exports.
+fn follow_reexports(
+    cache: &LsifDataCache,
+    mut def: Arc<Definition>,
+    max_depth: usize,
+) -> Option<Arc<Definition>> {
+    for _ in 0..=max_depth {
+        let original = match (&def.kind, &def.aliased_name) {
+            (DefinitionScope::Exported, Some(name)) => cache.defs_with_name(name),
+            _ => None,
+        };
+        match original {
+            // e.g. `export { a as a } from './a'`, if `a` is not exported elsewhere
+            Some(original) if !Arc::ptr_eq(original, &def) => def = Arc::clone(original),
+            _ => return Some(def),
+        }
+    }
+    None
+}
+
+/// Returns the vertex of the given range of the given location, with the byte offsets of the
+/// location if the given flag is set.
+fn range_vertex(range: Range, location: &Location, emit_byte_offsets: bool) -> RangeVertex {
+    let byte_offset = |offset| Some(offset).filter(|_| emit_byte_offsets);
+    RangeVertex {
+        range,
+        start_byte: byte_offset(location.range.start_byte),
+        end_byte: byte_offset(location.range.end_byte),
+        tag: None,
+    }
+}
+
+/// Reads the file at the given path from the given git tree, or from the file system if
+/// there is none.
+fn read_source(path: &Path, git_tree: Option<&GitTree>) -> Result<Vec<u8>> {
+    match git_tree {
+        Some(git_tree) => git_tree.read(path),
+        None => read_file(path),
+    }
+}
+
+/// Returns the paths of all the files that would be indexed with the given options, i.e.
+/// the files that have the format of one of the languages of the options. The files are read
+/// from the file list given in the options if any, otherwise the project root is traversed.
+///
+/// Symbolic links are only followed if the options say so. Links that form a cycle are
+/// detected by the walker and skipped, so the traversal always terminates.
+///
+/// Files larger than the maximum file size of the options, if any, are skipped, and so are
+/// the files that did not change since the revision of `--since`, if any. Each file is only
+/// returned once.
+pub fn discover_files(opt: &Opts) -> Result<Vec<PathBuf>> {
+    Ok(discover_files_with_languages(opt)?
+        .into_iter()
+        .map(|(path, _)| path)
+        .collect())
+}
+
+/// Returns the paths of all the files that would be indexed with the given options, see
+/// `discover_files`, with their language, see `language_of`.
+pub fn discover_files_with_languages(opt: &Opts) -> Result<Vec<(PathBuf, Language)>> {
+    let files = discover_analyzed_files(opt)?;
+    Ok(match changed_files_of(opt)? {
+        Some(changed_files) => files
+            .into_iter()
+            .filter(|(path, _)| changed_files.contains(path))
+            .collect(),
+        None => files,
+    })
+}
+
+/// Returns the files that were added or changed since the revision of `--since`, if any.
+fn changed_files_of(opt: &Opts) -> Result<Option<HashSet<PathBuf>>> {
+    match &opt.since {
+        Some(since) => Ok(Some(changed_files(
+            &opt.project_root,
+            since,
+            opt.git_ref.as_deref(),
+        )?)),
+        None => Ok(None),
+    }
+}
+
+/// Returns the files to analyze with the given options, see `discover_files_with_languages`,
+/// which include the files that did not change since the revision of `--since`, so that
+/// the references of the changed files are resolved against the whole project.
+fn discover_analyzed_files(opt: &Opts) -> Result<Vec<(PathBuf, Language)>> {
+    let file_len = |path: &Path| std::fs::metadata(path).map_or(0, |m| m.len());
+    let language_of = language_of(opt)?;
+    // (Path, Length in bytes, Language)
+    let res: Vec<(PathBuf, u64, Language)> = match (&opt.git_ref, &opt.files_from) {
+        (Some(git_ref), _) => GitTree::open(&opt.project_root, git_ref)?
+            .files()?
+            .into_iter()
+            .filter_map(|(path, len)| {
+                let language = language_of(&path)?;
+                Some((path, len, language))
+            })
+            .collect(),
+        (None, Some(files_from)) => read_file_list(files_from, &opt.project_root)?
+            .into_iter()
+            .filter_map(|path| {
+                let language = language_of(&path)?;
+                Some((path.clone(), file_len(&path), language))
+            })
+            .collect(),
+        (None, None) => WalkBuilder::new(&opt.project_root)
+            .follow_links(opt.follow_symlinks)
+            .hidden(!opt.include_hidden)
+            .build()
+            .filter_map(Result::ok)
+            // The metadata of broken links cannot be read
+            .filter(|entry| entry.metadata().is_ok_and(|m| m.is_file()))
+            .filter_map(|entry| {
+                let language = language_of(entry.path())?;
+                Some((entry.path().to_path_buf(), file_len(entry.path()), language))
+            })
+            .collect(),
+    };
+
+    let mut excludes = OverrideBuilder::new(&opt.project_root);
+    for glob in &opt.exclude {
+        excludes
+            .add(&format!("!{}", glob))
+            .with_context(|| format!("Invalid exclude glob {:?}", glob))?;
+    }
+    let excludes = excludes.build()?;
+
+    // The canonical paths of the files, so that a file reached through several paths, e.g.
+    // directly and through a symlinked directory with `--follow-symlinks`, is only indexed
+    // once, at the first of its paths. The files of a git revision may not exist in the
+    // working tree, their paths are kept as they are.
+    let mut seen = HashSet::new();
+    Ok(res
+        .into_iter()
+        .filter(|(path, ..)| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone())))
+        .filter(|(path, ..)| !excludes.matched(path, false).is_ignore())
+        .filter(|(path, len, _)| match opt.max_file_size {
+            Some(max_file_size) => !is_too_large(path, *len, max_file_size),
+            None => true,
         })
-        .collect();
+        .map(|(path, _, language)| (path, language))
+        .collect())
+}
 
-    Ok(parsers)
+/// Returns a function that returns the language of the file at the given path with the
+/// given options, if it is one to index: the language of the last `--language-override`
+/// glob that matches the file if any, or the language of its extension otherwise. Only the
+/// files with the extension of a supported language are overridden, so that a glob such as
+/// `flow/**` does not index the other files of the directory.
+fn language_of(opt: &Opts) -> Result<impl Fn(&Path) -> Option<Language> + '_> {
+    let overrides = opt
+        .language_override
+        .iter()
+        .map(|o| {
+            let mut globs = OverrideBuilder::new(&opt.project_root);
+            globs
+                .add(&o.glob)
+                .with_context(|| format!("Invalid language override glob {:?}", o.glob))?;
+            Ok((globs.build()?, o.language))
+        })
+        .collect::<Result<Vec<(Override, Language)>>>()?;
+
+    Ok(move |path: &Path| {
+        Language::from_extension(path.extension()?.to_str()?)?;
+        match overrides
+            .iter()
+            .rev()
+            .find(|(globs, _)| globs.matched(path, false).is_whitelist())
+        {
+            Some((_, language)) => Some(*language).filter(|l| opt.language.selects(*l)),
+            None => opt.language.language_of(path),
+        }
+    })
+}
+
+/// Runs the given operation, and its parallel iterators, in the given thread pool if any,
+/// or in the global pool of rayon otherwise.
+fn in_thread_pool<R: Send>(thread_pool: Option<&ThreadPool>, op: impl FnOnce() -> R + Send) -> R {
+    match thread_pool {
+        Some(thread_pool) => thread_pool.install(op),
+        None => op(),
+    }
+}
+
+/// The globs of the test files if the options give none.
+pub const DEFAULT_TEST_GLOBS: &[&str] = &["*.test.*", "*.spec.*"];
+
+/// Returns the globs of the test files of the given options, relative to the project root.
+fn test_files_of(opt: &Opts) -> Result<Override> {
+    let mut globs = OverrideBuilder::new(&opt.project_root);
+    if opt.test_glob.is_empty() {
+        for glob in DEFAULT_TEST_GLOBS {
+            globs.add(glob)?;
+        }
+    }
+    for glob in &opt.test_glob {
+        globs
+            .add(glob)
+            .with_context(|| format!("Invalid test glob {:?}", glob))?;
+    }
+    Ok(globs.build()?)
 }
 
-/// Returns true if the given `DirEntry` has an extension equal to one of
-/// the given extensions, and false otherwise.
-fn check_extensions(dir_entry: &DirEntry, extensions: Vec<String>) -> bool {
-    extensions.iter().any(|ex| has_extension(dir_entry, ex))
+/// Returns true, and logs that the file is skipped, if the given length of the file at
+/// the given path is larger than the given number of bytes.
+fn is_too_large(path: &Path, len: u64, max_file_size: u64) -> bool {
+    if len > max_file_size {
+        info!(
+            "Skipping {}: {} bytes is larger than the maximum file size",
+            path.display(),
+            len
+        );
+    }
+    len > max_file_size
 }
 
-/// Returns true if the given `DirEntry`'s extension is equal to the given
-/// extension.
-fn has_extension(dir_entry: &DirEntry, target_ext: &str) -> bool {
-    dir_entry
-        .path()
-        .extension()
-        .and_then(|e| e.to_str())
-        .map(|e| e == target_ext)
-        .unwrap_or(false)
+/// Reads a list of newline-separated file paths from the file at the given path, or from
+/// stdin if the path is `-`. Relative paths are relative to the given project root.
+///
+/// Returns an error if one of the listed files does not exist.
+fn read_file_list(list_path: &Path, project_root: &Path) -> Result<Vec<PathBuf>> {
+    let content = if list_path == Path::new("-") {
+        let mut content = String::new();
+        std::io::stdin()
+            .read_to_string(&mut content)
+            .context("Could not read the file list from stdin")?;
+        content
+    } else {
+        String::from_utf8_lossy(&read_file(list_path)?).into_owned()
+    };
+
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let path = project_root.join(line);
+            path.canonicalize()
+                .with_context(|| format!("Could not find the listed file {:?}", path))
+        })
+        .collect()
 }
 
-/// Returns all the capture names (names starting with '@') in the given query source in
-/// the same order they appear.
+/// Returns the capture name (names starting with '@') of each pattern in the given query
+/// source, in the same order they appear, or `None` for the patterns without a capture.
+/// The `@name`, `@receiver`, `@original` and `@scope.body` captures, that only complete the data of another
+/// capture, are not the capture name of a pattern. The other captures of a pattern are still
+/// analyzed, see `Analyzer::data_from_query_match`.
 ///
 /// This is different from `Query::capture_names` which returns a list of
 /// unique capture names.
-fn get_capture_names(query: &Query, query_src: String) -> Vec<String> {
-    let start_bytes: Vec<usize> = (0..query.pattern_count())
+///
+/// Returns an error if the capture name of a pattern is not one of `PATTERN_CAPTURES`.
+pub(crate) fn get_capture_names(query: &Query, query_src: &str) -> Result<Vec<Option<String>>> {
+    let mut start_bytes: Vec<usize> = (0..query.pattern_count())
         .map(|i| query.start_byte_for_pattern(i))
         .collect();
+    start_bytes.push(query_src.len());
 
-    let mut patterns = vec![];
-    for pat_idx in 1..=start_bytes.len() {
-        let mut query_src = query_src.clone();
-        let start_byte = start_bytes[pat_idx - 1];
-        let mut drained: String = if pat_idx != start_bytes.len() {
-            query_src.drain(start_byte..start_bytes[pat_idx]).collect()
-        } else {
-            query_src.drain(start_byte..).collect()
-        };
-        let query_start = drained.find('@').unwrap() + 1;
-        let mut drained: String = drained.drain(query_start..).collect();
-        let query_end = drained.find(|c| c == '\n' || c == ' ' || c == ')').unwrap();
-        let query_name: String = drained.drain(..query_end).collect();
-        patterns.push(query_name);
-    }
+    start_bytes
+        .windows(2)
+        .enumerate()
+        .map(|(pattern_index, bounds)| {
+            let pattern = &query_src[bounds[0]..bounds[1]];
+            let (offset, name) = match captures_of(pattern)
+                .into_iter()
+                .find(|(_, name)| {
+                    ![
+                        NAME_CAPTURE,
+                        RECEIVER_CAPTURE,
+                        ORIGINAL_CAPTURE,
+                        SCOPE_BODY_CAPTURE,
+                        FULL_CAPTURE,
+                        SOURCE_CAPTURE,
+                    ]
+                    .contains(name)
+                })
+            {
+                Some(capture) => capture,
+                None => return Ok(None),
+            };
+            if !PATTERN_CAPTURES.contains(&name) {
+                bail!(
+                    "Unknown capture '@{}' in pattern {} of the query (byte {}), expected one of: {}",
+                    name,
+                    pattern_index,
+                    bounds[0] + offset,
+                    PATTERN_CAPTURES.join(", ")
+                );
+            }
+            Ok(Some(name.to_string()))
+        })
+        .collect()
+}
 
-    patterns
+/// Returns the names of the captures in the given pattern source with their byte offsets,
+/// skipping comments and strings, e.g. the arguments of predicates.
+fn captures_of(pattern: &str) -> Vec<(usize, &str)> {
+    let mut captures = vec![];
+    let mut chars = pattern.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            ';' => {
+                for (_, c) in &mut chars {
+                    if c == '\n' {
+                        break;
+                    }
+                }
+            }
+            '"' => {
+                while let Some((_, c)) = chars.next() {
+                    match c {
+                        '\\' => {
+                            chars.next();
+                        }
+                        '"' => break,
+                        _ => {}
+                    }
+                }
+            }
+            '@' => {
+                let start = i + c.len_utf8();
+                let mut end = start;
+                while let Some(&(j, c)) = chars.peek() {
+                    if !(c.is_alphanumeric() || c == '_' || c == '-' || c == '.') {
+                        break;
+                    }
+                    end = j + c.len_utf8();
+                    chars.next();
+                }
+                captures.push((i, &pattern[start..end]));
+            }
+            _ => {}
+        }
+    }
+    captures
 }
@@ -1 +1,8 @@
+pub mod git;
+pub mod incremental;
 pub mod indexer;
+pub mod package;
+pub mod shards;
+pub mod stats;
+pub mod timings;
+pub mod unresolved;
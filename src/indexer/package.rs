@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, path::Path};
+
+use anyhow::{Context, Result};
+use serde::Deserialize;
+
+use super::git::GitTree;
+use crate::protocol::types::PackageManager;
+
+/// The package of an indexed project, as declared in its manifest at the project root.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub manager: PackageManager,
+    pub name: String,
+    pub version: Option<String>,
+    /// Name -> Version requirement
+    pub dependencies: BTreeMap<String, Option<String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PackageJson {
+    name: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    dev_dependencies: BTreeMap<String, String>,
+    #[serde(default)]
+    peer_dependencies: BTreeMap<String, String>,
+}
+
+impl Package {
+    /// Reads the package of the project with the given root from its `package.json` or
+    /// `pom.xml`, in the given git revision if any or in the working tree otherwise. Returns
+    /// `None` if the project has neither, or if its `package.json` has no name, e.g. for the
+    /// private root of a monorepo.
+    pub fn read(project_root: &Path, git_tree: Option<&GitTree>) -> Result<Option<Self>> {
+        let read_manifest = |path: &Path| -> Result<Option<String>> {
+            let content = match git_tree {
+                Some(git_tree) => git_tree.read_if_exists(path)?,
+                None if path.is_file() => Some(std::fs::read(path)?),
+                None => None,
+            };
+            content
+                .map(|content| String::from_utf8(content).context("Invalid UTF-8"))
+                .transpose()
+                .with_context(|| format!("Could not read {:?}", path))
+        };
+
+        let package_json = project_root.join("package.json");
+        if let Some(content) = read_manifest(&package_json)? {
+            return Self::from_package_json(&content)
+                .with_context(|| format!("Could not read {:?}", package_json));
+        }
+
+        let pom_xml = project_root.join("pom.xml");
+        if let Some(content) = read_manifest(&pom_xml)? {
+            return Self::from_pom_xml(&content)
+                .with_context(|| format!("Could not read {:?}", pom_xml))
+                .map(Some);
+        }
+
+        Ok(None)
+    }
+
+    fn from_package_json(content: &str) -> Result<Option<Self>> {
+        let package: PackageJson = serde_json::from_str(content)?;
+        let name = match package.name {
+            Some(name) => name,
+            None => return Ok(None),
+        };
+        let dependencies = package
+            .dependencies
+            .into_iter()
+            .chain(package.dev_dependencies)
+            .chain(package.peer_dependencies)
+            .map(|(name, version)| (name, Some(version)))
+            .collect();
+
+        Ok(Some(Self {
+            manager: PackageManager::Npm,
+            name,
+            version: package.version,
+            dependencies,
+        }))
+    }
+
+    /// Reads the coordinates and the dependencies of a `pom.xml`. This is not an XML
+    /// parser, it only looks at the elements of the project, its parent and its
+    /// dependencies, and ignores properties like `${project.version}`.
+    fn from_pom_xml(content: &str) -> Result<Self> {
+        let content = strip_xml_comments(content);
+        let parent = element(&content, "parent");
+        let dependencies = element(&content, "dependencies").unwrap_or("");
+        let mut project = content.clone();
+        for section in [
+            "parent",
+            "dependencies",
+            "dependencyManagement",
+            "build",
+            "profiles",
+        ]
+        .iter()
+        {
+            if let Some(inner) = element(&project, section) {
+                project = project.replacen(inner, "", 1);
+            }
+        }
+
+        let group_id = element(&project, "groupId")
+            .or_else(|| parent.and_then(|parent| element(parent, "groupId")))
+            .context("Missing groupId")?;
+        let artifact_id = element(&project, "artifactId").context("Missing artifactId")?;
+        let version =
+            element(&project, "version").or_else(|| parent.and_then(|p| element(p, "version")));
+
+        let dependencies = elements(dependencies, "dependency")
+            .filter_map(|dependency| {
+                let group_id = element(dependency, "groupId")?;
+                let artifact_id = element(dependency, "artifactId")?;
+                let version = element(dependency, "version").map(str::to_string);
+                Some((format!("{}:{}", group_id, artifact_id), version))
+            })
+            .collect();
+
+        Ok(Self {
+            manager: PackageManager::Maven,
+            name: format!("{}:{}", group_id, artifact_id),
+            version: version.map(str::to_string),
+            dependencies,
+        })
+    }
+
+    /// Returns the dependency that the given module is from, e.g. `left-pad` for
+    /// `left-pad/lib/index` and `@types/node` for `@types/node/fs`. Relative modules are
+    /// part of the project.
+    pub fn dependency_of(&self, module: &str) -> Option<(&String, &Option<String>)> {
+        if module.starts_with('.') || module.starts_with('/') {
+            return None;
+        }
+        let segments = if module.starts_with('@') { 2 } else { 1 };
+        let end = module
+            .match_indices('/')
+            .nth(segments - 1)
+            .map_or(module.len(), |(i, _)| i);
+        self.dependencies.get_key_value(&module[..end])
+    }
+}
+
+fn strip_xml_comments(content: &str) -> String {
+    let mut stripped = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find("<!--") {
+        stripped.push_str(&rest[..start]);
+        rest = match rest[start..].find("-->") {
+            Some(end) => &rest[start + end + 3..],
+            None => "",
+        };
+    }
+    stripped.push_str(rest);
+    stripped
+}
+
+/// Returns the trimmed content of the first element with the given name.
+fn element<'a>(content: &'a str, name: &str) -> Option<&'a str> {
+    elements(content, name).next()
+}
+
+/// Returns the trimmed contents of the elements with the given name, elements with the
+/// same name must not be nested.
+fn elements<'a>(content: &'a str, name: &str) -> impl Iterator<Item = &'a str> {
+    let open = format!("<{}>", name);
+    let close = format!("</{}>", name);
+    let mut rest = content;
+    std::iter::from_fn(move || {
+        let start = rest.find(&open)? + open.len();
+        let end = start + rest[start..].find(&close)?;
+        let inner = &rest[start..end];
+        rest = &rest[end + close.len()..];
+        Some(inner.trim())
+    })
+}
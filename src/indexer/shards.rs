@@ -0,0 +1,120 @@
+//! Indexing a monorepo as one dump per package, see `--out-dir`.
+//!
+//! Each top-level directory of the project root that has files to index is a package, and
+//! is indexed as its own project: its dump has its own metadata and project vertices, and
+//! references to the other packages are left to the monikers of the package manifests.
+//! The files at the top level of the project root are indexed as a package too, named
+//! after the project root.
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    path::{Component, Path, PathBuf},
+};
+
+use anyhow::{bail, Context, Result};
+
+use crate::{
+    cli::Opts,
+    emitter::file_emitter::{wait_until_flushed, FileEmitter, DEFAULT_BUF_SIZE},
+    indexer::{
+        indexer::{discover_files, Indexer},
+        stats::IndexStats,
+    },
+};
+
+/// A dump written for a package.
+#[derive(Debug)]
+pub struct Shard {
+    pub package: PathBuf,
+    pub output: PathBuf,
+    pub stats: IndexStats,
+}
+
+/// Returns the packages of the project of the given options, i.e. the top-level directories
+/// of the project root that have files to index, in the order of their paths. The project
+/// root comes first if it has files to index at its top level.
+pub fn packages(opt: &Opts) -> Result<Vec<PathBuf>> {
+    let mut has_root_files = false;
+    let packages: BTreeSet<PathBuf> = discover_files(opt)?
+        .iter()
+        .filter_map(|path| {
+            let relative = path.strip_prefix(&opt.project_root).ok()?;
+            let mut components = relative.components();
+            match (components.next(), components.next()) {
+                (Some(Component::Normal(package)), Some(_)) => Some(opt.project_root.join(package)),
+                _ => {
+                    has_root_files = true;
+                    None
+                }
+            }
+        })
+        .collect();
+    Ok(has_root_files
+        .then(|| opt.project_root.clone())
+        .into_iter()
+        .chain(packages)
+        .collect())
+}
+
+/// Returns the options to index the given package of the project of the given options into
+/// `<out_dir>/<package>.json`. The package of the project root only has the files at its
+/// top level, the given packages are excluded.
+pub fn shard_opts(opt: &Opts, package: &Path, packages: &[PathBuf], out_dir: &Path) -> Opts {
+    let name = package.file_name().unwrap_or_default().to_string_lossy();
+    let mut exclude = opt.exclude.clone();
+    if package == opt.project_root {
+        exclude.extend(
+            packages
+                .iter()
+                .filter(|other| **other != opt.project_root)
+                .filter_map(|other| Some(format!("/{}/**", other.file_name()?.to_string_lossy()))),
+        );
+    }
+    Opts {
+        project_root: package.to_path_buf(),
+        output: Some(out_dir.join(format!("{}.json", name))),
+        project_name: None,
+        exclude,
+        ..opt.clone()
+    }
+}
+
+/// Indexes each package of the project of the given options into its own dump in the given
+/// directory, which is created if it does not exist.
+pub fn index_shards(opt: &Opts, out_dir: &Path) -> Result<Vec<Shard>> {
+    std::fs::create_dir_all(out_dir)
+        .with_context(|| format!("Could not create the output directory {:?}", out_dir))?;
+
+    let packages = packages(opt)?;
+    let root_name = opt.project_root.file_name();
+    if let Some(package) = packages
+        .iter()
+        .find(|p| **p != opt.project_root && p.file_name() == root_name)
+    {
+        bail!(
+            "The package {:?} has the same dump as the files at the top level of the project root",
+            package
+        );
+    }
+
+    let mut shards = vec![];
+    for package in &packages {
+        let shard_opt = shard_opts(opt, package, &packages, out_dir);
+        let output = shard_opt.output.clone().unwrap();
+        let file = File::create(&output)
+            .with_context(|| format!("Could not open the output file {:?}", output))?;
+
+        let buffer_size = opt.buffer_size.unwrap_or(DEFAULT_BUF_SIZE);
+        let (emitter, signal_receiver) =
+            FileEmitter::with_capacity(file, opt.output_format, buffer_size);
+        let stats = Indexer::index_with_stats(shard_opt, emitter)?;
+        wait_until_flushed(&signal_receiver)
+            .with_context(|| format!("Could not write the output file {:?}", output))?;
+        shards.push(Shard {
+            package: package.clone(),
+            output,
+            stats,
+        });
+    }
+    Ok(shards)
+}
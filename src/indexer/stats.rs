@@ -0,0 +1,59 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::Serialize;
+
+/// Counts of what was indexed in a run.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct IndexStats {
+    pub documents: usize,
+    pub definitions: usize,
+    /// All the references found, including the unresolved ones.
+    pub references: usize,
+    /// References for which no definition was found, e.g. references to dependencies.
+    pub unresolved_references: usize,
+    /// Range vertices emitted for both definitions and references.
+    pub ranges: usize,
+    /// Language -> Number of documents
+    pub languages: BTreeMap<String, usize>,
+    /// Files that were skipped because they took longer than the parse timeout to parse,
+    /// in the order of their paths.
+    pub timed_out_files: Vec<PathBuf>,
+}
+
+impl IndexStats {
+    /// Writes the stats as JSON to the file at the given path.
+    pub fn write_to(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_vec_pretty(self)?;
+        std::fs::write(path, content)
+            .with_context(|| format!("Could not write the stats file {:?}", path))
+    }
+}
+
+/// The outcome of a successful run, as returned by `Indexer::index`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct IndexSummary {
+    pub documents: usize,
+    pub definitions: usize,
+    /// All the references found, including the unresolved ones.
+    pub references: usize,
+    pub unresolved_references: usize,
+    /// The time taken by the whole run, including writing the caches and reports.
+    pub elapsed: Duration,
+}
+
+impl IndexSummary {
+    pub fn new(stats: &IndexStats, elapsed: Duration) -> Self {
+        Self {
+            documents: stats.documents,
+            definitions: stats.definitions,
+            references: stats.references,
+            unresolved_references: stats.unresolved_references,
+            elapsed,
+        }
+    }
+}
@@ -0,0 +1,53 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+    time::Duration,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The time taken to index a file, as written in the timings report.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct FileTiming {
+    pub file: String,
+    /// Time taken to read and parse the file, in microseconds.
+    pub parse_micros: u64,
+    /// Time taken to analyze the syntax tree of the file, in microseconds.
+    pub analysis_micros: u64,
+}
+
+impl FileTiming {
+    pub fn new(file: String, parse: Duration, analysis: Duration) -> Self {
+        Self {
+            file,
+            parse_micros: parse.as_micros() as u64,
+            analysis_micros: analysis.as_micros() as u64,
+        }
+    }
+
+    /// Returns the total time taken by the file, in microseconds.
+    pub fn total_micros(&self) -> u64 {
+        self.parse_micros + self.analysis_micros
+    }
+}
+
+/// Writes the given timings to the file at the given path, one JSON object per line,
+/// sorted by the slowest file first.
+pub fn write_report(path: &Path, timings: &mut [FileTiming]) -> Result<()> {
+    timings.sort_by(|a, b| {
+        b.total_micros()
+            .cmp(&a.total_micros())
+            .then_with(|| a.file.cmp(&b.file))
+    });
+    let file = File::create(path)
+        .with_context(|| format!("Could not create the timings report {:?}", path))?;
+    let mut writer = BufWriter::new(file);
+    for timing in timings.iter() {
+        serde_json::to_writer(&mut writer, timing)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
@@ -0,0 +1,54 @@
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+    path::Path,
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::analyzer::analyzer::Reference;
+
+/// A reference for which no definition was found, as written in the unresolved
+/// references report.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub struct UnresolvedReference {
+    pub file: String,
+    /// Zero-based line of the start of the reference.
+    pub line: usize,
+    /// Zero-based column of the start of the reference.
+    pub character: usize,
+    pub identifier: String,
+}
+
+impl From<&Reference> for UnresolvedReference {
+    fn from(r: &Reference) -> Self {
+        Self {
+            file: r.location.file_path.clone(),
+            line: r.location.range.start_point.row,
+            character: r.location.range.start_point.column,
+            identifier: r.node_name.to_string(),
+        }
+    }
+}
+
+/// Writes the given references to the file at the given path, one JSON object per line,
+/// sorted by file and position.
+pub fn write_report(path: &Path, refs: &[Reference]) -> Result<()> {
+    let mut entries: Vec<UnresolvedReference> = refs.iter().map(From::from).collect();
+    entries.sort_by(|a, b| (&a.file, a.line, a.character).cmp(&(&b.file, b.line, b.character)));
+
+    let file = File::create(path).with_context(|| {
+        format!(
+            "Could not create the unresolved references report {:?}",
+            path
+        )
+    })?;
+    let mut writer = BufWriter::new(file);
+    for entry in entries {
+        serde_json::to_writer(&mut writer, &entry)?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
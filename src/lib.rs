@@ -0,0 +1,15 @@
+//! An LSIF indexer built on tree-sitter queries. The `zas-lsif-tools` binary is a thin
+//! command-line wrapper around `Indexer`, which benchmarks and other tools can use directly.
+pub mod analyzer;
+pub mod cli;
+pub mod config;
+pub mod emitter;
+pub mod error;
+pub mod indexer;
+pub mod logger;
+pub mod merge;
+pub mod protocol;
+#[cfg(test)]
+mod query_tests;
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,42 @@
+use std::io::{self, Write};
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// A logger that writes messages to stderr, errors and warnings are prefixed with
+/// their level.
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let mut stderr = io::stderr();
+        let _ = match record.level() {
+            Level::Error | Level::Warn => writeln!(
+                stderr,
+                "{}: {}",
+                record.level().to_string().to_lowercase(),
+                record.args()
+            ),
+            _ => writeln!(stderr, "{}", record.args()),
+        };
+    }
+
+    fn flush(&self) {
+        let _ = io::stderr().flush();
+    }
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Logs the messages up to the given level to stderr.
+pub fn init(level: LevelFilter) {
+    // Only fails if a logger was already set
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
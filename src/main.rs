@@ -1,62 +1,260 @@
-use std::env;
+use std::{
+    env,
+    fs::File,
+    path::Path,
+    sync::mpsc::{channel, Sender},
+};
 
 use anyhow::Context;
-use cli::Opts;
 use indicatif::ProgressBar;
-use structopt::StructOpt;
-
-use crate::{emitter::file_emitter::FileEmitter, indexer::indexer::Indexer};
-
-mod analyzer;
-mod cli;
-mod emitter;
-pub mod indexer;
-mod protocol;
-mod query_tests;
-mod tests;
+use zas_lsif_tools::{
+    cli::{languages_list, Command, MergeOpts, Opts},
+    emitter::{
+        file_emitter::{
+            last_id, wait_until_flushed, FileEmitter, ShutdownHandle, DEFAULT_BUF_SIZE,
+        },
+        validating_emitter::ValidatingEmitter,
+    },
+    indexer::{
+        indexer::{discover_files, Indexer},
+        shards::index_shards,
+        stats::IndexSummary,
+    },
+    logger,
+    merge::merge_dumps,
+    protocol,
+};
 
 fn main() {
-    let args = env::args();
-    // A hack to avoid sub-commands
-    for arg in args {
-        if &arg == "--langs" {
-            println!("Currently supported languages:");
-            println!("\t- JavaScript");
-            println!("\t- GraphQL");
-            println!("\t- Java");
-            println!("\t- TypeScript");
-            return;
-        }
+    let args: Vec<String> = env::args().collect();
+    if let Some(command) = Command::from_args_if_any(&args) {
+        run_command(command);
+        return;
     }
 
     let start = std::time::Instant::now();
 
-    let spinner = ProgressBar::new_spinner();
-    spinner.set_message("Parsing files");
+    let mut opt = match Opts::from_config_and_args(&args) {
+        Ok(opt) => opt,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+    opt.args = args;
+    logger::init(opt.log_level());
+    if let Err(e) = opt.canonicalize_paths() {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
+    if let Err(e) = opt.validate() {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
 
-    let mut opt: Opts = Opts::from_args();
-    opt.canonicalize_paths();
+    if opt.dry_run {
+        match discover_files(&opt) {
+            Ok(paths) => paths.iter().for_each(|path| println!("{}", path.display())),
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
 
-    let output = std::fs::OpenOptions::new()
-        .write(true)
-        .create(true)
-        .open(&opt.output.clone().unwrap())
-        .context("Could not open the output file")
-        .unwrap();
-    output.set_len(0).unwrap();
+    if let Some(out_dir) = opt.out_dir.clone() {
+        match index_shards(&opt, &out_dir) {
+            Ok(shards) => {
+                for shard in shards {
+                    println!(
+                        "Indexed {} documents of {} into {}",
+                        shard.stats.documents,
+                        shard.package.display(),
+                        shard.output.display()
+                    );
+                }
+            }
+            Err(e) => {
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let output = match open_output(opt.output.as_ref().unwrap(), opt.append) {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
+    if opt.append {
+        let path = opt.output.as_ref().unwrap();
+        opt.appended_after_id = match last_id(path) {
+            Ok(id) => id,
+            Err(e) => {
+                eprintln!("Could not read the dump to append to {:?}: {}", path, e);
+                std::process::exit(1);
+            }
+        };
+    }
 
-    let (emitter, signal_receiver) = FileEmitter::new(output);
+    let buffer_size = opt.buffer_size.unwrap_or(DEFAULT_BUF_SIZE);
+    let (emitter, signal_receiver) =
+        FileEmitter::with_capacity(output, opt.output_format, buffer_size);
+    let emitter = emitter.with_first_id_after(opt.appended_after_id);
+    let run_events = handle_interrupts(emitter.shutdown_handle());
 
-    Indexer::index(opt, emitter).unwrap();
+    let (print_stats, stats_file) = (opt.stats, opt.stats_file.clone());
+    // The indexer shows the progress of each step, the spinner is only shown afterwards
+    let spinner = if opt.quiet {
+        ProgressBar::hidden()
+    } else {
+        ProgressBar::new_spinner()
+    };
+    let result = if opt.validate_graph {
+        let (emitter, validation_receiver) = ValidatingEmitter::new(emitter);
+        Indexer::index_with_stats(opt, emitter)
+            .map(|stats| (stats, validation_receiver.recv().unwrap()))
+    } else {
+        Indexer::index_with_stats(opt, emitter).map(|stats| (stats, Ok(())))
+    };
+    let (stats, validation_result) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+    };
 
     spinner.enable_steady_tick(60);
     spinner.set_message("waiting for the buffer to be flushed");
 
-    // Wait until the buffer is flushed
-    signal_receiver.recv().unwrap();
+    // Wait until the buffer is flushed, the process only exits with 0 once the dump is complete
+    if let Err(e) = wait_until_flushed(&signal_receiver) {
+        spinner.finish_and_clear();
+        eprintln!("Could not write the output file: {}", e);
+        std::process::exit(1);
+    }
+    // The dump is complete, an interrupt no longer has anything to flush
+    let _ = run_events.send(RunEvent::Finished);
+
+    if let Err(e) = validation_result {
+        spinner.finish_and_clear();
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
 
+    let summary = IndexSummary::new(&stats, start.elapsed());
     spinner.finish_with_message(&format!(
-        "Finished indexing, took {}ms",
-        start.elapsed().as_millis()
+        "Finished indexing {} documents ({} definitions, {} references, {} unresolved), took {}ms",
+        summary.documents,
+        summary.definitions,
+        summary.references,
+        summary.unresolved_references,
+        summary.elapsed.as_millis()
     ));
+
+    if let Some(path) = stats_file {
+        if let Err(e) = stats.write_to(&path) {
+            eprintln!("{:#}", e);
+            std::process::exit(1);
+        }
+    } else if print_stats {
+        eprintln!("{}", serde_json::to_string_pretty(&stats).unwrap());
+    }
+}
+
+/// Runs the given subcommand, and exits with 1 if it fails.
+fn run_command(command: Command) {
+    let result = match command {
+        Command::Merge(opt) => merge(&opt),
+        Command::Languages => {
+            println!("{}", languages_list());
+            Ok(())
+        }
+        Command::Schema => {
+            let schema = protocol::schema::entry_schema();
+            println!("{}", serde_json::to_string_pretty(&schema).unwrap());
+            Ok(())
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{:#}", e);
+        std::process::exit(1);
+    }
+}
+
+/// Merges the dumps of the given options into their output file.
+fn merge(opt: &MergeOpts) -> anyhow::Result<()> {
+    let output = open_output(&opt.output, false)?;
+    let (mut emitter, signal_receiver) = FileEmitter::new(output, opt.output_format);
+    let stats = merge_dumps(&opt.dumps, &mut emitter)?;
+    wait_until_flushed(&signal_receiver).context("Could not write the output file")?;
+    println!(
+        "Merged {} dumps ({} entries, {} monikers linked) into {}",
+        stats.dumps,
+        stats.entries,
+        stats.linked_monikers,
+        opt.output.display()
+    );
+    Ok(())
+}
+
+/// Opens and truncates the output file at the given path, creating its parent directories
+/// if they do not exist, e.g. for `--output build/lsif/dump.json` in a fresh tree.
+fn open_output(path: &Path, append: bool) -> anyhow::Result<File> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).with_context(|| {
+            format!(
+                "Could not create the directory of the output file {:?}",
+                parent
+            )
+        })?;
+    }
+    let output = std::fs::OpenOptions::new()
+        .write(true)
+        .append(append)
+        .create(true)
+        .open(path)
+        .with_context(|| format!("Could not open the output file {:?}", path))?;
+    if append {
+        return Ok(output);
+    }
+    output
+        .set_len(0)
+        .with_context(|| format!("Could not truncate the output file {:?}", path))?;
+    Ok(output)
+}
+
+/// The events the thread of `handle_interrupts` waits for.
+enum RunEvent {
+    Interrupted,
+    Finished,
+}
+
+/// Installs a SIGINT handler that makes the given emitter flush the entries emitted so far
+/// and exits, so that an interrupted run leaves a valid (if partial) output file. The
+/// returned sender stops the handling thread once indexing is finished.
+fn handle_interrupts(shutdown_handle: ShutdownHandle) -> Sender<RunEvent> {
+    let (sender, receiver) = channel();
+    let interrupt_sender = sender.clone();
+    ctrlc::set_handler(move || {
+        // The dump is already complete, so the run is interrupted as without the handler
+        if interrupt_sender.send(RunEvent::Interrupted).is_err() {
+            std::process::exit(130);
+        }
+    })
+    .expect("Could not install the SIGINT handler");
+
+    std::thread::spawn(move || {
+        if let Ok(RunEvent::Interrupted) = receiver.recv() {
+            shutdown_handle.shutdown();
+            eprintln!("\nInterrupted, the output file only contains the entries emitted so far");
+            std::process::exit(130);
+        }
+    });
+    sender
 }
@@ -0,0 +1,219 @@
+//! Merging dumps that were indexed separately, e.g. the packages of a monorepo indexed with
+//! `--out-dir`, into a single dump, see the `merge` subcommand.
+//!
+//! The entries of each dump are emitted again with new identifiers, so that they do not
+//! collide. Only the metadata of the first dump is kept, each dump keeps its own project.
+//! The `import` monikers of a dump are linked to the matching `export` monikers of the other
+//! dumps with `refersTo` edges, so that references can be followed across packages.
+use std::{collections::HashMap, path::PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use crate::{
+    emitter::emitter::Emitter,
+    protocol::{
+        graph::read_entries,
+        types::{Edge, EdgeData, Element, Item, Moniker, NumberOrString, Vertex, ID},
+    },
+};
+
+/// What was merged.
+#[derive(Debug, Default)]
+pub struct MergeStats {
+    pub dumps: usize,
+    pub entries: usize,
+    /// The number of `import` monikers linked to an `export` moniker of another dump.
+    pub linked_monikers: usize,
+}
+
+/// A moniker of a merged dump, with its new identifier and the name of its package, if any.
+struct MergedMoniker {
+    dump: usize,
+    id: ID,
+    moniker: Moniker,
+    package: Option<String>,
+}
+
+/// Emits the entries of the dumps at the given paths, in the `ndjson` or the `array` output
+/// format, with the given emitter, and ends it.
+///
+/// The vertices of a dump are emitted before its edges, so that edges can reference
+/// vertices that come after them in the dump.
+pub fn merge_dumps<E: Emitter>(paths: &[PathBuf], emitter: &mut E) -> Result<MergeStats> {
+    let mut stats = MergeStats::default();
+    let mut monikers = vec![];
+    // New ID of a moniker -> Index in `monikers`
+    let mut moniker_indices = HashMap::new();
+    let mut has_metadata = false;
+    for (dump, path) in paths.iter().enumerate() {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Could not read the dump {:?}", path))?;
+        let entries = read_entries(&content).with_context(|| format!("Invalid dump {:?}", path))?;
+
+        // Old ID -> New ID
+        let mut ids = HashMap::new();
+        // New ID of a package information vertex -> Name of the package
+        let mut packages = HashMap::new();
+        let mut edges = vec![];
+        for entry in entries {
+            let vertex = match entry.data {
+                Element::Vertex(vertex) => vertex,
+                Element::Edge(edge) => {
+                    edges.push(edge);
+                    continue;
+                }
+            };
+            if let Vertex::MetaData(_) = vertex {
+                if has_metadata {
+                    continue;
+                }
+                has_metadata = true;
+            }
+            let moniker = match &vertex {
+                Vertex::Moniker(moniker) => Some(moniker.clone()),
+                _ => None,
+            };
+            let package = match &vertex {
+                Vertex::PackageInformation(package) => Some(package.name.clone()),
+                _ => None,
+            };
+            let id = emitter.emit_vertex(vertex);
+            ids.insert(to_id(&entry.id)?, id);
+            if let Some(moniker) = moniker {
+                moniker_indices.insert(id, monikers.len());
+                monikers.push(MergedMoniker {
+                    dump,
+                    id,
+                    moniker,
+                    package: None,
+                });
+            }
+            if let Some(package) = package {
+                packages.insert(id, package);
+            }
+        }
+        stats.entries += ids.len() + edges.len();
+
+        for mut edge in edges {
+            remap_edge(&mut edge, &ids).with_context(|| format!("Invalid dump {:?}", path))?;
+            if let Edge::PackageInformation(data) = &edge {
+                let (moniker_id, package_id) = (to_id(&data.out_v)?, to_id(&data.in_v)?);
+                if let Some(&index) = moniker_indices.get(&moniker_id) {
+                    monikers[index].package = packages.get(&package_id).cloned();
+                }
+            }
+            emitter.emit_edge(edge);
+        }
+        stats.dumps += 1;
+    }
+
+    // (Scheme, Package, Name) -> Export monikers
+    let mut exports: HashMap<_, Vec<&MergedMoniker>> = HashMap::new();
+    for export in &monikers {
+        if let Some(key) = export_key(export) {
+            exports.entry(key).or_default().push(export);
+        }
+    }
+    for import in &monikers {
+        let exports = match import_key(import).and_then(|key| exports.get(&key)) {
+            Some(exports) => exports,
+            None => continue,
+        };
+        for export in exports.iter().filter(|export| export.dump != import.dump) {
+            emitter.emit_edge(Edge::RefersTo(EdgeData {
+                in_v: NumberOrString::Number(export.id),
+                out_v: NumberOrString::Number(import.id),
+            }));
+            stats.linked_monikers += 1;
+        }
+    }
+    emitter.end();
+    Ok(stats)
+}
+
+/// Returns the scheme, the package and the name of the given `import` moniker, which refers
+/// to the `export` monikers with the same ones, see `export_key`. Import identifiers are
+/// `<package>:<name>`, where the package is the one of their package information, e.g.
+/// `org.example:app` for Maven. The imports of symbols of unknown packages have no package
+/// and refer to no export.
+fn import_key(import: &MergedMoniker) -> Option<(&str, &str, &str)> {
+    if import.moniker.kind != "import" {
+        return None;
+    }
+    let package = import.package.as_deref()?;
+    let name = in_package(&import.moniker.identifier, package)?;
+    Some((&import.moniker.scheme, package, name))
+}
+
+/// Returns the scheme, the package and the name of the given `export` moniker, see
+/// `import_key`. Export identifiers are `<package>:<path>:<name>`.
+fn export_key(export: &MergedMoniker) -> Option<(&str, &str, &str)> {
+    if export.moniker.kind != "export" {
+        return None;
+    }
+    let package = export.package.as_deref()?;
+    let name = in_package(&export.moniker.identifier, package)?
+        .rsplit(':')
+        .next()?;
+    Some((&export.moniker.scheme, package, name))
+}
+
+/// Returns the given moniker identifier without the given package and its separator.
+fn in_package<'a>(identifier: &'a str, package: &str) -> Option<&'a str> {
+    identifier.strip_prefix(package)?.strip_prefix(':')
+}
+
+/// Replaces the identifiers of the vertices of the given edge with their new identifiers.
+fn remap_edge(edge: &mut Edge, ids: &HashMap<ID, ID>) -> Result<()> {
+    let new_id = |id: ID| {
+        ids.get(&id)
+            .copied()
+            .ok_or_else(|| anyhow!("An edge references the missing vertex {}", id))
+    };
+    let remap = |v: &mut NumberOrString| -> Result<()> {
+        *v = NumberOrString::Number(new_id(to_id(v)?)?);
+        Ok(())
+    };
+    match edge {
+        Edge::Contains(data) => {
+            remap(&mut data.out_v)?;
+            data.in_vs.iter_mut().try_for_each(remap)?;
+        }
+        Edge::Item(item) => {
+            let data = match item {
+                Item::Definition(data) | Item::Reference(data) | Item::Neither(data) => data,
+            };
+            remap(&mut data.out_v)?;
+            data.in_vs.iter_mut().try_for_each(remap)?;
+            data.document = new_id(data.document)?;
+        }
+        Edge::RefersTo(data)
+        | Edge::Next(data)
+        | Edge::Moniker(data)
+        | Edge::PackageInformation(data)
+        | Edge::Definition(data)
+        | Edge::Declaration(data)
+        | Edge::Hover(data)
+        | Edge::References(data)
+        | Edge::Implementation(data)
+        | Edge::TypeDefinition(data)
+        | Edge::FoldingRange(data)
+        | Edge::DocumentLink(data)
+        | Edge::DocumentSymbol(data)
+        | Edge::Diagnostic(data) => {
+            remap(&mut data.out_v)?;
+            remap(&mut data.in_v)?;
+        }
+    }
+    Ok(())
+}
+
+fn to_id(n: &NumberOrString) -> Result<ID> {
+    match n {
+        NumberOrString::Number(n) => Ok(*n),
+        NumberOrString::String(s) => match s.parse() {
+            Ok(id) => Ok(id),
+            Err(_) => bail!("Invalid identifier {:?}", s),
+        },
+    }
+}
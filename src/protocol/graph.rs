@@ -0,0 +1,194 @@
+//! A reader of dumps, that answers the requests of an editor, e.g. go to definition, by
+//! walking the edges of the graph like an LSIF-aware language server would.
+use std::{cmp::Reverse, collections::HashMap};
+
+use anyhow::{Context, Result};
+
+use super::types::{
+    Edge, Element, Entry, Item, MultiEdgeDataWithDocument, NumberOrString, Position, Range, Vertex,
+    ID,
+};
+
+/// A range of a document of a dump.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DocumentRange {
+    pub uri: String,
+    pub range: Range,
+}
+
+/// The vertices and edges of a dump that are needed to answer requests, indexed by the
+/// identifiers of their vertices.
+#[derive(Debug, Default)]
+pub struct LsifGraph {
+    /// Document ID -> URI
+    documents: HashMap<ID, String>,
+    /// Range ID -> Range
+    ranges: HashMap<ID, Range>,
+    /// Document URI -> IDs of the ranges it contains
+    document_ranges: HashMap<String, Vec<ID>>,
+    /// Range ID -> Document ID
+    range_documents: HashMap<ID, ID>,
+    /// Range or result set ID -> Result set ID
+    next: HashMap<ID, ID>,
+    /// Range or result set ID -> Definition result ID
+    definition_results: HashMap<ID, ID>,
+    /// Range or result set ID -> Reference result ID
+    reference_results: HashMap<ID, ID>,
+    /// Result ID -> Items of the result
+    items: HashMap<ID, Vec<Item>>,
+}
+
+impl LsifGraph {
+    /// Builds the graph of the given entries, in any order.
+    pub fn new(entries: impl IntoIterator<Item = Entry>) -> Self {
+        let mut graph = Self::default();
+        let mut contains = Vec::new();
+        for entry in entries {
+            let id = to_id(&entry.id);
+            match entry.data {
+                Element::Vertex(Vertex::Document(d)) => {
+                    graph.documents.insert(id, d.uri);
+                }
+                Element::Vertex(Vertex::Range(r)) => {
+                    graph.ranges.insert(id, r.range);
+                }
+                Element::Edge(Edge::Contains(data)) => contains.push(data),
+                Element::Edge(Edge::Next(data)) => {
+                    graph.next.insert(to_id(&data.out_v), to_id(&data.in_v));
+                }
+                Element::Edge(Edge::Definition(data)) => {
+                    graph
+                        .definition_results
+                        .insert(to_id(&data.out_v), to_id(&data.in_v));
+                }
+                Element::Edge(Edge::References(data)) => {
+                    graph
+                        .reference_results
+                        .insert(to_id(&data.out_v), to_id(&data.in_v));
+                }
+                Element::Edge(Edge::Item(item)) => {
+                    let out_v = to_id(&item_data(&item).out_v);
+                    graph.items.entry(out_v).or_default().push(item);
+                }
+                _ => {}
+            }
+        }
+
+        // The documents are only known once all the vertices are read
+        for data in contains {
+            let document_id = to_id(&data.out_v);
+            let uri = match graph.documents.get(&document_id) {
+                Some(uri) => uri.clone(),
+                // e.g. the project contains the documents
+                None => continue,
+            };
+            let range_ids = data.in_vs.iter().map(to_id);
+            for range_id in range_ids.clone() {
+                graph.range_documents.insert(range_id, document_id);
+            }
+            graph
+                .document_ranges
+                .entry(uri)
+                .or_default()
+                .extend(range_ids);
+        }
+
+        graph
+    }
+
+    /// Reads the graph of a dump in the `ndjson` or the `array` output format.
+    pub fn from_json(content: &str) -> Result<Self> {
+        Ok(Self::new(read_entries(content)?))
+    }
+
+    /// Returns the identifier of the innermost range of the document with the given URI
+    /// that contains the given position. The end of a range is exclusive.
+    pub fn range_at(&self, uri: &str, position: Position) -> Option<ID> {
+        self.document_ranges
+            .get(uri)?
+            .iter()
+            .filter_map(|id| Some((*id, self.ranges.get(id)?)))
+            .filter(|(_, range)| range.start <= position && position < range.end)
+            // Ranges are nested, so the innermost one starts last
+            .max_by_key(|(_, range)| (range.start, Reverse(range.end)))
+            .map(|(id, _)| id)
+    }
+
+    /// Returns the ranges that define the symbol at the given position of the document
+    /// with the given URI, i.e. the answer to a go to definition request.
+    pub fn definitions(&self, uri: &str, position: Position) -> Vec<DocumentRange> {
+        self.range_at(uri, position)
+            .and_then(|id| self.result_of(id, &self.definition_results))
+            .map_or(vec![], |result_id| self.item_ranges(result_id, |_| true))
+    }
+
+    /// Returns the ranges that reference the symbol at the given position of the document
+    /// with the given URI, without the ranges that define it.
+    pub fn references(&self, uri: &str, position: Position) -> Vec<DocumentRange> {
+        self.range_at(uri, position)
+            .and_then(|id| self.result_of(id, &self.reference_results))
+            .map_or(vec![], |result_id| {
+                self.item_ranges(result_id, |item| matches!(item, Item::Reference(_)))
+            })
+    }
+
+    /// Returns the result of the given range or result set from the given results, following
+    /// the `next` edges until a vertex with a result is found.
+    fn result_of(&self, mut id: ID, results: &HashMap<ID, ID>) -> Option<ID> {
+        // The number of steps is bounded in case the `next` edges form a cycle
+        for _ in 0..=self.next.len() {
+            if let Some(result_id) = results.get(&id) {
+                return Some(*result_id);
+            }
+            id = *self.next.get(&id)?;
+        }
+        None
+    }
+
+    /// Returns the ranges of the items of the given result that match the given filter.
+    fn item_ranges(&self, result_id: ID, filter: fn(&Item) -> bool) -> Vec<DocumentRange> {
+        self.items
+            .get(&result_id)
+            .into_iter()
+            .flatten()
+            .filter(|item| filter(item))
+            .flat_map(|item| item_data(item).in_vs.iter().map(to_id))
+            .filter_map(|range_id| {
+                let document_id = self.range_documents.get(&range_id)?;
+                Some(DocumentRange {
+                    uri: self.documents.get(document_id)?.clone(),
+                    range: *self.ranges.get(&range_id)?,
+                })
+            })
+            .collect()
+    }
+}
+
+fn item_data(item: &Item) -> &MultiEdgeDataWithDocument {
+    match item {
+        Item::Definition(data) | Item::Reference(data) | Item::Neither(data) => data,
+    }
+}
+
+/// Reads the entries of a dump in the `ndjson` or the `array` output format, in order.
+pub fn read_entries(content: &str) -> Result<Vec<Entry>> {
+    if content.trim_start().starts_with('[') {
+        return serde_json::from_str(content).context("Could not read the entries of the dump");
+    }
+    content
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .enumerate()
+        .map(|(i, line)| {
+            serde_json::from_str(line)
+                .with_context(|| format!("Could not read entry {} of the dump", i + 1))
+        })
+        .collect()
+}
+
+fn to_id(n: &NumberOrString) -> ID {
+    match n {
+        NumberOrString::Number(n) => *n,
+        NumberOrString::String(s) => s.parse().unwrap_or(0),
+    }
+}
@@ -1,3 +1,5 @@
 //! Types for the Language Server Index Format
 #[macro_use]
 pub mod types;
+pub mod graph;
+pub mod schema;
@@ -0,0 +1,262 @@
+//! A JSON Schema of the emitted entries, for consumers that validate or deserialize
+//! dumps in other languages.
+//!
+//! The schema is written by hand to match the serde representation of `Entry`: the
+//! `Element` is flattened into the entry and tagged with `type`, vertices and edges are
+//! tagged with `label`, and the `item` edges of reference results have a `property`.
+use serde_json::{json, Value};
+
+use super::types::{Language, SUPPORTED_LSIF_VERSIONS};
+
+/// The vertex labels and the schemas of their properties.
+fn vertices() -> Vec<(&'static str, Value)> {
+    vec![
+        (
+            "project",
+            object(json!({
+                "languageId": reference("languageId"),
+                "name": { "type": "string" },
+            })),
+        ),
+        (
+            "document",
+            object(json!({
+                "uri": { "type": "string" },
+                "languageId": reference("documentLanguageId"),
+            })),
+        ),
+        (
+            "range",
+            with_optional(
+                object(json!({
+                    "start": reference("position"),
+                    "end": reference("position"),
+                })),
+                json!({
+                    "startByte": { "type": "integer", "minimum": 0 },
+                    "endByte": { "type": "integer", "minimum": 0 },
+                    "tag": object(json!({
+                        "type": { "type": "string", "enum": ["definition"] },
+                        "text": { "type": "string" },
+                        "kind": { "type": "integer", "minimum": 1 },
+                        "fullRange": reference("range"),
+                    })),
+                }),
+            ),
+        ),
+        ("resultSet", object(json!({}))),
+        (
+            "hoverResult",
+            object(json!({
+                "result": object(json!({
+                    "contents": {
+                        "type": "array",
+                        "items": object(json!({
+                            "language": { "type": "string" },
+                            "value": { "type": "string" },
+                            "isRawString": { "type": "boolean" },
+                        })),
+                    },
+                })),
+            })),
+        ),
+        (
+            "metaData",
+            with_optional(
+                object(json!({
+                    "version": { "type": "string", "enum": SUPPORTED_LSIF_VERSIONS },
+                    "positionEncoding": { "type": "string" },
+                    "projectRoot": { "type": "string" },
+                })),
+                json!({
+                    "toolInfo": with_optional(
+                        object(json!({ "name": { "type": "string" } })),
+                        json!({
+                            "version": { "type": "string" },
+                            "args": { "type": "array", "items": { "type": "string" } },
+                        }),
+                    ),
+                }),
+            ),
+        ),
+        (
+            "moniker",
+            object(json!({
+                "kind": { "type": "string" },
+                "scheme": { "type": "string" },
+                "identifier": { "type": "string" },
+            })),
+        ),
+        (
+            "packageInformation",
+            with_optional(
+                object(json!({
+                    "name": { "type": "string" },
+                    "manager": { "type": "string", "enum": ["npm", "maven"] },
+                })),
+                json!({ "version": { "type": "string" } }),
+            ),
+        ),
+        ("definitionResult", object(json!({}))),
+        ("declarationResult", object(json!({}))),
+        ("implementationResult", object(json!({}))),
+        ("referenceResult", object(json!({}))),
+        (
+            "diagnosticResult",
+            object(json!({
+                "result": {
+                    "type": "array",
+                    "items": with_optional(
+                        object(json!({
+                            "range": object(json!({
+                                "start": reference("position"),
+                                "end": reference("position"),
+                            })),
+                            "message": { "type": "string" },
+                        })),
+                        json!({
+                            "severity": { "type": "integer", "minimum": 1, "maximum": 4 },
+                            "code": { "type": ["integer", "string"] },
+                            "source": { "type": "string" },
+                        }),
+                    ),
+                },
+            })),
+        ),
+        ("exportResult", object(json!({}))),
+        ("externalImportResult", object(json!({}))),
+    ]
+}
+
+/// The edge labels and the schemas of their properties, `item` edges are handled apart.
+fn edges() -> Vec<(&'static str, Value)> {
+    let edge = object(json!({
+        "inV": reference("id"),
+        "outV": reference("id"),
+    }));
+    let mut edges = vec![(
+        "contains",
+        object(json!({
+            "inVs": { "type": "array", "items": reference("id") },
+            "outV": reference("id"),
+        })),
+    )];
+    edges.extend(
+        [
+            "refersTo",
+            "next",
+            "moniker",
+            "packageInformation",
+            "textDocument/definition",
+            "textDocument/declaration",
+            "textDocument/hover",
+            "textDocument/references",
+            "textDocument/implementation",
+            "textDocument/typeDefinition",
+            "textDocument/foldingRange",
+            "textDocument/documentLink",
+            "textDocument/documentSymbol",
+            "textDocument/diagnostic",
+        ]
+        .iter()
+        .map(|label| (*label, edge.clone())),
+    );
+    edges
+}
+
+/// Returns the JSON Schema (draft 7) of an emitted entry, i.e. a line of an `ndjson` dump
+/// or an element of an `array` dump.
+pub fn entry_schema() -> Value {
+    let mut variants: Vec<Value> = vertices()
+        .into_iter()
+        .map(|(label, properties)| variant("vertex", label, properties))
+        .collect();
+    variants.extend(
+        edges()
+            .into_iter()
+            .map(|(label, properties)| variant("edge", label, properties)),
+    );
+    let item = with_optional(
+        object(json!({
+            "document": { "type": "integer" },
+            "inVs": { "type": "array", "items": reference("id") },
+            "outV": reference("id"),
+        })),
+        json!({
+            "property": { "type": "string", "enum": ["definitions", "references"] },
+            "access": { "type": "string", "enum": ["read", "write"] },
+        }),
+    );
+    variants.push(variant("edge", "item", item));
+
+    let languages: Vec<Value> = Language::all()
+        .iter()
+        .map(|language| serde_json::to_value(language).unwrap())
+        .collect();
+    let document_languages: Vec<&str> = Language::all()
+        .iter()
+        .flat_map(|language| language.lsif_language_ids())
+        .copied()
+        .collect();
+
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Entry",
+        "oneOf": variants,
+        "definitions": {
+            "id": { "type": ["integer", "string"] },
+            "languageId": { "type": "string", "enum": languages },
+            "documentLanguageId": { "type": "string", "enum": document_languages },
+            "position": object(json!({
+                "line": { "type": "integer" },
+                "character": { "type": "integer" },
+            })),
+            "range": object(json!({
+                "start": reference("position"),
+                "end": reference("position"),
+            })),
+        },
+    })
+}
+
+/// Returns the schema of the entries of the given element type and label, with the
+/// given properties schema.
+fn variant(element: &str, label: &str, properties: Value) -> Value {
+    let mut schema = properties;
+    let own = json!({
+        "id": reference("id"),
+        "type": { "const": element },
+        "label": { "const": label },
+    });
+    let properties = schema["properties"].as_object_mut().unwrap();
+    for (name, property) in own.as_object().unwrap() {
+        properties.insert(name.clone(), property.clone());
+    }
+    let required = schema["required"].as_array_mut().unwrap();
+    required.extend(vec![json!("id"), json!("type"), json!("label")]);
+    schema
+}
+
+/// Returns the schema of an object with exactly the given properties, all required.
+fn object(properties: Value) -> Value {
+    let required: Vec<&String> = properties.as_object().unwrap().keys().collect();
+    json!({
+        "type": "object",
+        "properties": properties,
+        "required": required,
+        "additionalProperties": false,
+    })
+}
+
+/// Adds the given properties to the given object schema, without requiring them.
+fn with_optional(mut schema: Value, properties: Value) -> Value {
+    let object = schema["properties"].as_object_mut().unwrap();
+    for (name, property) in properties.as_object().unwrap() {
+        object.insert(name.clone(), property.clone());
+    }
+    schema
+}
+
+fn reference(definition: &str) -> Value {
+    json!({ "$ref": format!("#/definitions/{}", definition) })
+}
@@ -1,4 +1,4 @@
-use std::str::FromStr;
+use std::{path::Path, str::FromStr};
 
 use languageserver_types as lsp;
 pub use languageserver_types::{NumberOrString, Range};
@@ -8,6 +8,16 @@ use serde::{Deserialize, Serialize};
 pub type ID = u64;
 pub type RangeId = lsp::NumberOrString;
 
+/// The LSIF version declared in the metadata vertex when none is specified.
+pub const DEFAULT_LSIF_VERSION: &str = "0.5.0";
+
+/// The LSIF versions whose vertices and edges match what the indexer emits
+/// (e.g. `item` edges carrying a `document` and a `property`).
+pub const SUPPORTED_LSIF_VERSIONS: &[&str] = &["0.4.0", "0.4.3", "0.5.0"];
+
+/// The scheme of the emitted monikers.
+pub const MONIKER_SCHEME: &str = "zas";
+
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Entry {
@@ -30,17 +40,20 @@ pub enum Element {
 pub enum Vertex {
     Project(Project),
     Document(Document),
-    Range(Range),
+    Range(RangeVertex),
     ResultSet(ResultSet),
     HoverResult(HoverResult),
     MetaData(MetaData),
     Moniker(Moniker),
+    PackageInformation(PackageInformation),
 
     // Method results
     DefinitionResult(DefinitionResult),
+    DeclarationResult(DeclarationResult),
+    ImplementationResult(ImplementationResult),
 
     ReferenceResult(ReferenceResult),
-    DiagnosticResult,
+    DiagnosticResult(DiagnosticResult),
     ExportResult,
     ExternalImportResult,
 }
@@ -53,6 +66,7 @@ pub enum Edge {
     RefersTo(EdgeData),
     Next(EdgeData),
     Moniker(EdgeData),
+    PackageInformation(EdgeData),
 
     Item(Item),
 
@@ -92,6 +106,18 @@ pub struct MultiEdgeDataWithDocument {
     pub document: u64,
     pub in_vs: Vec<lsp::NumberOrString>,
     pub out_v: lsp::NumberOrString,
+    /// Whether the references of a reference item read or write the symbol.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub access: Option<ReferenceAccess>,
+}
+
+/// Whether a reference reads or writes the symbol it refers to.
+#[derive(Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum ReferenceAccess {
+    #[default]
+    Read,
+    Write,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -108,22 +134,60 @@ pub enum DefinitionResultType {
     Array(LocationOrRangeId),
 }
 
+/// An `item` edge. The items of a reference result have the `definitions` or `references`
+/// property, the items of the other results, e.g. definition results, have none.
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
-#[serde(rename_all = "camelCase")]
-#[serde(tag = "property")]
+#[serde(from = "ItemEdge", into = "ItemEdge")]
 pub enum Item {
     Definition(MultiEdgeDataWithDocument),
     Reference(MultiEdgeDataWithDocument),
-    #[serde(rename = "")]
     Neither(MultiEdgeDataWithDocument),
 }
 
+/// The serialized form of an `Item`.
+#[derive(Serialize, Deserialize)]
+struct ItemEdge {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    property: Option<ItemProperty>,
+    #[serde(flatten)]
+    data: MultiEdgeDataWithDocument,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum ItemProperty {
+    Definitions,
+    References,
+}
+
+impl From<ItemEdge> for Item {
+    fn from(edge: ItemEdge) -> Self {
+        match edge.property {
+            Some(ItemProperty::Definitions) => Item::Definition(edge.data),
+            Some(ItemProperty::References) => Item::Reference(edge.data),
+            None => Item::Neither(edge.data),
+        }
+    }
+}
+
+impl From<Item> for ItemEdge {
+    fn from(item: Item) -> Self {
+        let (property, data) = match item {
+            Item::Definition(data) => (Some(ItemProperty::Definitions), data),
+            Item::Reference(data) => (Some(ItemProperty::References), data),
+            Item::Neither(data) => (None, data),
+        };
+        ItemEdge { property, data }
+    }
+}
+
 impl Edge {
     pub fn item(out_v: ID, in_vs: Vec<ID>, doc_id: ID) -> Self {
         Self::Item(Item::Neither(MultiEdgeDataWithDocument {
             document: doc_id,
             in_vs: in_vs.iter().map(|v| NumberOrString::Number(*v)).collect(),
             out_v: NumberOrString::Number(out_v),
+            access: None,
         }))
     }
 
@@ -132,14 +196,16 @@ impl Edge {
             document: doc_id,
             in_vs: in_vs.iter().map(|v| NumberOrString::Number(*v)).collect(),
             out_v: NumberOrString::Number(out_v),
+            access: None,
         }))
     }
 
-    pub fn ref_item(out_v: ID, in_vs: Vec<ID>, doc_id: ID) -> Self {
+    pub fn ref_item(out_v: ID, in_vs: Vec<ID>, doc_id: ID, access: ReferenceAccess) -> Self {
         Self::Item(Item::Reference(MultiEdgeDataWithDocument {
             document: doc_id,
             in_vs: in_vs.iter().map(|v| NumberOrString::Number(*v)).collect(),
             out_v: NumberOrString::Number(out_v),
+            access: Some(access),
         }))
     }
 
@@ -154,15 +220,69 @@ impl Edge {
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Document {
-    #[serde(with = "url_serde")]
-    pub uri: lsp::Url,
-    pub language_id: Language,
+    /// An absolute `file://` URI, or a path relative to the project root of the metadata.
+    pub uri: String,
+    /// The language identifier of the document, see `Language::lsif_language_id`.
+    pub language_id: String,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ResultSet {}
 
+/// A range vertex. The byte offsets of the range in its file are an extension of the
+/// protocol for tools that patch the text, they are only emitted if they were requested.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct RangeVertex {
+    #[serde(flatten)]
+    pub range: Range,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub start_byte: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub end_byte: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<RangeTag>,
+}
+
+impl From<Range> for RangeVertex {
+    fn from(range: Range) -> Self {
+        RangeVertex {
+            range,
+            start_byte: None,
+            end_byte: None,
+            tag: None,
+        }
+    }
+}
+
+/// The tag of the range of a definition, with the range of its whole declaration, e.g. a
+/// function with its body, so that editors can show it when peeking.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(tag = "type", rename = "definition", rename_all = "camelCase")]
+pub struct RangeTag {
+    pub text: String,
+    pub kind: SymbolKind,
+    pub full_range: Range,
+}
+
+/// The kind of a symbol, as numbered by the `SymbolKind` of the LSP.
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize, Clone, Copy)]
+#[serde(transparent)]
+pub struct SymbolKind(pub u8);
+
+impl SymbolKind {
+    pub const MODULE: SymbolKind = SymbolKind(2);
+    pub const CLASS: SymbolKind = SymbolKind(5);
+    pub const METHOD: SymbolKind = SymbolKind(6);
+    pub const PROPERTY: SymbolKind = SymbolKind(7);
+    pub const ENUM: SymbolKind = SymbolKind(10);
+    pub const INTERFACE: SymbolKind = SymbolKind(11);
+    pub const FUNCTION: SymbolKind = SymbolKind(12);
+    pub const VARIABLE: SymbolKind = SymbolKind(13);
+    pub const TYPE_PARAMETER: SymbolKind = SymbolKind(26);
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct HoverResult {
@@ -187,10 +307,25 @@ pub struct LSIFMarkedString {
 #[serde(rename_all = "camelCase")]
 pub struct DefinitionResult {}
 
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DeclarationResult {}
+
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct ImplementationResult {}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ReferenceResult {}
 
+/// The diagnostics of a document, e.g. its syntax errors.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct DiagnosticResult {
+    pub result: Vec<lsp::Diagnostic>,
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct MetaData {
@@ -198,8 +333,8 @@ pub struct MetaData {
     pub(crate) position_encoding: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub(crate) tool_info: Option<ToolInfo>,
-    #[serde(with = "url_serde")]
-    pub(crate) project_root: lsp::Url,
+    /// An absolute `file://` URI, or `.` when the document URIs are relative.
+    pub(crate) project_root: String,
 }
 
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
@@ -210,6 +345,33 @@ pub struct Moniker {
     pub(crate) identifier: String,
 }
 
+/// The package that a moniker comes from, e.g. the package of an `export` moniker.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PackageInformation {
+    pub(crate) name: String,
+    pub(crate) manager: PackageManager,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) version: Option<String>,
+}
+
+/// The package managers whose manifests are read, also used as moniker schemes.
+#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum PackageManager {
+    Npm,
+    Maven,
+}
+
+impl PackageManager {
+    pub fn scheme(&self) -> &'static str {
+        match self {
+            PackageManager::Npm => "npm",
+            PackageManager::Maven => "maven",
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Serialize, Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct ToolInfo {
@@ -220,11 +382,23 @@ pub struct ToolInfo {
     pub(crate) args: Option<Vec<String>>,
 }
 
+impl ToolInfo {
+    /// Returns the info of a tool without arguments, the indexer records the arguments of
+    /// its options if they have any.
+    pub fn new(name: impl Into<String>, version: Option<String>) -> Self {
+        ToolInfo {
+            name: name.into(),
+            version,
+            args: None,
+        }
+    }
+}
+
 impl Default for ToolInfo {
     fn default() -> Self {
         ToolInfo {
-            name: "Zas-LSIF-Generator".to_string(),
-            version: None,
+            name: "lsif-os".to_string(),
+            version: Some(env!("CARGO_PKG_VERSION").to_string()),
             args: None,
         }
     }
@@ -234,10 +408,14 @@ impl Default for ToolInfo {
 #[serde(rename_all = "camelCase")]
 pub struct Project {
     pub language_id: Language,
+    /// A human-readable name of the project, e.g. to tell apart the projects of a
+    /// database of several dumps.
+    #[serde(default)]
+    pub name: String,
 }
 
 /// This enum represents all the currently supported languages.
-#[derive(Debug, PartialEq, Serialize, Deserialize, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
 pub enum Language {
     JavaScript,
@@ -247,14 +425,101 @@ pub enum Language {
     TypeScript,
 }
 
+/// The languages that `--language auto` indexes, in the order of precedence used when
+/// several of them have the same extension.
+pub const AUTO_LANGUAGES: [Language; 4] = [
+    Language::TypeScript,
+    Language::JavaScript,
+    Language::Java,
+    Language::GraphQL,
+];
+
 impl Language {
+    /// Returns all the languages, including the ones whose grammar is not compiled in.
+    pub fn all() -> &'static [Language] {
+        &[
+            Language::JavaScript,
+            Language::GraphQL,
+            Language::Lua,
+            Language::Java,
+            Language::TypeScript,
+        ]
+    }
+
+    /// Returns the language with the given file extension, ignoring its case, i.e. the
+    /// inverse of `get_extensions`.
+    pub fn from_extension(extension: &str) -> Option<Language> {
+        let extension = extension.to_lowercase();
+        Language::all()
+            .iter()
+            .copied()
+            .find(|language| language.get_extensions().contains(&extension))
+    }
+
+    /// Returns the language of the extension of the given path, if it is one of
+    /// `AUTO_LANGUAGES`.
+    pub fn from_path(path: &Path) -> Option<Language> {
+        Language::from_extension(path.extension()?.to_str()?)
+            .filter(|language| AUTO_LANGUAGES.contains(language))
+    }
+
+    /// Returns the language identifier of the document at the given path, which is of the
+    /// language, as used by editors, e.g. `typescriptreact` for a `.tsx` file.
+    pub fn lsif_language_id(&self, path: &Path) -> &'static str {
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_lowercase);
+        match (self, extension.as_deref()) {
+            (Language::JavaScript, Some("jsx")) => "javascriptreact",
+            (Language::JavaScript, _) => "javascript",
+            (Language::GraphQL, _) => "graphql",
+            (Language::Lua, _) => "lua",
+            (Language::Java, _) => "java",
+            (Language::TypeScript, Some("tsx")) => "typescriptreact",
+            (Language::TypeScript, Some("vue")) => "vue",
+            (Language::TypeScript, _) => "typescript",
+        }
+    }
+
+    /// Returns all the language identifiers of the documents of the language, see
+    /// `lsif_language_id`.
+    pub fn lsif_language_ids(&self) -> &'static [&'static str] {
+        match self {
+            Language::JavaScript => &["javascript", "javascriptreact"],
+            Language::GraphQL => &["graphql"],
+            Language::Lua => &["lua"],
+            Language::Java => &["java"],
+            Language::TypeScript => &["typescript", "typescriptreact", "vue"],
+        }
+    }
+
+    /// Returns the name of the tree-sitter grammar of the language, e.g. `tsx` for
+    /// `tree-sitter-tsx`.
+    pub fn grammar_name(&self) -> &'static str {
+        match self {
+            Language::JavaScript => "javascript",
+            Language::GraphQL => "graphql",
+            Language::Lua => "lua",
+            Language::Java => "java",
+            Language::TypeScript => "tsx",
+        }
+    }
+
     pub fn get_extensions(&self) -> Vec<String> {
         match self {
             Language::JavaScript => vec!["js".to_string()],
-            Language::GraphQL => vec!["graphql".to_string()],
+            Language::GraphQL => vec![
+                "graphql".to_string(),
+                "graphqls".to_string(),
+                "gql".to_string(),
+            ],
             Language::Lua => vec!["lua".to_string()],
             Language::Java => vec!["java".to_string()],
-            Language::TypeScript => vec!["ts".to_string(), "tsx".to_string()],
+            Language::TypeScript => {
+                // The `<script>` blocks of Vue components, see `analyzer::embedded`
+                vec!["ts".to_string(), "tsx".to_string(), "vue".to_string()]
+            }
         }
     }
 
@@ -305,6 +570,11 @@ impl ToString for Language {
 /// The following code defines a edge of type `Next` going from `a` to `b`
 ///
 /// ```
+/// use zas_lsif_tools::{
+///     edge,
+///     protocol::types::{Edge, EdgeData, NumberOrString},
+/// };
+///
 /// let a = 3;
 /// let b = 3;
 /// let edge = edge!(Next, a -> b);
@@ -339,16 +609,21 @@ macro_rules! edge {
 /// # Examples
 ///
 /// ```
-/// enum StringOrVec {
-///     String(String),
-///     Vec(Vec<String>)
+/// use zas_lsif_tools::impl_from_variant;
+///
+/// struct Document;
+/// struct Range;
+///
+/// enum Vertex {
+///     Document(Document),
+///     Range(Range),
 /// }
 ///
-/// impl_from_variant(String, StringOrVec);
-/// impl_from_variant(Vec<String>, StringOrVec);
+/// impl_from_variant!(Document, Vertex);
+/// impl_from_variant!(Range, Vertex);
 ///
-/// fn ex(s: String) -> StringOrVec {
-///     s.into()
+/// fn ex(d: Document) -> Vertex {
+///     d.into()
 /// }
 /// ```
 #[macro_export]
@@ -364,13 +639,28 @@ macro_rules! impl_from_variant {
 
 impl_from_variant!(Project, Vertex);
 impl_from_variant!(Document, Vertex);
-impl_from_variant!(Range, Vertex);
 impl_from_variant!(ResultSet, Vertex);
 impl_from_variant!(MetaData, Vertex);
 impl_from_variant!(ReferenceResult, Vertex);
 impl_from_variant!(DefinitionResult, Vertex);
+impl_from_variant!(DeclarationResult, Vertex);
+impl_from_variant!(ImplementationResult, Vertex);
 impl_from_variant!(HoverResult, Vertex);
 impl_from_variant!(Moniker, Vertex);
+impl_from_variant!(PackageInformation, Vertex);
+impl_from_variant!(DiagnosticResult, Vertex);
+
+impl From<RangeVertex> for Vertex {
+    fn from(v: RangeVertex) -> Vertex {
+        Vertex::Range(v)
+    }
+}
+
+impl From<Range> for Vertex {
+    fn from(v: Range) -> Vertex {
+        Vertex::Range(v.into())
+    }
+}
 
 #[derive(Debug, PartialEq, Serialize, Deserialize)]
 #[serde(untagged)]
@@ -1,16 +1,105 @@
-use crate::{analyzer::ffi::query_for_language, protocol::types::Language};
+use std::sync::Arc;
 
-/// Tests whether the query files are valid
+use crate::{
+    analyzer::ffi::{query_for_language, ts_language_from},
+    protocol::types::Language,
+};
+
+#[path = "../build/query_check.rs"]
+mod query_check;
+
+use query_check::{check_query, Grammar};
+
+/// Tests whether the query files of the languages whose grammar is compiled in are valid
 #[test]
 fn test_query_files() {
-    for lang in [
-        Language::GraphQL,
-        Language::Java,
-        Language::JavaScript,
-        Language::TypeScript,
-    ]
-    .iter()
-    {
-        query_for_language(lang).unwrap();
+    for lang in Language::all() {
+        if ts_language_from(lang).is_err() {
+            assert_eq!(*lang, Language::Lua);
+            continue;
+        }
+        query_for_language(lang, &lang.get_query_source()).unwrap();
+    }
+}
+
+/// Tests whether a query is only compiled once for the same language and source
+#[test]
+fn test_queries_are_cached() {
+    let lang = Language::GraphQL;
+    let first = query_for_language(&lang, &lang.get_query_source()).unwrap();
+    let second = query_for_language(&lang, &lang.get_query_source()).unwrap();
+    assert!(Arc::ptr_eq(&first, &second));
+    assert_eq!(first.pattern_count(), second.pattern_count());
+
+    // Another source is another query
+    let other = query_for_language(&lang, "(comment) @comment").unwrap();
+    assert!(!Arc::ptr_eq(&first, &other));
+    assert_eq!(other.pattern_count(), 1);
+}
+
+/// A grammar with a `statement` of a `name` field and an optional `;`, and `comment` extras
+fn test_grammar() -> Grammar {
+    Grammar::from_json(&serde_json::json!({
+        "rules": {
+            "program": { "type": "REPEAT", "content": { "type": "SYMBOL", "name": "statement" } },
+            "statement": {
+                "type": "SEQ",
+                "members": [
+                    { "type": "FIELD", "name": "name", "content": { "type": "SYMBOL", "name": "identifier" } },
+                    { "type": "CHOICE", "members": [{ "type": "STRING", "value": ";" }, { "type": "BLANK" }] },
+                    { "type": "CHOICE", "members": [{ "type": "STRING", "value": "\"" }, { "type": "BLANK" }] }
+                ]
+            },
+            "identifier": { "type": "PATTERN", "value": "[a-z]+" }
+        },
+        "externals": [{ "type": "SYMBOL", "name": "comment" }]
+    }))
+}
+
+/// Tests whether the valid queries pass the build-time check
+#[test]
+fn test_check_valid_queries() {
+    let grammar = test_grammar();
+    for query in &[
+        "(statement name: (identifier) @name)",
+        // Predicates, whose arguments are not node types
+        "((identifier) @x (#eq? @x \"self\"))",
+        "((identifier) @x (#match? @x \"^[A-Z](foo|\\\"bar)\"))",
+        // Negated predicates
+        "((identifier) @x (#not-eq? @x \"self\"))",
+        "((identifier) @x (#not-match? @x \"^_\"))",
+        // Quoted anonymous nodes, including an escaped quote
+        "(statement \";\" @semicolon)",
+        "(statement \"\\\"\" @quote)",
+        // Alternations, quantifiers, wildcards, extras and comments
+        "[(identifier) (comment)] @x\n; (unknown (\n(program (statement)* @s)",
+        "(program (_)+ @any (ERROR)? @error)",
+    ] {
+        assert!(check_query(query, &grammar).is_ok(), "{}", query);
+    }
+}
+
+/// Tests whether the invalid queries fail the build-time check with the position of the
+/// error
+#[test]
+fn test_check_invalid_queries() {
+    let grammar = test_grammar();
+    for (query, message, kind, line, column) in &[
+        ("(statement)\n(unknown)", "unknown", "NodeType", 2, 2),
+        ("(statement value: (identifier))", "value", "Field", 1, 12),
+        ("(statement \"+\")", "+", "NodeType", 1, 12),
+        // A node type outside of parentheses
+        ("(statement identifier)", "identifier", "Syntax", 1, 12),
+        ("(statement (identifier)", "(", "Syntax", 1, 1),
+        ("(statement))", ")", "Syntax", 1, 12),
+        ("[(identifier))", ")", "Syntax", 1, 14),
+    ] {
+        let error = check_query(query, &grammar).err().unwrap();
+        assert_eq!(
+            (error.message.as_str(), error.kind, error.line, error.column),
+            (*message, *kind, *line, *column),
+            "{}",
+            query
+        );
     }
 }
@@ -1,18 +1,29 @@
 use std::{
-    path::PathBuf,
-    sync::mpsc::{channel, Sender},
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender},
+    },
 };
 
 use languageserver_types::{NumberOrString, Url};
 use protocol::types::Range;
+use serde_json::Value;
 
 use crate::{
-    cli::Opts,
-    emitter::emitter::Emitter,
+    cli::{Opts, DEFAULT_TODO_MARKERS},
+    emitter::{emitter::Emitter, file_emitter::OutputFormat},
     indexer::indexer::Indexer,
     protocol::{
         self,
-        types::{Edge, Element, Language, Vertex, ID},
+        schema::entry_schema,
+        types::{
+            Diagnostic, Edge, EdgeData, Element, Language, MetaData, Moniker, PackageInformation,
+            Project, RangeVertex, ReferenceAccess, Vertex, DEFAULT_LSIF_VERSION, ID,
+            MONIKER_SCHEME,
+        },
     },
 };
 
@@ -32,36 +43,151 @@ pub fn project_root_uri() -> Url {
     protocol::types::Url::from_file_path(project_root()).unwrap()
 }
 
-/// Indexes the test data of the given language and returns the LSIF elements found.
-/// Each LSIF element corresponds to a line emitted in an LSIF dump.
-pub fn get_elements(lang: Language) -> Elements {
-    let (tx, rx) = channel();
-    let emitter = TestsEmitter {
-        elements: Default::default(),
-        tx,
-        id: 0,
-    };
-
-    let opts = Opts {
+/// Returns the default options for indexing the test data of the given language.
+pub fn test_opts(lang: Language) -> Opts {
+    Opts {
         project_root: PathBuf::from(format!(
             "{}/src/tests/test_data/{}",
             project_root(),
             lang.to_string()
         )),
-        language: lang,
+        language: lang.into(),
         output: None,
-    };
+        out_dir: None,
+        output_format: OutputFormat::Ndjson,
+        buffer_size: None,
+        incremental: None,
+        lsif_version: DEFAULT_LSIF_VERSION.to_string(),
+        validate_graph: false,
+        stats: false,
+        stats_file: None,
+        report_unresolved: None,
+        emit_external_monikers: false,
+        timings: None,
+        files_from: None,
+        git_ref: None,
+        since: None,
+        follow_symlinks: false,
+        include_hidden: false,
+        exclude: vec![],
+        language_override: vec![],
+        max_file_size: None,
+        parse_timeout_ms: None,
+        threads: None,
+        dry_run: false,
+        relative_paths: false,
+        query_dir: None,
+        no_diagnostics: false,
+        todo_comments: false,
+        todo_markers: DEFAULT_TODO_MARKERS.split(',').map(String::from).collect(),
+        no_hover: false,
+        definitions_only: false,
+        emit_byte_offsets: false,
+        max_import_depth: 8,
+        treat_tests_as_local: false,
+        test_glob: vec![],
+        moniker_scheme: MONIKER_SCHEME.to_string(),
+        project_name: None,
+        append: false,
+        config: None,
+        quiet: false,
+        verbose: 0,
+        args: vec![],
+        appended_after_id: 0,
+    }
+}
+
+/// Indexes the test data of the given language and returns the LSIF elements found.
+/// Each LSIF element corresponds to a line emitted in an LSIF dump.
+pub fn get_elements(lang: Language) -> Elements {
+    get_elements_with_opts(test_opts(lang))
+}
+
+/// Indexes a project with the given options and returns the LSIF elements found.
+pub fn get_elements_with_opts(opts: Opts) -> Elements {
+    let (emitter, rx) = TestsEmitter::new();
 
     Indexer::index(opts, emitter).unwrap();
 
     rx.recv().unwrap()
 }
 
+/// A directory for the files of a test, e.g. a project to index, with a unique path. It is
+/// removed with its content when dropped, even if the test panics.
+pub struct TestDir {
+    path: PathBuf,
+}
+
+impl TestDir {
+    /// Creates an empty directory whose name starts with `lsif-os-<name>`. Its path is
+    /// canonical, like the paths of the indexed files.
+    pub fn new(name: &str) -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "lsif-os-{}-{}-{}",
+            name,
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        let _ = fs::remove_dir_all(&path);
+        fs::create_dir_all(&path).unwrap();
+        Self {
+            path: path.canonicalize().unwrap(),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the path of the given path relative to the directory.
+    pub fn join(&self, relative: impl AsRef<Path>) -> PathBuf {
+        self.path.join(relative)
+    }
+
+    /// Writes the given content to the file at the given path relative to the directory,
+    /// creating its parent directories, and returns the path of the file.
+    pub fn write(&self, relative: &str, content: impl AsRef<[u8]>) -> PathBuf {
+        let path = self.join(relative);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Returns the URI of the document of the file at the given path relative to the
+    /// directory.
+    pub fn uri(&self, relative: &str) -> String {
+        Url::from_file_path(self.join(relative))
+            .unwrap()
+            .to_string()
+    }
+
+    /// Returns the test options to index the directory as a project of the given language.
+    pub fn opts(&self, lang: Language) -> Opts {
+        Opts {
+            project_root: self.path.clone(),
+            ..test_opts(lang)
+        }
+    }
+
+    /// Indexes the directory as a project of the given language and returns the LSIF
+    /// elements found.
+    pub fn index(&self, lang: Language) -> Elements {
+        get_elements_with_opts(self.opts(lang))
+    }
+}
+
+impl Drop for TestDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.path);
+    }
+}
+
 impl Elements {
     /// Returns the range in the given file with the given start line and character.
     pub fn find_range(&self, filename: &str, line_char: (u64, u64)) -> Option<(Range, ID)> {
         for (v, id) in self.vertices() {
-            if let Vertex::Range(r) = v {
+            if let Vertex::Range(RangeVertex { range: r, .. }) = v {
                 if r.start.line == line_char.0 && r.start.character == line_char.1 {
                     if &self.find_document_uri_containing(id)? == filename {
                         return Some((r.clone(), id));
@@ -74,7 +200,7 @@ impl Elements {
 
     fn find_range_by_id(&self, target_id: ID) -> Option<Range> {
         for (v, id) in self.vertices() {
-            if let Vertex::Range(r) = v {
+            if let Vertex::Range(RangeVertex { range: r, .. }) = v {
                 if id == target_id {
                     return Some(r.clone());
                 }
@@ -107,8 +233,44 @@ impl Elements {
         ranges
     }
 
-    /// Returns the ranges attached to the definition result with the given
-    /// identifier.
+    /// Returns the declaration ranges attached to the range or result set
+    /// with the given identifier.
+    pub fn find_declaration_ranges(&self, id: ID) -> Vec<Range> {
+        self.find_method_ranges(id, |e| match e {
+            Edge::Declaration(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// Returns the implementation ranges attached to the range or result set
+    /// with the given identifier.
+    pub fn find_implementation_ranges(&self, id: ID) -> Vec<Range> {
+        self.find_method_ranges(id, |e| match e {
+            Edge::Implementation(data) => Some(data),
+            _ => None,
+        })
+    }
+
+    /// Returns the ranges of the results attached, with the method edges selected by the
+    /// given function, to the range or result set with the given identifier.
+    fn find_method_ranges(&self, id: ID, method: fn(&Edge) -> Option<&EdgeData>) -> Vec<Range> {
+        let mut ranges = Vec::new();
+        for (e, _) in self.edges() {
+            match (e, method(e)) {
+                (_, Some(data)) if to_number(&data.out_v) == id => {
+                    ranges.extend(self.find_definition_ranges_by_result_id(to_number(&data.in_v)));
+                }
+                (Edge::Next(next), _) if to_number(&next.out_v) == id => {
+                    ranges.extend(self.find_method_ranges(to_number(&next.in_v), method));
+                }
+                _ => {}
+            }
+        }
+
+        ranges
+    }
+
+    /// Returns the ranges attached to the result with the given identifier.
     fn find_definition_ranges_by_result_id(&self, id: ID) -> Vec<Range> {
         let mut ranges = Vec::new();
         for (e, _) in self.edges() {
@@ -130,6 +292,33 @@ impl Elements {
         ranges
     }
 
+    /// Returns the number of times the vertex with the given id appears as an
+    /// in-vertex of a reference `item` edge.
+    pub fn count_reference_items(&self, id: ID) -> usize {
+        self.edges()
+            .into_iter()
+            .filter_map(|(e, _)| match e {
+                Edge::Item(protocol::types::Item::Reference(item)) => Some(item),
+                _ => None,
+            })
+            .flat_map(|item| item.in_vs.iter())
+            .filter(|in_v| to_number(in_v) == id)
+            .count()
+    }
+
+    /// Returns the access of each reference item that has the range with the given id.
+    pub fn reference_accesses(&self, id: ID) -> Vec<Option<ReferenceAccess>> {
+        self.edges()
+            .into_iter()
+            .filter_map(|(e, _)| match e {
+                Edge::Item(protocol::types::Item::Reference(item)) => Some(item),
+                _ => None,
+            })
+            .filter(|item| item.in_vs.iter().any(|in_v| to_number(in_v) == id))
+            .map(|item| item.access)
+            .collect()
+    }
+
     /// Returns the URI of the document that contains the vertex with the given id.
     pub fn find_document_uri_containing(&self, id: ID) -> Option<String> {
         for (e, _) in self.edges() {
@@ -144,6 +333,147 @@ impl Elements {
         None
     }
 
+    /// Returns the metadata vertex, if one was emitted.
+    pub fn metadata(&self) -> Option<&MetaData> {
+        self.vertices().into_iter().find_map(|(v, _)| match v {
+            Vertex::MetaData(m) => Some(m),
+            _ => None,
+        })
+    }
+
+    /// Returns the project vertex and its id, if one was emitted.
+    pub fn project(&self) -> Option<(&Project, ID)> {
+        self.vertices().into_iter().find_map(|(v, id)| match v {
+            Vertex::Project(p) => Some((p, id)),
+            _ => None,
+        })
+    }
+
+    /// Returns the ids of the vertices that the vertex with the given id contains.
+    pub fn contained_in(&self, id: ID) -> Vec<ID> {
+        self.edges()
+            .into_iter()
+            .filter_map(|(e, _)| match e {
+                Edge::Contains(c) if to_number(&c.out_v) == id => Some(c),
+                _ => None,
+            })
+            .flat_map(|c| c.in_vs.iter().map(to_number))
+            .collect()
+    }
+
+    /// Returns the ids of the result sets that the range with the given id refers to.
+    pub fn refers_to(&self, id: ID) -> Vec<ID> {
+        self.edges()
+            .into_iter()
+            .filter_map(|(e, _)| match e {
+                Edge::RefersTo(data) if to_number(&data.out_v) == id => Some(to_number(&data.in_v)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the value of the first marked string of each hover result vertex.
+    pub fn hover_values(&self) -> Vec<String> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, _)| match v {
+                Vertex::HoverResult(h) => Some(h.result.contents[0].value.clone()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the ids of the hover result vertices and the number of `hover` edges to each.
+    pub fn hover_results(&self) -> HashMap<ID, usize> {
+        let mut hover_results: HashMap<ID, usize> = self
+            .vertices()
+            .into_iter()
+            .filter_map(|(v, id)| match v {
+                Vertex::HoverResult(_) => Some((id, 0)),
+                _ => None,
+            })
+            .collect();
+        for (e, _) in self.edges() {
+            if let Edge::Hover(data) = e {
+                *hover_results.entry(to_number(&data.in_v)).or_default() += 1;
+            }
+        }
+        hover_results
+    }
+
+    /// Returns all the moniker vertices.
+    pub fn monikers(&self) -> Vec<&Moniker> {
+        self.monikers_with_ids()
+            .into_iter()
+            .map(|(m, _)| m)
+            .collect()
+    }
+
+    /// Returns the moniker vertices and their ids.
+    pub fn monikers_with_ids(&self) -> Vec<(&Moniker, ID)> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, id)| match v {
+                Vertex::Moniker(m) => Some((m, id)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the package information vertices and their ids.
+    pub fn package_informations(&self) -> Vec<(&PackageInformation, ID)> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, id)| match v {
+                Vertex::PackageInformation(p) => Some((p, id)),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the id of the package information vertex of the moniker with the given id.
+    pub fn package_of_moniker(&self, moniker_id: ID) -> Option<ID> {
+        self.edges().into_iter().find_map(|(e, _)| match e {
+            Edge::PackageInformation(d) if to_number(&d.out_v) == moniker_id => {
+                Some(to_number(&d.in_v))
+            }
+            _ => None,
+        })
+    }
+
+    /// Returns the URIs of all the documents.
+    pub fn document_uris(&self) -> Vec<String> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, _)| match v {
+                Vertex::Document(d) => Some(d.uri.to_string()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns all the range vertices.
+    pub fn range_vertices(&self) -> Vec<&RangeVertex> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, _)| match v {
+                Vertex::Range(r) => Some(r),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Returns the URI and the language of each document.
+    pub fn document_languages(&self) -> Vec<(String, String)> {
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, _)| match v {
+                Vertex::Document(d) => Some((d.uri.to_string(), d.language_id.clone())),
+                _ => None,
+            })
+            .collect()
+    }
+
     /// Returns the URI of the document with the given id.
     pub fn find_uri_by_document_id(&self, target_id: ID) -> Option<String> {
         for (v, id) in self.vertices() {
@@ -156,6 +486,37 @@ impl Elements {
 
         None
     }
+
+    /// Returns the diagnostics of the document with the given URI.
+    pub fn diagnostics_of(&self, uri: &str) -> Vec<Diagnostic> {
+        let document_ids: Vec<ID> = self
+            .vertices()
+            .into_iter()
+            .filter_map(|(v, id)| match v {
+                Vertex::Document(d) if d.uri == uri => Some(id),
+                _ => None,
+            })
+            .collect();
+        let result_ids: Vec<ID> = self
+            .edges()
+            .into_iter()
+            .filter_map(|(e, _)| match e {
+                Edge::Diagnostic(d) if document_ids.contains(&to_number(&d.out_v)) => {
+                    Some(to_number(&d.in_v))
+                }
+                _ => None,
+            })
+            .collect();
+
+        self.vertices()
+            .into_iter()
+            .filter_map(|(v, id)| match v {
+                Vertex::DiagnosticResult(r) if result_ids.contains(&id) => Some(r.result.clone()),
+                _ => None,
+            })
+            .flatten()
+            .collect()
+    }
 }
 
 #[derive(Debug)]
@@ -187,6 +548,25 @@ impl Elements {
             })
             .collect()
     }
+
+    /// Returns the elements as they are written in a dump, one JSON value per entry.
+    pub fn to_json(&self) -> Vec<Value> {
+        self.0
+            .iter()
+            .map(|e| {
+                serde_json::to_value(protocol::types::Entry {
+                    id: NumberOrString::Number(e.id),
+                    data: e.element.clone(),
+                })
+                .unwrap()
+            })
+            .collect()
+    }
+}
+
+/// Returns a validator of the entries against `protocol::schema::entry_schema`.
+pub fn entry_validator() -> jsonschema::Validator {
+    jsonschema::validator_for(&entry_schema()).unwrap()
 }
 
 fn to_number(n: &NumberOrString) -> ID {
@@ -205,6 +585,18 @@ pub struct TestsEmitter {
 }
 
 impl TestsEmitter {
+    /// Creates a new `TestsEmitter` and a `Receiver` that receives the emitted
+    /// elements once `end` is called.
+    pub fn new() -> (Self, Receiver<Elements>) {
+        let (tx, rx) = channel();
+        let emitter = TestsEmitter {
+            elements: Default::default(),
+            tx,
+            id: 0,
+        };
+        (emitter, rx)
+    }
+
     fn next_id(&mut self) -> ID {
         self.id += 1;
         self.id
@@ -8,10 +8,10 @@ mod helpers;
 
 mod typescript {
     use super::{
-        assert_definition,
-        helpers::{get_elements, project_root_uri},
+        assert_definition, assert_definition_in,
+        helpers::{get_elements, project_root_uri, TestDir},
     };
-    use crate::protocol::types::Language;
+    use crate::protocol::types::{Language, ReferenceAccess};
 
     #[test]
     fn test_def_var() {
@@ -30,23 +30,2088 @@ mod typescript {
         let elements = get_elements(Language::TypeScript);
         assert_definition(&elements, "TypeScript/index.ts", (5, 11), (4, 15)).unwrap();
     }
+
+    #[test]
+    fn test_def_shadowing_param() {
+        let elements = get_elements(Language::TypeScript);
+        assert_definition(&elements, "TypeScript/shadowing.ts", (1, 11), (0, 18)).unwrap();
+    }
+
+    #[test]
+    fn test_def_param_does_not_leak() {
+        let elements = get_elements(Language::TypeScript);
+        assert_definition(&elements, "TypeScript/shadowing.ts", (21, 11), (20, 19)).unwrap();
+        // The parameter is only in the scope of the function, not in the one of its name
+        assert_definition(&elements, "TypeScript/shadowing.ts", (24, 12), (18, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_def_shadowing_inner_function() {
+        let elements = get_elements(Language::TypeScript);
+        assert_definition(&elements, "TypeScript/shadowing.ts", (7, 11), (9, 13)).unwrap();
+    }
+
+    #[test]
+    fn test_def_hoisted_function() {
+        let elements = get_elements(Language::TypeScript);
+        // The inner `helper` is declared after the call but shadows the outer one
+        assert_definition(&elements, "TypeScript/hoisting.ts", (5, 11), (7, 13)).unwrap();
+        assert_definition(&elements, "TypeScript/hoisting.ts", (5, 22), (12, 9)).unwrap();
+        // A `var` is visible in its whole function, not only in its block
+        assert_definition(&elements, "TypeScript/hoisting.ts", (21, 11), (18, 12)).unwrap();
+        assert!(
+            assert_definition(&elements, "TypeScript/hoisting.ts", (21, 21), (20, 12)).is_err()
+        );
+    }
+
+    #[test]
+    fn test_enum_members() {
+        let elements = get_elements(Language::TypeScript);
+        assert_definition(&elements, "TypeScript/enums.ts", (9, 16), (0, 5)).unwrap();
+        assert_definition(&elements, "TypeScript/enums.ts", (9, 24), (0, 5)).unwrap();
+        // Members are resolved within the enum of their receiver
+        assert_definition(&elements, "TypeScript/enums.ts", (9, 30), (1, 4)).unwrap();
+        assert_definition(&elements, "TypeScript/enums.ts", (10, 22), (6, 4)).unwrap();
+        assert_definition(&elements, "TypeScript/enums.ts", (10, 34), (2, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_function_full_range() {
+        let entries = get_elements(Language::TypeScript).to_json();
+        let tag = entries
+            .iter()
+            .find(|e| e["label"] == "range" && e["tag"]["text"] == "later")
+            .map(|e| &e["tag"])
+            .unwrap();
+
+        // The full range spans the whole declaration, from `function` to the closing brace
+        assert_eq!(tag["type"], "definition");
+        assert_eq!(tag["kind"], 12);
+        assert_eq!(tag["fullRange"]["start"]["line"], 12);
+        assert_eq!(tag["fullRange"]["start"]["character"], 0);
+        assert_eq!(tag["fullRange"]["end"]["line"], 14);
+        assert_eq!(tag["fullRange"]["end"]["character"], 1);
+    }
+
+    #[test]
+    fn test_jsx_component_refs() {
+        let elements = get_elements(Language::TypeScript);
+        // Self-closing, opening and closing tags
+        assert_definition(&elements, "TypeScript/components.tsx", (5, 17), (0, 9)).unwrap();
+        assert_definition(&elements, "TypeScript/components.tsx", (5, 38), (0, 9)).unwrap();
+        assert_definition(&elements, "TypeScript/components.tsx", (5, 58), (0, 9)).unwrap();
+    }
+
+    #[test]
+    fn test_tsx_language_id() {
+        let elements = get_elements(Language::TypeScript);
+        let language_of = |name: &str| {
+            let uri = format!(
+                "{}/src/tests/test_data/TypeScript/{}",
+                project_root_uri(),
+                name
+            );
+            elements
+                .document_languages()
+                .into_iter()
+                .find(|(document, _)| *document == uri)
+                .map(|(_, language)| language)
+        };
+        assert_eq!(
+            language_of("components.tsx").as_deref(),
+            Some("typescriptreact")
+        );
+        assert_eq!(language_of("index.ts").as_deref(), Some("typescript"));
+        assert_eq!(language_of("component.vue").as_deref(), Some("vue"));
+    }
+
+    #[test]
+    fn test_enum_members_are_not_visible_by_name() {
+        let root = TestDir::new("enum-member-name");
+        root.write(
+            "index.ts",
+            "enum Color {\n    Red,\n}\n\nRed;\nColor.Red;\n",
+        );
+
+        let elements = root.index(Language::TypeScript);
+        let file_uri = root.uri("index.ts");
+        assert!(assert_definition_in(&elements, &file_uri, (4, 0), (1, 4)).is_err());
+        assert_definition_in(&elements, &file_uri, (5, 6), (1, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_def_after_multi_byte_content() {
+        let elements = get_elements(Language::TypeScript);
+        assert_definition(&elements, "TypeScript/unicode.ts", (2, 12), (1, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_utf16_columns() {
+        let elements = get_elements(Language::TypeScript);
+        // `🎉` is 2 code units in UTF-16, but 4 bytes in UTF-8
+        assert_definition(&elements, "TypeScript/utf16.ts", (0, 25), (0, 4)).unwrap();
+        assert_definition(&elements, "TypeScript/utf16.ts", (1, 12), (0, 21)).unwrap();
+        // `é` is 1 code unit, but 2 bytes
+        assert_definition(&elements, "TypeScript/utf16.ts", (1, 15), (0, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_aliased_import() {
+        let elements = get_elements(Language::TypeScript);
+        let file_uri = format!(
+            "{}/src/tests/test_data/TypeScript/alias.ts",
+            project_root_uri()
+        );
+        let (_range, id) = elements.find_range(&file_uri, (0, 17)).unwrap();
+
+        let result_set_id = elements.refers_to(id)[0];
+        let original = elements.find_definition_ranges(result_set_id)[0];
+        assert_eq!((original.start.line, original.start.character), (0, 16));
+        assert_definition(&elements, "TypeScript/alias.ts", (2, 0), (0, 17)).unwrap();
+    }
+
+    #[test]
+    fn test_destructuring() {
+        let elements = get_elements(Language::TypeScript);
+        let file = "TypeScript/destructuring.ts";
+        // `a`, `b`, `c`, `d`, `renamed`, `f`, `rest`, `g`, `h` and `others`
+        let bindings = [
+            ((5, 12), (1, 8)),
+            ((5, 15), (1, 11)),
+            ((5, 18), (2, 7)),
+            ((5, 21), (2, 10)),
+            ((5, 24), (3, 11)),
+            ((5, 33), (3, 20)),
+            ((5, 36), (3, 30)),
+            ((5, 42), (4, 7)),
+            ((5, 45), (4, 17)),
+            ((5, 48), (4, 24)),
+        ];
+        for (reference, definition) in bindings.iter() {
+            assert_definition(&elements, file, *reference, *definition).unwrap();
+        }
+
+        // Property keys are not bindings
+        let file_uri = format!("{}/src/tests/test_data/{}", project_root_uri(), file);
+        assert!(elements.find_range(&file_uri, (3, 8)).is_none());
+    }
+
+    #[test]
+    fn test_vue_component() {
+        let elements = get_elements(Language::TypeScript);
+        let file = "TypeScript/component.vue";
+        // `Props`, `greet` and `message`, at the positions of the whole file
+        assert_definition(&elements, file, (7, 22), (5, 5)).unwrap();
+        assert_definition(&elements, file, (11, 16), (7, 9)).unwrap();
+        assert_definition(&elements, file, (12, 12), (11, 6)).unwrap();
+
+        // The template and the style are not parsed
+        let file_uri = format!("{}/src/tests/test_data/{}", project_root_uri(), file);
+        assert!(elements.find_range(&file_uri, (1, 10)).is_none());
+        assert!(elements.find_range(&file_uri, (16, 0)).is_none());
+    }
+
+    #[test]
+    fn test_declaration() {
+        let elements = get_elements(Language::TypeScript);
+        let file_uri = format!(
+            "{}/src/tests/test_data/TypeScript/declarations.d.ts",
+            project_root_uri()
+        );
+        let (_range, id) = elements.find_range(&file_uri, (1, 17)).unwrap();
+
+        let decl = elements.find_declaration_ranges(id)[0];
+        assert_eq!((decl.start.line, decl.start.character), (1, 17));
+        assert!(elements.find_definition_ranges(id).is_empty());
+    }
+
+    #[test]
+    fn test_declaration_mirrors_definition() {
+        let elements = get_elements(Language::TypeScript);
+        let file_uri = format!(
+            "{}/src/tests/test_data/TypeScript/index.ts",
+            project_root_uri()
+        );
+        let (_range, id) = elements.find_range(&file_uri, (0, 4)).unwrap();
+
+        let decls = elements.find_declaration_ranges(id);
+        assert!(!decls.is_empty());
+        assert_eq!(decls, elements.find_definition_ranges(id));
+    }
+
+    #[test]
+    fn test_declaration_implementation() {
+        let elements = get_elements(Language::TypeScript);
+        let file_uri = format!(
+            "{}/src/tests/test_data/TypeScript/math.d.ts",
+            project_root_uri()
+        );
+        let (_range, id) = elements.find_range(&file_uri, (1, 24)).unwrap();
+
+        assert!(elements.find_definition_ranges(id).is_empty());
+        let implementation = elements.find_implementation_ranges(id)[0];
+        assert_eq!(
+            (implementation.start.line, implementation.start.character),
+            (0, 16)
+        );
+    }
+
+    #[test]
+    fn test_def_range_narrowed_to_name() {
+        let elements = get_elements(Language::TypeScript);
+        let file_uri = format!(
+            "{}/src/tests/test_data/TypeScript/types.ts",
+            project_root_uri()
+        );
+        let (_range, id) = elements.find_range(&file_uri, (4, 12)).unwrap();
+
+        let def_range = elements.find_definition_ranges(id)[0];
+        assert_eq!((def_range.start.line, def_range.start.character), (0, 5));
+        assert_eq!((def_range.end.line, def_range.end.character), (0, 10));
+    }
+
+    #[test]
+    fn test_reference_access() {
+        let root = TestDir::new("reference-access");
+        root.write(
+            "index.ts",
+            "function update() {\n    let x = 1;\n    x = 5;\n    return x;\n}\n",
+        );
+
+        let elements = root.index(Language::TypeScript);
+
+        let file_uri = root.uri("index.ts");
+        let (_range, write_id) = elements.find_range(&file_uri, (2, 4)).unwrap();
+        let (_range, read_id) = elements.find_range(&file_uri, (3, 11)).unwrap();
+        assert_eq!(
+            elements.reference_accesses(write_id),
+            vec![Some(ReferenceAccess::Write)]
+        );
+        assert_eq!(
+            elements.reference_accesses(read_id),
+            vec![Some(ReferenceAccess::Read)]
+        );
+    }
+
+    #[test]
+    fn test_member_reference() {
+        let root = TestDir::new("member-reference");
+        root.write(
+            "index.ts",
+            "const obj = {\n    method() {\n        return 1;\n    },\n    value: 2,\n};\n\
+             function method() {}\n\
+             obj.method();\n\
+             method(obj.value);\n\
+             value;\n",
+        );
+
+        let elements = root.index(Language::TypeScript);
+
+        let file_uri = root.uri("index.ts");
+        assert_definition_in(&elements, &file_uri, (7, 4), (1, 4)).unwrap();
+        assert_definition_in(&elements, &file_uri, (8, 0), (6, 9)).unwrap();
+        assert_definition_in(&elements, &file_uri, (8, 11), (4, 4)).unwrap();
+        // The key of an object literal is only found through the object
+        assert!(assert_definition_in(&elements, &file_uri, (9, 0), (4, 4)).is_err());
+    }
+
+    #[test]
+    fn test_single_reference_item() {
+        let elements = get_elements(Language::TypeScript);
+        let (_range, id) = elements
+            .find_range(
+                &format!(
+                    "{}/src/tests/test_data/TypeScript/index.ts",
+                    project_root_uri()
+                ),
+                (8, 0),
+            )
+            .unwrap();
+        assert_eq!(elements.count_reference_items(id), 1);
+    }
+
+    #[test]
+    fn test_latin1_file() {
+        let root = TestDir::new("latin1");
+        // `é` is a single byte in Latin-1, which is not valid UTF-8
+        root.write(
+            "index.ts",
+            b"// Caf\xe9\nlet x = 'r\xe9sum\xe9';\nconsole.log(x);\n".as_ref(),
+        );
+
+        let elements = root.index(Language::TypeScript);
+
+        let file_uri = root.uri("index.ts");
+        assert_definition_in(&elements, &file_uri, (2, 12), (1, 4)).unwrap();
+    }
+}
+
+mod metadata {
+    use super::helpers::{
+        entry_validator, get_elements_with_opts, test_opts, TestDir, TestsEmitter,
+    };
+    use crate::{
+        indexer::indexer::Indexer,
+        protocol::types::{Language, ToolInfo, DEFAULT_LSIF_VERSION},
+    };
+
+    #[test]
+    fn test_document_without_ranges() {
+        let root = TestDir::new("document-without-ranges");
+        root.write("a.ts", "let x = 1;\n");
+        root.write("empty.ts", "// Nothing to see here\n");
+
+        let elements = root.index(Language::TypeScript);
+
+        let (_project, project_id) = elements.project().unwrap();
+        let documents = elements.contained_in(project_id);
+        assert_eq!(documents.len(), 2);
+        for document_id in documents {
+            let uri = elements.find_uri_by_document_id(document_id).unwrap();
+            let ranges = elements.contained_in(document_id);
+            if uri.ends_with("empty.ts") {
+                assert!(ranges.is_empty());
+            } else {
+                assert!(!ranges.is_empty());
+            }
+        }
+        // Each contains edge has at least one in-vertex
+        for entry in elements.to_json() {
+            if entry["label"] == "contains" {
+                assert!(!entry["inVs"].as_array().unwrap().is_empty(), "{}", entry);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_project() {
+        let root = TestDir::new("empty-project");
+        root.write("README.md", "No TypeScript here\n");
+
+        let elements = root.index(Language::TypeScript);
+
+        assert!(elements.metadata().is_some());
+        let (_project, project_id) = elements.project().unwrap();
+        assert!(elements.contained_in(project_id).is_empty());
+        let entries = elements.to_json();
+        assert_eq!(entries.len(), 2);
+        let validator = entry_validator();
+        for entry in entries {
+            assert!(validator.is_valid(&entry), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_project_name() {
+        let elements = get_elements_with_opts(test_opts(Language::TypeScript));
+        let (project, _) = elements.project().unwrap();
+        assert_eq!(project.name, "TypeScript");
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.project_name = Some("my-project".to_string());
+        let elements = get_elements_with_opts(opts);
+        let project = elements
+            .to_json()
+            .into_iter()
+            .find(|entry| entry["label"] == "project")
+            .unwrap();
+        assert_eq!(project["name"], "my-project");
+    }
+
+    #[test]
+    fn test_default_lsif_version() {
+        let elements = get_elements_with_opts(test_opts(Language::TypeScript));
+        let metadata = elements.metadata().unwrap();
+        assert_eq!(metadata.version, DEFAULT_LSIF_VERSION);
+    }
+
+    #[test]
+    fn test_configured_lsif_version() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.lsif_version = "0.4.3".to_string();
+        let elements = get_elements_with_opts(opts);
+
+        let json = serde_json::to_value(elements.metadata().unwrap()).unwrap();
+        assert_eq!(json["version"], "0.4.3");
+    }
+
+    #[test]
+    fn test_tool_info() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.args = vec!["lsif-os".to_string(), "typescript".to_string()];
+        let elements = get_elements_with_opts(opts);
+
+        let json = serde_json::to_value(elements.metadata().unwrap()).unwrap();
+        assert_eq!(json["toolInfo"]["name"], "lsif-os");
+        assert_eq!(json["toolInfo"]["version"], env!("CARGO_PKG_VERSION"));
+        assert_eq!(json["toolInfo"]["args"][1], "typescript");
+    }
+
+    #[test]
+    fn test_custom_tool_info() {
+        let (emitter, rx) = TestsEmitter::new();
+        let tool_info = ToolInfo::new("my-indexer", Some("2.0.0".to_string()));
+        Indexer::index_with_tool_info(test_opts(Language::TypeScript), emitter, tool_info).unwrap();
+        let elements = rx.recv().unwrap();
+
+        let json = serde_json::to_value(elements.metadata().unwrap()).unwrap();
+        assert_eq!(json["toolInfo"]["name"], "my-indexer");
+        assert_eq!(json["toolInfo"]["version"], "2.0.0");
+    }
+
+    #[test]
+    fn test_project_vertex() {
+        let elements = get_elements_with_opts(test_opts(Language::TypeScript));
+        assert!(elements.metadata().is_some());
+
+        let (project, project_id) = elements.project().unwrap();
+        assert_eq!(project.language_id, Language::TypeScript);
+
+        let documents = elements.contained_in(project_id);
+        assert_eq!(documents.len(), elements.document_uris().len());
+        for id in documents {
+            assert!(elements.find_uri_by_document_id(id).is_some());
+        }
+    }
+
+    #[test]
+    fn test_relative_paths() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.relative_paths = true;
+        let elements = get_elements_with_opts(opts);
+
+        assert_eq!(elements.metadata().unwrap().project_root, ".");
+        let uris = elements.document_uris();
+        assert!(uris.contains(&"index.ts".to_string()));
+        assert!(uris.iter().all(|uri| !uri.starts_with("file://")));
+    }
+
+    #[test]
+    fn test_project_root_is_not_a_directory() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.project_root = opts.project_root.join("index.ts");
+        let error = opts.canonicalize_paths().unwrap_err();
+        assert!(
+            error
+                .to_string()
+                .ends_with("does not exist or is not a directory"),
+            "{}",
+            error
+        );
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.project_root = opts.project_root.join("missing");
+        assert!(opts.canonicalize_paths().is_err());
+    }
+
+    #[test]
+    fn test_unsupported_lsif_version() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.lsif_version = "0.1".to_string();
+        assert!(opts.validate().is_err());
+    }
+}
+
+mod file_emitter {
+    use std::{
+        collections::HashSet,
+        fs::{self, OpenOptions},
+        io::{self, Write},
+        path::Path,
+    };
+
+    use super::helpers::{test_opts, TestDir};
+    use crate::{
+        emitter::{
+            emitter::Emitter,
+            file_emitter::{last_id, wait_until_flushed, FileEmitter, OutputFormat},
+        },
+        indexer::indexer::Indexer,
+        protocol::{
+            graph::LsifGraph,
+            types::{Edge, Element, Entry, Language, NumberOrString, Position, ResultSet, Vertex},
+        },
+    };
+
+    #[test]
+    fn test_array_output_round_trip() {
+        let dir = TestDir::new("array-output");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let (emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Array);
+        Indexer::index(test_opts(Language::TypeScript), emitter).unwrap();
+        signal_receiver.recv().unwrap().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let entries = serde_json::from_str::<Vec<Entry>>(&content).unwrap();
+        assert!(matches!(
+            entries.first().map(|e| &e.data),
+            Some(Element::Vertex(Vertex::MetaData(_)))
+        ));
+    }
+
+    /// A writer that fails like a full disk.
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            Err(io::Error::other("No space left on device"))
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_io_error_is_reported() {
+        let (emitter, signal_receiver) = FileEmitter::with_writer(FullDisk, OutputFormat::Ndjson);
+        Indexer::index(test_opts(Language::TypeScript), emitter).unwrap();
+
+        let err = signal_receiver.recv().unwrap().unwrap_err();
+        assert_eq!(err.to_string(), "No space left on device");
+    }
+
+    /// A writer whose thread panics, like a bug in the emitter thread.
+    struct PanickingWriter;
+
+    impl Write for PanickingWriter {
+        fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+            panic!("The writer panicked");
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_emitter_thread_panic_is_reported() {
+        let (emitter, signal_receiver) =
+            FileEmitter::with_writer(PanickingWriter, OutputFormat::Ndjson);
+        Indexer::index(test_opts(Language::TypeScript), emitter).unwrap();
+
+        let err = wait_until_flushed(&signal_receiver).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "The emitter thread stopped before flushing the output"
+        );
+    }
+
+    #[test]
+    fn test_custom_buffer_capacity() {
+        let dir = TestDir::new("buffer-capacity");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        // A buffer smaller than an entry makes every entry written in several writes
+        let (mut emitter, signal_receiver) =
+            FileEmitter::with_capacity(file, OutputFormat::Ndjson, 8);
+        for _ in 0..100 {
+            emitter.emit_vertex(ResultSet {});
+        }
+        emitter.end();
+        wait_until_flushed(&signal_receiver).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let ids: Vec<NumberOrString> = content
+            .lines()
+            .map(|line| serde_json::from_str::<Entry>(line).unwrap().id)
+            .collect();
+        let expected: Vec<NumberOrString> = (1..=100).map(NumberOrString::Number).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_batched_entries_are_ordered() {
+        let dir = TestDir::new("batch");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let (emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Ndjson);
+        let mut emitter = emitter.with_batch_size(7);
+        for _ in 0..100 {
+            emitter.emit_vertex(ResultSet {});
+        }
+        emitter.end();
+        signal_receiver.recv().unwrap().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let ids: Vec<NumberOrString> = content
+            .lines()
+            .map(|line| serde_json::from_str::<Entry>(line).unwrap().id)
+            .collect();
+        let expected: Vec<NumberOrString> = (1..=100).map(NumberOrString::Number).collect();
+        assert_eq!(ids, expected);
+    }
+
+    #[test]
+    fn test_shutdown_before_end() {
+        let dir = TestDir::new("shutdown");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let (emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Ndjson);
+        let mut emitter = emitter.with_batch_size(10);
+        // The last 5 entries are not sent before the shutdown
+        for _ in 0..105 {
+            emitter.emit_vertex(ResultSet {});
+        }
+        emitter.shutdown_handle().shutdown();
+        // Emitting after a shutdown must not fail
+        emitter.emit_vertex(ResultSet {});
+        emitter.end();
+        // The dump is partial
+        assert!(wait_until_flushed(&signal_receiver).is_err());
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        assert!(content.ends_with('\n'));
+        let entries: Vec<Entry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 100);
+    }
+
+    #[test]
+    fn test_dropped_before_end() {
+        let dir = TestDir::new("dropped-emitter");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let (emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Ndjson);
+        let mut emitter = emitter.with_batch_size(10);
+        // The last 5 entries are never sent
+        for _ in 0..105 {
+            emitter.emit_vertex(ResultSet {});
+        }
+        drop(emitter);
+
+        let err = wait_until_flushed(&signal_receiver).unwrap_err();
+        assert!(err.to_string().contains("dropped"), "{}", err);
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().count(), 100);
+    }
+
+    #[test]
+    fn test_emit_raw_round_trip() {
+        let dir = TestDir::new("emit-raw");
+        let path = dir.join("dump.json");
+        let file = std::fs::File::create(&path).unwrap();
+
+        let (mut emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Ndjson);
+        emitter.emit_vertex(ResultSet {});
+        let raw: Entry =
+            serde_json::from_str(r#"{"id":42,"type":"vertex","label":"resultSet"}"#).unwrap();
+        assert_eq!(emitter.emit_raw(raw), 2);
+        let raw: Entry =
+            serde_json::from_str(r#"{"id":43,"type":"edge","label":"next","outV":2,"inV":1}"#)
+                .unwrap();
+        assert_eq!(emitter.emit_raw(raw), 3);
+        emitter.end();
+        signal_receiver.recv().unwrap().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+
+        let entries: Vec<Entry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(
+            entries[1],
+            Entry {
+                id: NumberOrString::Number(2),
+                data: Element::Vertex(Vertex::ResultSet(ResultSet {})),
+            }
+        );
+        assert_eq!(entries[2].id, NumberOrString::Number(3));
+        assert!(matches!(entries[2].data, Element::Edge(Edge::Next(_))));
+    }
+
+    /// Indexes the project at the given root in the given language, appending the entries
+    /// to the dump at the given path like `--append`.
+    fn append_run(root: &Path, language: Language, path: &Path) {
+        let mut opts = test_opts(language);
+        opts.project_root = root.to_path_buf();
+        opts.append = true;
+        opts.appended_after_id = last_id(path).unwrap();
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .unwrap();
+
+        let (emitter, signal_receiver) = FileEmitter::new(file, OutputFormat::Ndjson);
+        let emitter = emitter.with_first_id_after(opts.appended_after_id);
+        Indexer::index(opts, emitter).unwrap();
+        signal_receiver.recv().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_append_runs() {
+        let root = TestDir::new("append");
+        root.write("index.ts", "let x = 1;\nconsole.log(x);\n");
+        root.write(
+            "schema.graphql",
+            "type Query {\n    user: User\n}\n\ntype User {\n    name: String\n}\n",
+        );
+        let path = root.join("dump.lsif");
+        assert_eq!(last_id(&path).unwrap(), 0);
+
+        append_run(root.path(), Language::TypeScript, &path);
+        let first_run_last_id = last_id(&path).unwrap();
+        append_run(root.path(), Language::GraphQL, &path);
+
+        let content = fs::read_to_string(&path).unwrap();
+        let entries: Vec<Entry> = content
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let ids: Vec<u64> = entries
+            .iter()
+            .map(|entry| match entry.id {
+                NumberOrString::Number(id) => id,
+                NumberOrString::String(_) => panic!("Unexpected string identifier"),
+            })
+            .collect();
+        assert!(ids.windows(2).all(|w| w[0] < w[1]));
+        assert!(ids.contains(&(first_run_last_id + 1)));
+
+        let count = |f: fn(&Vertex) -> bool| {
+            entries
+                .iter()
+                .filter(|entry| matches!(&entry.data, Element::Vertex(v) if f(v)))
+                .count()
+        };
+        assert_eq!(count(|v| matches!(v, Vertex::MetaData(_))), 1);
+        assert_eq!(count(|v| matches!(v, Vertex::Project(_))), 2);
+
+        // The edges of both runs only reference vertices of the dump
+        let vertex_ids: HashSet<u64> = entries
+            .iter()
+            .zip(&ids)
+            .filter(|(entry, _)| matches!(entry.data, Element::Vertex(_)))
+            .map(|(_, id)| *id)
+            .collect();
+        for line in content.lines() {
+            let entry: serde_json::Value = serde_json::from_str(line).unwrap();
+            if entry["type"] != "edge" {
+                continue;
+            }
+            let mut edge_ids = entry["inVs"].as_array().cloned().unwrap_or_default();
+            edge_ids.push(entry["outV"].clone());
+            edge_ids.extend(entry.get("inV").cloned());
+            for id in edge_ids {
+                assert!(vertex_ids.contains(&id.as_u64().unwrap()), "{}", line);
+            }
+        }
+
+        // Both runs can be queried from the combined dump
+        let graph = LsifGraph::from_json(&content).unwrap();
+        let definitions = graph.definitions(&root.uri("index.ts"), Position::new(1, 12));
+        assert_eq!(definitions[0].range.start, Position::new(0, 4));
+        let definitions = graph.definitions(&root.uri("schema.graphql"), Position::new(1, 10));
+        assert_eq!(definitions[0].range.start, Position::new(4, 5));
+    }
+
+    #[test]
+    fn test_append_requires_ndjson() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.append = true;
+        opts.output_format = OutputFormat::Array;
+        assert!(opts.validate().is_err());
+    }
+}
+
+mod graph {
+    use std::collections::{HashMap, HashSet};
+
+    use super::helpers::{get_elements, project_root_uri, test_opts, TestDir};
+    use crate::{
+        emitter::file_emitter::{FileEmitter, OutputFormat},
+        indexer::indexer::Indexer,
+        protocol::{
+            graph::LsifGraph,
+            types::{Language, Position},
+        },
+    };
+
+    /// Indexes the TypeScript test data into a dump of the given format and reads it back.
+    fn read_dump(output_format: OutputFormat) -> LsifGraph {
+        let dir = TestDir::new("graph");
+        let path = dir.join("dump.lsif");
+        let file = std::fs::File::create(&path).unwrap();
+        let (emitter, signal_receiver) = FileEmitter::new(file, output_format);
+        Indexer::index(test_opts(Language::TypeScript), emitter).unwrap();
+        signal_receiver.recv().unwrap().unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        LsifGraph::from_json(&content).unwrap()
+    }
+
+    fn index_uri() -> String {
+        format!(
+            "{}/src/tests/test_data/TypeScript/index.ts",
+            project_root_uri()
+        )
+    }
+
+    #[test]
+    fn test_definitions() {
+        let graph = read_dump(OutputFormat::Ndjson);
+
+        let definitions = graph.definitions(&index_uri(), Position::new(2, 12));
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].uri, index_uri());
+        assert_eq!(definitions[0].range.start, Position::new(0, 4));
+
+        // Anywhere in the reference to `hello`, not only at its start
+        let definitions = graph.definitions(&index_uri(), Position::new(8, 3));
+        assert_eq!(definitions.len(), 1);
+        assert_eq!(definitions[0].range.start, Position::new(4, 9));
+
+        assert!(graph
+            .definitions(&index_uri(), Position::new(100, 0))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_references() {
+        let graph = read_dump(OutputFormat::Array);
+
+        let references = graph.references(&index_uri(), Position::new(0, 4));
+        assert!(references
+            .iter()
+            .any(|r| r.uri == index_uri() && r.range.start == Position::new(2, 12)));
+    }
+
+    #[test]
+    fn test_item_properties() {
+        let entries = get_elements(Language::TypeScript).to_json();
+        let ids_labeled = |label: &str| -> HashSet<u64> {
+            entries
+                .iter()
+                .filter(|e| e["label"] == label)
+                .map(|e| e["id"].as_u64().unwrap())
+                .collect()
+        };
+        let reference_results = ids_labeled("referenceResult");
+        assert!(!reference_results.is_empty());
+
+        let mut definition_items = HashMap::new();
+        for item in entries.iter().filter(|e| e["label"] == "item") {
+            let out_v = item["outV"].as_u64().unwrap();
+            if reference_results.contains(&out_v) {
+                assert!(
+                    item["property"] == "definitions" || item["property"] == "references",
+                    "{}",
+                    item
+                );
+                if item["property"] == "definitions" {
+                    assert_eq!(item["inVs"].as_array().unwrap().len(), 1, "{}", item);
+                    *definition_items.entry(out_v).or_insert(0) += 1;
+                }
+            } else {
+                // The items of definition, declaration and implementation results
+                assert!(item.get("property").is_none(), "{}", item);
+            }
+        }
+        // Exactly one `definitions` item per definition
+        assert_eq!(definition_items.len(), reference_results.len());
+        assert!(definition_items.values().all(|count| *count == 1));
+    }
+}
+
+mod validating_emitter {
+    use super::helpers::{test_opts, TestsEmitter};
+    use crate::{
+        edge,
+        emitter::{emitter::Emitter, validating_emitter::ValidatingEmitter},
+        indexer::indexer::Indexer,
+        protocol::types::{Edge, EdgeData, Language, NumberOrString, ResultSet},
+    };
+
+    #[test]
+    fn test_indexed_graph_is_valid() {
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let (emitter, validation_receiver) = ValidatingEmitter::new(emitter);
+
+        Indexer::index(test_opts(Language::TypeScript), emitter).unwrap();
+
+        validation_receiver.recv().unwrap().unwrap();
+    }
+
+    #[test]
+    fn test_dangling_edge() {
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let (mut emitter, validation_receiver) = ValidatingEmitter::new(emitter);
+
+        let result_set_id = emitter.emit_vertex(ResultSet {});
+        let missing_id = result_set_id + 100;
+        emitter.emit_edge(edge!(Next, result_set_id -> missing_id));
+        emitter.end();
+
+        assert!(validation_receiver.recv().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_contains_edge_to_non_range() {
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let (mut emitter, validation_receiver) = ValidatingEmitter::new(emitter);
+
+        let a = emitter.emit_vertex(ResultSet {});
+        let b = emitter.emit_vertex(ResultSet {});
+        emitter.emit_edge(Edge::contains(a, vec![b]));
+        emitter.end();
+
+        assert!(validation_receiver.recv().unwrap().is_err());
+    }
+}
+
+mod analyzer {
+    use tree_sitter::{Point, Range};
+
+    use crate::{
+        analyzer::analyzer::{symbol_kind, Scope},
+        protocol::types::SymbolKind,
+    };
+
+    fn range(start_byte: usize, end_byte: usize) -> Range {
+        Range {
+            start_byte,
+            end_byte,
+            start_point: Point::new(0, start_byte),
+            end_point: Point::new(0, end_byte),
+        }
+    }
+
+    #[test]
+    fn test_symbol_kinds() {
+        let kinds = [
+            ("function_declaration", SymbolKind::FUNCTION),
+            ("class_declaration", SymbolKind::CLASS),
+            ("method_definition", SymbolKind::METHOD),
+            ("interface_declaration", SymbolKind::INTERFACE),
+            ("enum_declaration", SymbolKind::ENUM),
+            ("type_alias_declaration", SymbolKind::CLASS),
+            ("type_parameter", SymbolKind::TYPE_PARAMETER),
+            ("internal_module", SymbolKind::MODULE),
+            ("public_field_definition", SymbolKind::PROPERTY),
+            ("lexical_declaration", SymbolKind::VARIABLE),
+        ];
+        for (node_kind, symbol) in kinds.iter() {
+            assert_eq!(symbol_kind(node_kind), *symbol, "{}", node_kind);
+        }
+    }
+
+    #[test]
+    fn test_innermost_scope_independent_of_order() {
+        let outer = Scope::new(range(0, 100));
+        let inner = Scope::new(range(10, 50));
+        let target = range(20, 25);
+
+        for scopes in [vec![outer, inner], vec![inner, outer]].iter() {
+            let scope = Scope::innermost_containing(scopes, &target).unwrap();
+            assert_eq!(scope.range(), inner.range());
+        }
+    }
+
+    #[test]
+    fn test_scope_does_not_enclose_itself() {
+        let scopes = vec![Scope::new(range(0, 100)), Scope::new(range(10, 10))];
+        let scope = Scope::innermost_containing(&scopes, &range(10, 10)).unwrap();
+        assert_eq!(scope.range(), range(0, 100));
+    }
+
+    #[test]
+    fn test_no_containing_scope() {
+        let scopes = vec![Scope::new(range(10, 50))];
+        assert!(Scope::innermost_containing(&scopes, &range(60, 70)).is_none());
+    }
+}
+
+mod containment {
+    use tree_sitter::{Point, Range};
+
+    use crate::analyzer::containment::Contain;
+
+    fn range(start_byte: usize, end_byte: usize) -> Range {
+        Range {
+            start_byte,
+            end_byte,
+            start_point: Point::new(0, start_byte),
+            end_point: Point::new(0, end_byte),
+        }
+    }
+
+    #[test]
+    fn test_equal_spans() {
+        let a = range(10, 20);
+        assert!(a.contains(&a));
+        assert!(a.overlaps(&a));
+    }
+
+    #[test]
+    fn test_nested_spans() {
+        let outer = range(0, 100);
+        let inner = range(10, 20);
+        assert!(outer.contains(&inner));
+        assert!(!inner.contains(&outer));
+        assert!(outer.overlaps(&inner));
+        assert!(inner.overlaps(&outer));
+    }
+
+    #[test]
+    fn test_adjacent_spans() {
+        let a = range(0, 10);
+        let b = range(10, 20);
+        assert!(!a.contains(&b));
+        assert!(!a.overlaps(&b));
+        assert!(!b.overlaps(&a));
+    }
+
+    #[test]
+    fn test_disjoint_spans() {
+        let a = range(0, 10);
+        let b = range(15, 20);
+        assert!(!a.contains(&b));
+        assert!(!a.overlaps(&b));
+    }
+
+    #[test]
+    fn test_partially_overlapping_spans() {
+        let a = range(0, 10);
+        let b = range(5, 15);
+        assert!(!a.contains(&b));
+        assert!(a.overlaps(&b));
+        assert!(b.overlaps(&a));
+    }
+
+    #[test]
+    fn test_strictly_contains() {
+        let outer = range(0, 100);
+        let inner = range(10, 20);
+        assert!(outer.strictly_contains(&inner));
+        assert!(!inner.strictly_contains(&outer));
+        assert!(!inner.strictly_contains(&inner));
+    }
+
+    #[test]
+    fn test_zero_width_spans_at_the_same_byte() {
+        let a = range(10, 10);
+        let b = range(10, 10);
+        assert!(a.contains(&b));
+        assert!(!a.strictly_contains(&b));
+        assert!(!a.overlaps(&b));
+        assert!(!a.contains_point(10));
+    }
+
+    #[test]
+    fn test_contains_point() {
+        let a = range(10, 20);
+        assert!(a.contains_point(10));
+        assert!(a.contains_point(19));
+        assert!(!a.contains_point(20));
+        assert!(!a.contains_point(9));
+    }
+}
+
+mod embedded {
+    use crate::analyzer::embedded::script_ranges;
+
+    /// Returns the content of the `<script>` blocks of the given component.
+    fn scripts(component: &str) -> Vec<&str> {
+        script_ranges(component.as_bytes())
+            .into_iter()
+            .map(|range| &component[range.start_byte..range.end_byte])
+            .collect()
+    }
+
+    #[test]
+    fn test_script_blocks() {
+        let component = "<template><p>Hi</p></template>\n\
+                         <script>let a = 1;</script>\n\
+                         <scripts>not code</scripts>\n\
+                         <script lang=\"ts\">let b = 2;";
+        assert_eq!(scripts(component), vec!["let a = 1;", "let b = 2;"]);
+    }
+
+    #[test]
+    fn test_commented_out_script() {
+        let component = "<!-- <script>let a = 1;</script> -->\n<script>let b = 2;</script>";
+        assert_eq!(scripts(component), vec!["let b = 2;"]);
+        // An unclosed comment hides the rest of the file
+        assert!(scripts("<!-- <script>let a = 1;</script>").is_empty());
+    }
+
+    #[test]
+    fn test_closing_bracket_in_attribute() {
+        let component = "<script data-a=\"a > b\" data-b='c>d'>let a = 1;</script>";
+        assert_eq!(scripts(component), vec!["let a = 1;"]);
+    }
+}
+
+mod incremental {
+    use std::fs;
+
+    use super::{
+        assert_definition_in,
+        helpers::{get_elements_with_opts, TestDir},
+    };
+    use crate::{
+        cli::Opts,
+        indexer::incremental::{analysis_fingerprint, IncrementalCache},
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_only_changed_files_are_analyzed() {
+        let root = TestDir::new("incremental");
+        let cache_path = root.join("lsif-os.cache");
+        let timings_path = root.join("timings.jsonl");
+
+        let a_content = "export function add(a: number, b: number) {\n    return a + b;\n}\n";
+        let b_content = "let x = 1;\nconsole.log(x);\n";
+        let new_b_content = "let y = 1;\n\nconsole.log(y);\n";
+        let a = root.write("a.ts", a_content);
+        let b = root.write("b.ts", b_content);
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.incremental = Some(cache_path.clone());
+        opts.timings = Some(timings_path.clone());
+        get_elements_with_opts(opts.clone());
+        assert_eq!(
+            fs::read_to_string(&timings_path).unwrap().lines().count(),
+            2
+        );
+
+        let fingerprint = analysis_fingerprint(&opts, &Language::TypeScript.get_query_source());
+        let cache = IncrementalCache::load(&cache_path, Language::TypeScript, fingerprint);
+        assert!(cache.is_fresh(a.to_str().unwrap(), a_content.as_bytes()));
+        assert!(cache.is_fresh(b.to_str().unwrap(), b_content.as_bytes()));
+
+        root.write("b.ts", new_b_content);
+        assert!(cache.is_fresh(a.to_str().unwrap(), a_content.as_bytes()));
+        assert!(!cache.is_fresh(b.to_str().unwrap(), new_b_content.as_bytes()));
+
+        let elements = get_elements_with_opts(opts);
+        // Only `b.ts` was analyzed again, `a.ts` was restored from the cache
+        let timings = fs::read_to_string(&timings_path).unwrap();
+        assert_eq!(timings.lines().count(), 1, "{}", timings);
+        assert!(timings.contains("b.ts"), "{}", timings);
+        assert_definition_in(&elements, &root.uri("a.ts"), (1, 11), (0, 20)).unwrap();
+        assert_definition_in(&elements, &root.uri("b.ts"), (2, 12), (0, 4)).unwrap();
+
+        let cache = IncrementalCache::load(&cache_path, Language::TypeScript, fingerprint);
+        assert!(cache.is_fresh(b.to_str().unwrap(), new_b_content.as_bytes()));
+    }
+
+    #[test]
+    fn test_changed_options_discard_the_cache() {
+        let root = TestDir::new("incremental-options");
+        let timings_path = root.join("timings.jsonl");
+        root.write("a.ts", "// TODO: fix\nlet x = 1;\n");
+        root.write("b.ts", "let y = 1;\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.incremental = Some(root.join("lsif-os.cache"));
+        opts.timings = Some(timings_path.clone());
+        // The number of files that were analyzed, i.e. that were not restored from the cache
+        let analyzed_files = |opts: &Opts| {
+            get_elements_with_opts(opts.clone());
+            fs::read_to_string(&timings_path).unwrap().lines().count()
+        };
+        assert_eq!(analyzed_files(&opts), 2);
+        assert_eq!(analyzed_files(&opts), 0);
+
+        opts.todo_comments = true;
+        assert_eq!(analyzed_files(&opts), 2);
+        let uri = root.uri("a.ts");
+        assert_eq!(
+            get_elements_with_opts(opts.clone())
+                .diagnostics_of(&uri)
+                .len(),
+            1
+        );
+
+        opts.todo_markers = vec!["FIXME".to_string()];
+        assert_eq!(analyzed_files(&opts), 2);
+        assert!(get_elements_with_opts(opts).diagnostics_of(&uri).is_empty());
+    }
+}
+
+mod stats {
+    use super::helpers::{TestDir, TestsEmitter};
+    use crate::{
+        indexer::{indexer::Indexer, stats::IndexStats},
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_stats_of_small_project() {
+        let root = TestDir::new("stats");
+        root.write("a.ts", "let x = 1;\nconsole.log(x);\n");
+
+        let opts = root.opts(Language::TypeScript);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let stats = Indexer::index_with_stats(opts, emitter).unwrap();
+
+        assert_eq!(
+            stats,
+            IndexStats {
+                documents: 1,
+                definitions: 1,
+                // `x`, `console` and `log`, the name of `x` is its definition
+                references: 3,
+                // `console` and `log`
+                unresolved_references: 2,
+                ranges: 2,
+                languages: vec![("TypeScript".to_string(), 1)].into_iter().collect(),
+                timed_out_files: vec![],
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_timeout() {
+        let root = TestDir::new("parse-timeout");
+        root.write("a.ts", "let x = 1;\nconsole.log(x);\n");
+        // Takes far longer than a millisecond to parse
+        root.write("large.ts", "let y = [1, 2, 3];\n".repeat(200_000));
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.parse_timeout_ms = Some(1);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let stats = Indexer::index_with_stats(opts, emitter).unwrap();
+
+        assert_eq!(stats.timed_out_files, vec![root.join("large.ts")]);
+        // The other files are still indexed
+        assert_eq!(stats.definitions, 1);
+    }
+
+    #[test]
+    fn test_index_returns_summary() {
+        let root = TestDir::new("summary");
+        root.write("a.ts", "let x = 1;\nconsole.log(x);\n");
+        root.write("b.ts", "function f() {}\nf();\n");
+
+        let opts = root.opts(Language::TypeScript);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let summary = Indexer::index(opts, emitter).unwrap();
+
+        assert_eq!(summary.documents, 2);
+        // `x` and `f`
+        assert_eq!(summary.definitions, 2);
+
+        // The same files are indexed with a pool of a single thread
+        let mut opts = root.opts(Language::TypeScript);
+        opts.threads = Some(1);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let single_thread_summary = Indexer::index(opts, emitter).unwrap();
+        assert_eq!(single_thread_summary.documents, 2);
+        assert_eq!(single_thread_summary.definitions, 2);
+        assert_eq!(single_thread_summary.references, summary.references);
+        // `x`, `f`, `console` and `log`
+        assert_eq!(summary.references, 4);
+        assert_eq!(summary.unresolved_references, 2);
+    }
+}
+
+mod unresolved {
+    use std::fs;
+
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::{indexer::unresolved::UnresolvedReference, protocol::types::Language};
+
+    #[test]
+    fn test_report_unresolved() {
+        let dir = TestDir::new("unresolved");
+        let report_path = dir.join("report.jsonl");
+        let mut opts = test_opts(Language::TypeScript);
+        opts.report_unresolved = Some(report_path.clone());
+        get_elements_with_opts(opts.clone());
+
+        let report = fs::read_to_string(&report_path).unwrap();
+        let entries: Vec<UnresolvedReference> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let file = opts
+            .project_root
+            .join("unresolved.ts")
+            .to_str()
+            .unwrap()
+            .to_string();
+        assert!(entries.contains(&UnresolvedReference {
+            file,
+            line: 1,
+            character: 4,
+            identifier: "undefinedSymbol".to_string(),
+        }));
+        assert!(!entries.iter().any(|e| e.identifier == "defined"));
+    }
+}
+
+mod timings {
+    use std::fs;
+
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::{
+        indexer::{indexer::discover_files, timings::FileTiming},
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_timings_list_every_file() {
+        let dir = TestDir::new("timings");
+        let report_path = dir.join("report.jsonl");
+        let mut opts = test_opts(Language::TypeScript);
+        opts.timings = Some(report_path.clone());
+        get_elements_with_opts(opts.clone());
+
+        let report = fs::read_to_string(&report_path).unwrap();
+        let timings: Vec<FileTiming> = report
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+
+        let mut files: Vec<String> = timings.iter().map(|t| t.file.clone()).collect();
+        files.sort();
+        let mut expected: Vec<String> = discover_files(&opts)
+            .unwrap()
+            .into_iter()
+            .map(|path| path.to_str().unwrap().to_string())
+            .collect();
+        expected.sort();
+        assert_eq!(files, expected);
+        // The slowest file comes first
+        assert!(timings
+            .windows(2)
+            .all(|w| w[0].total_micros() >= w[1].total_micros()));
+    }
+}
+
+mod moniker {
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::{
+        indexer::package::Package,
+        protocol::types::{Language, PackageManager, MONIKER_SCHEME},
+    };
+
+    #[test]
+    fn test_configured_moniker_scheme() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.moniker_scheme = "tsc".to_string();
+        let elements = get_elements_with_opts(opts);
+
+        let monikers = elements.monikers();
+        assert!(!monikers.is_empty());
+        for m in monikers {
+            assert_eq!(m.scheme, "tsc");
+        }
+    }
+
+    #[test]
+    fn test_same_file_names_in_different_dirs() {
+        let dir = TestDir::new("moniker");
+        // The project is named after its directory
+        for name in ["a", "b"].iter() {
+            dir.write(
+                &format!("my-project/{}/index.ts", name),
+                "export class Main {}\n",
+            );
+        }
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.project_root = dir.join("my-project");
+        let elements = get_elements_with_opts(opts);
+
+        let mut identifiers: Vec<&str> = elements
+            .monikers()
+            .into_iter()
+            .filter(|m| m.kind == "exported")
+            .map(|m| {
+                assert_eq!(m.scheme, MONIKER_SCHEME);
+                m.identifier.as_str()
+            })
+            .collect();
+        identifiers.sort();
+        assert_eq!(
+            identifiers,
+            vec!["my-project:a/index.ts:Main", "my-project:b/index.ts:Main"]
+        );
+    }
+
+    #[test]
+    fn test_npm_export_and_import_monikers() {
+        let root = TestDir::new("npm-moniker");
+        root.write(
+            "package.json",
+            r#"{ "name": "my-lib", "version": "1.2.3", "dependencies": { "left-pad": "^1.3.0" } }"#,
+        );
+        root.write(
+            "index.ts",
+            "import leftPad, { pad as padStart } from 'left-pad';\n\
+             export class Main {}\n\
+             const padded = padStart(\"x\", 2);\n\
+             leftPad(\"x\", 2);\n",
+        );
+
+        let elements = root.index(Language::TypeScript);
+        let monikers = elements.monikers_with_ids();
+        let packages = elements.package_informations();
+
+        let (export, export_id) = monikers.iter().find(|(m, _)| m.kind == "export").unwrap();
+        assert_eq!(export.scheme, "npm");
+        assert_eq!(export.identifier, "my-lib:index.ts:Main");
+        let export_package = elements.package_of_moniker(*export_id).unwrap();
+        let (package, _) = packages
+            .iter()
+            .find(|(_, id)| *id == export_package)
+            .unwrap();
+        assert_eq!(package.name, "my-lib");
+        assert_eq!(package.version.as_deref(), Some("1.2.3"));
+
+        let mut imports: Vec<&str> = monikers
+            .iter()
+            .filter(|(m, _)| m.kind == "import")
+            .map(|(m, _)| m.identifier.as_str())
+            .collect();
+        imports.sort();
+        assert_eq!(imports, vec!["left-pad:default", "left-pad:pad"]);
+
+        let (import, import_id) = monikers.iter().find(|(m, _)| m.kind == "import").unwrap();
+        assert_eq!(import.scheme, "npm");
+        let import_package = elements.package_of_moniker(*import_id).unwrap();
+        let (package, _) = packages
+            .iter()
+            .find(|(_, id)| *id == import_package)
+            .unwrap();
+        assert_eq!(package.name, "left-pad");
+        assert_eq!(package.manager, PackageManager::Npm);
+    }
+
+    #[test]
+    fn test_no_import_moniker_without_import() {
+        let root = TestDir::new("npm-no-import");
+        root.write(
+            "package.json",
+            r#"{ "name": "my-lib", "dependencies": { "left-pad": "^1.3.0" } }"#,
+        );
+        root.write("index.ts", "leftPad(\"x\", 2);\n");
+
+        let elements = root.index(Language::TypeScript);
+        assert!(!elements.monikers().iter().any(|m| m.kind == "import"));
+    }
+
+    #[test]
+    fn test_external_import_monikers() {
+        let root = TestDir::new("external-moniker");
+        root.write(
+            "index.ts",
+            "import { readFileSync } from 'fs';\nreadFileSync('a');\nreadFileSync('b');\n",
+        );
+
+        let mut opts = root.opts(Language::TypeScript);
+        let elements = get_elements_with_opts(opts.clone());
+        assert!(!elements.monikers().iter().any(|m| m.kind == "import"));
+
+        opts.emit_external_monikers = true;
+        let elements = get_elements_with_opts(opts);
+        let monikers = elements.monikers_with_ids();
+        let imports: Vec<_> = monikers
+            .iter()
+            .filter(|(m, _)| m.kind == "import")
+            .collect();
+        // The references to the same symbol share a moniker
+        assert_eq!(imports.len(), 1);
+        let (import, import_id) = imports[0];
+        assert_eq!(import.scheme, MONIKER_SCHEME);
+        assert_eq!(import.identifier, "readFileSync");
+        assert!(elements.package_of_moniker(*import_id).is_none());
+    }
+
+    #[test]
+    fn test_pom_xml_package() {
+        let root = TestDir::new("maven-package");
+        root.write(
+            "pom.xml",
+            r#"<project>
+  <parent>
+    <groupId>org.example</groupId>
+    <artifactId>parent</artifactId>
+    <version>1.0</version>
+  </parent>
+  <!-- <artifactId>commented-out</artifactId> -->
+  <artifactId>app</artifactId>
+  <version>2.0</version>
+  <dependencies>
+    <dependency>
+      <groupId>com.google.guava</groupId>
+      <artifactId>guava</artifactId>
+      <version>30.0-jre</version>
+    </dependency>
+  </dependencies>
+</project>"#,
+        );
+
+        let package = Package::read(root.path(), None).unwrap().unwrap();
+        assert_eq!(package.manager, PackageManager::Maven);
+        assert_eq!(package.name, "org.example:app");
+        assert_eq!(package.version.as_deref(), Some("2.0"));
+        assert_eq!(
+            package.dependencies.get("com.google.guava:guava"),
+            Some(&Some("30.0-jre".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_package_json_without_name() {
+        let root = TestDir::new("unnamed-package");
+        root.write(
+            "package.json",
+            r#"{ "private": true, "dependencies": { "left-pad": "^1.3.0" } }"#,
+        );
+        root.write(
+            "index.ts",
+            "import { pad } from 'left-pad';\nexport class Main {}\npad();\n",
+        );
+
+        assert_eq!(Package::read(root.path(), None).unwrap(), None);
+        let elements = root.index(Language::TypeScript);
+        assert!(elements.package_informations().is_empty());
+        assert!(!elements.monikers().iter().any(|m| m.scheme == "npm"));
+    }
+
+    #[test]
+    fn test_dependency_of_module() {
+        let package = Package {
+            manager: PackageManager::Npm,
+            name: "my-lib".to_string(),
+            version: None,
+            dependencies: vec![
+                ("left-pad".to_string(), None),
+                ("@types/node".to_string(), None),
+            ]
+            .into_iter()
+            .collect(),
+        };
+        let dependency = |module| package.dependency_of(module).map(|(name, _)| name.as_str());
+
+        assert_eq!(dependency("left-pad"), Some("left-pad"));
+        assert_eq!(dependency("left-pad/lib/index"), Some("left-pad"));
+        assert_eq!(dependency("@types/node"), Some("@types/node"));
+        assert_eq!(dependency("@types/node/fs"), Some("@types/node"));
+        assert_eq!(dependency("@types"), None);
+        assert_eq!(dependency("./left-pad"), None);
+        assert_eq!(dependency("fs"), None);
+    }
+}
+
+mod files_from {
+    use std::fs;
+
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir, TestsEmitter};
+    use crate::{indexer::indexer::Indexer, protocol::types::Language};
+
+    #[test]
+    fn test_only_listed_files_are_indexed() {
+        let root = TestDir::new("files-from");
+        for name in ["a.ts", "b.ts", "c.ts"].iter() {
+            root.write(name, "let x = 1;\n");
+        }
+        let manifest = root.write("files.txt", "a.ts\n\nb.ts\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.files_from = Some(manifest.clone());
+        let elements = get_elements_with_opts(opts);
+
+        let mut uris = elements.document_uris();
+        uris.sort();
+        assert_eq!(uris, vec![root.uri("a.ts"), root.uri("b.ts")]);
+    }
+
+    #[test]
+    fn test_duplicate_listed_file() {
+        let root = TestDir::new("files-from-duplicate");
+        root.write("a.ts", "let x = 1;\n");
+        let manifest = root.write("files.txt", "a.ts\n./a.ts\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.files_from = Some(manifest);
+        let elements = get_elements_with_opts(opts);
+
+        assert_eq!(elements.document_uris().len(), 1);
+    }
+
+    #[test]
+    fn test_missing_listed_file() {
+        let dir = TestDir::new("files-from-missing");
+        let manifest = dir.write("files.txt", "does-not-exist.ts\n");
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.files_from = Some(manifest);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        assert!(Indexer::index(opts, emitter).is_err());
+    }
+
+    #[test]
+    fn test_unreadable_listed_file() {
+        let root = TestDir::new("files-from-unreadable");
+        // A directory cannot be read as a file
+        fs::create_dir_all(root.join("dir.ts")).unwrap();
+        let manifest = root.write("files.txt", "dir.ts\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.files_from = Some(manifest);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let error = Indexer::index(opts, emitter).unwrap_err();
+        assert!(
+            error.to_string().contains("Could not read file"),
+            "{}",
+            error
+        );
+    }
+}
+
+mod discovery {
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::{indexer::indexer::discover_files, protocol::types::Language};
+
+    #[test]
+    fn test_graphql_extensions() {
+        let root = TestDir::new("graphql-extensions");
+        let schema = "type Query {\n    user: User\n}\n\ntype User {\n    name: String\n}\n";
+        for name in ["a.graphql", "b.gql", "c.graphqls"].iter() {
+            root.write(name, schema);
+        }
+
+        let opts = root.opts(Language::GraphQL);
+        assert_eq!(discover_files(&opts).unwrap().len(), 3);
+        let elements = get_elements_with_opts(opts);
+
+        // The files are parsed the same, whatever their extension
+        for name in ["a.graphql", "b.gql", "c.graphqls"].iter() {
+            let (_range, id) = elements.find_range(&root.uri(name), (1, 10)).unwrap();
+            let definition = elements.find_definition_ranges(id)[0];
+            assert_eq!((definition.start.line, definition.start.character), (4, 5));
+        }
+    }
+
+    #[test]
+    fn test_uppercase_extensions() {
+        let root = TestDir::new("uppercase-extensions");
+        root.write("Main.JS", "let x = 1;\nconsole.log(x);\n");
+        root.write("Component.TSX", "let y = 1;\nconsole.log(y);\n");
+
+        let mut opts = root.opts(Language::JavaScript);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("Main.JS")]);
+
+        opts.language = Language::TypeScript.into();
+        assert_eq!(
+            discover_files(&opts).unwrap(),
+            vec![root.join("Component.TSX")]
+        );
+        let elements = get_elements_with_opts(opts);
+        let uri = root.uri("Component.TSX");
+        let (_range, id) = elements.find_range(&uri, (1, 12)).unwrap();
+        let definition = elements.find_definition_ranges(id)[0];
+        assert_eq!((definition.start.line, definition.start.character), (0, 4));
+    }
+
+    #[test]
+    fn test_discover_files() {
+        let root = TestDir::new("discovery");
+        for name in ["a.ts", "b.tsx", "sub/c.ts", "d.js", "e.txt"].iter() {
+            root.write(name, "");
+        }
+
+        let opts = root.opts(Language::TypeScript);
+        let mut files: Vec<String> = discover_files(&opts)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(root.path())
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        files.sort();
+
+        assert_eq!(files, vec!["a.ts", "b.tsx", "sub/c.ts"]);
+    }
+
+    #[test]
+    fn test_include_hidden() {
+        let root = TestDir::new("include-hidden");
+        for name in ["a.ts", ".hidden.ts", ".config/b.ts"].iter() {
+            root.write(name, "");
+        }
+
+        let mut opts = root.opts(Language::TypeScript);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("a.ts")]);
+
+        opts.include_hidden = true;
+        let mut files = discover_files(&opts).unwrap();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                root.join(".config/b.ts"),
+                root.join(".hidden.ts"),
+                root.join("a.ts"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_max_file_size() {
+        let root = TestDir::new("max-file-size");
+        root.write("small.ts", "let x = 1;\n");
+        root.write("bundle.ts", "let x = 1;".repeat(1000));
+
+        let mut opts = root.opts(Language::TypeScript);
+        assert_eq!(discover_files(&opts).unwrap().len(), 2);
+
+        opts.max_file_size = Some(1024);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("small.ts")]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_follow_symlinks() {
+        use std::os::unix::fs::symlink;
+
+        let dir = TestDir::new("symlinks");
+        let root = dir.join("root");
+        let shared = dir.join("shared");
+        dir.write("root/a.ts", "");
+        dir.write("shared/b.ts", "");
+        symlink(shared.join("b.ts"), root.join("link.ts")).unwrap();
+        symlink(&shared, root.join("shared")).unwrap();
+        symlink(&root, root.join("cycle")).unwrap();
+        symlink(dir.join("missing.ts"), root.join("broken.ts")).unwrap();
+
+        let discover = |follow_symlinks| {
+            let mut opts = test_opts(Language::TypeScript);
+            opts.project_root = root.clone();
+            opts.follow_symlinks = follow_symlinks;
+            let mut files: Vec<String> = discover_files(&opts)
+                .unwrap()
+                .into_iter()
+                .map(|path| {
+                    path.strip_prefix(&root)
+                        .unwrap()
+                        .to_str()
+                        .unwrap()
+                        .to_string()
+                })
+                .collect();
+            files.sort();
+            files
+        };
+
+        assert_eq!(discover(false), vec!["a.ts"]);
+        // `link.ts` and `shared/b.ts` are the same file, found at the first of its paths
+        let files = discover(true);
+        assert_eq!(files.len(), 2, "{:?}", files);
+        assert_eq!(files[0], "a.ts");
+        assert!(
+            ["link.ts", "shared/b.ts"].contains(&files[1].as_str()),
+            "{:?}",
+            files
+        );
+    }
+}
+
+mod git {
+    use std::{fs, path::Path, process::Command};
+
+    use super::helpers::{test_opts, TestDir, TestsEmitter};
+    use crate::{
+        indexer::{
+            git::GitTree,
+            indexer::{discover_files, Indexer},
+            package::Package,
+        },
+        protocol::types::Language,
+    };
+
+    fn git(dir: &Path, args: &[&str]) {
+        let status = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(["-c", "user.name=test", "-c", "user.email=test@example.com"])
+            .args(args)
+            .status()
+            .unwrap();
+        assert!(status.success());
+    }
+
+    /// Creates a repository with a commit of two files, then changes the working tree so
+    /// that it no longer matches the commit.
+    fn repository(name: &str) -> TestDir {
+        let root = TestDir::new(name);
+        root.write("src/a.ts", "export function f() {}\n");
+        root.write("src/b.ts", "let x = 1;\nx;\n");
+        git(root.path(), &["init", "-q"]);
+        git(root.path(), &["add", "."]);
+        git(root.path(), &["commit", "-q", "-m", "Add a and b"]);
+
+        fs::remove_file(root.join("src/b.ts")).unwrap();
+        root.write("src/a.ts", "");
+        root.write("src/c.ts", "");
+        root
+    }
+
+    fn relative_files(root: &Path, git_ref: &str) -> Vec<String> {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.project_root = root.to_path_buf();
+        opts.git_ref = Some(git_ref.to_string());
+        let mut files: Vec<String> = discover_files(&opts)
+            .unwrap()
+            .into_iter()
+            .map(|path| {
+                path.strip_prefix(root)
+                    .unwrap()
+                    .to_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        files.sort();
+        files
+    }
+
+    #[test]
+    fn test_index_git_ref() {
+        let root = repository("git-ref");
+        assert_eq!(
+            relative_files(root.path(), "HEAD"),
+            vec!["src/a.ts", "src/b.ts"]
+        );
+        assert_eq!(
+            relative_files(&root.join("src"), "HEAD"),
+            vec!["a.ts", "b.ts"]
+        );
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.git_ref = Some("HEAD".to_string());
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let summary = Indexer::index(opts, emitter).unwrap();
+        assert_eq!(summary.documents, 2);
+        // The definitions of the committed files, the working tree has none. The exported
+        // `f` is also a definition of its scope
+        assert_eq!(summary.definitions, 3);
+    }
+
+    #[test]
+    fn test_read_files_of_git_ref() {
+        let root = repository("git-read");
+        root.write("package.json", r#"{ "name": "working-tree" }"#);
+        let tree = GitTree::open(root.path(), "HEAD").unwrap();
+
+        assert_eq!(
+            tree.read(&root.join("src/a.ts")).unwrap(),
+            b"export function f() {}\n"
+        );
+        assert_eq!(
+            tree.read(&root.join("src/b.ts")).unwrap(),
+            b"let x = 1;\nx;\n"
+        );
+        assert_eq!(tree.read_if_exists(&root.join("src/c.ts")).unwrap(), None);
+        assert!(tree.read(&root.join("src")).is_err());
+        // The process still reads files after an error
+        assert!(tree.read(&root.join("src/a.ts")).is_ok());
+        // The manifest is the one of the revision, which has none
+        assert_eq!(Package::read(root.path(), Some(&tree)).unwrap(), None);
+    }
+
+    #[test]
+    fn test_index_bare_repository() {
+        let root = repository("git-bare");
+        let bare = TestDir::new("git-bare-clone");
+        let bare = bare.join("repository.git");
+        git(
+            root.path(),
+            &["clone", "-q", "--bare", ".", bare.to_str().unwrap()],
+        );
+
+        assert_eq!(relative_files(&bare, "HEAD"), vec!["src/a.ts", "src/b.ts"]);
+    }
+
+    #[test]
+    fn test_since() {
+        let root = TestDir::new("git-since");
+        root.write("src/a.ts", "export function f() {}\n");
+        root.write("src/b.ts", "let x = 1;\n");
+        git(root.path(), &["init", "-q"]);
+        git(root.path(), &["add", "."]);
+        git(root.path(), &["commit", "-q", "-m", "Add a and b"]);
+        root.write("src/b.ts", "let x = 2;\nx;\n");
+        git(root.path(), &["commit", "-q", "-am", "Change b"]);
+
+        let changed_files = |git_ref: Option<&str>| {
+            let mut opts = test_opts(Language::TypeScript);
+            opts.project_root = root.join("src");
+            opts.since = Some("HEAD~1".to_string());
+            opts.git_ref = git_ref.map(String::from);
+            let mut files: Vec<_> = discover_files(&opts)
+                .unwrap()
+                .into_iter()
+                .map(|path| path.file_name().unwrap().to_str().unwrap().to_string())
+                .collect();
+            files.sort();
+            files
+        };
+        assert_eq!(changed_files(None), vec!["b.ts"]);
+        root.write("src/a.ts", "export function g() {}\n");
+        assert_eq!(changed_files(None), vec!["a.ts", "b.ts"]);
+        assert_eq!(changed_files(Some("HEAD")), vec!["b.ts"]);
+        // Untracked files are added files
+        root.write("src/a.ts", "export function f() {}\n");
+        root.write("src/c.ts", "f();\n");
+        assert_eq!(changed_files(None), vec!["b.ts", "c.ts"]);
+        assert_eq!(changed_files(Some("HEAD")), vec!["b.ts"]);
+
+        // The unchanged files are analyzed to resolve the references of the changed ones
+        let mut opts = root.opts(Language::TypeScript);
+        opts.since = Some("HEAD".to_string());
+        let (emitter, elements_receiver) = TestsEmitter::new();
+        let summary = Indexer::index(opts, emitter).unwrap();
+        assert_eq!(summary.documents, 1);
+        assert_eq!(summary.definitions, 0);
+        assert_eq!(summary.references, 1);
+        assert_eq!(summary.unresolved_references, 0);
+        let elements = elements_receiver.recv().unwrap();
+        let monikers = elements.monikers();
+        assert_eq!(monikers.len(), 1);
+        assert_eq!(monikers[0].kind, "import");
+        assert_eq!(
+            monikers[0].identifier,
+            format!(
+                "{}:src/a.ts:f",
+                root.path().file_name().unwrap().to_string_lossy()
+            )
+        );
+    }
+
+    #[test]
+    fn test_unknown_git_ref() {
+        let root = repository("git-unknown-ref");
+        let mut opts = root.opts(Language::TypeScript);
+        opts.git_ref = Some("does-not-exist".to_string());
+        assert!(discover_files(&opts).is_err());
+    }
+}
+
+mod lsif_data_cache {
+    use std::sync::Arc;
+
+    use smol_str::SmolStr;
+    use tree_sitter::{Point, Range};
+
+    use crate::{
+        analyzer::{
+            analyzer::{Definition, DefinitionScope, Location, Reference},
+            lsif_data_cache::LsifDataCache,
+        },
+        protocol::types::ReferenceAccess,
+    };
+
+    fn location(start_byte: usize, end_byte: usize) -> Location {
+        Location {
+            file_path: "index.ts".to_string(),
+            range: Range {
+                start_byte,
+                end_byte,
+                start_point: Point::new(0, start_byte),
+                end_point: Point::new(0, end_byte),
+            },
+        }
+    }
+
+    #[test]
+    fn test_duplicate_document() {
+        let mut cache = LsifDataCache::default();
+        assert_eq!(cache.cache_document("index.ts".to_string(), 1), 1);
+        assert_eq!(cache.cache_document("index.ts".to_string(), 2), 1);
+        assert_eq!(cache.get_documents().count(), 1);
+    }
+
+    #[test]
+    fn test_duplicate_reference() {
+        let mut cache = LsifDataCache::default();
+        cache.cache_document("index.ts".to_string(), 1);
+
+        let def = Arc::new(Definition {
+            location: location(0, 1),
+            node_name: SmolStr::new("a"),
+            comment: String::new(),
+            kind: DefinitionScope::Exported,
+            is_declaration: false,
+            aliased_name: None,
+            full_range: None,
+        });
+        cache.cache_definition(&def, 1, 2, 3);
+
+        let r = Reference {
+            location: location(10, 11),
+            node_name: SmolStr::new("a"),
+            def: Some(Arc::clone(&def)),
+            access: ReferenceAccess::Read,
+            import: None,
+        };
+        cache.cache_reference_range(&r, 4);
+        assert!(cache.cache_reference(&def, &r, 4));
+        assert!(!cache.cache_reference(&def, &r, 4));
+
+        let def_info = cache.get_definition_info(&def.location).unwrap();
+        assert_eq!(
+            def_info.reference_range_ids[&(1, ReferenceAccess::Read)],
+            vec![4]
+        );
+        assert_eq!(
+            cache.get_document("index.ts").unwrap().reference_range_ids,
+            vec![4]
+        );
+    }
+}
+
+fn assert_definition(
+    elements: &Elements,
+    rel_file_path: &str,
+    sym_pos: (u64, u64),
+    def_pos: (u64, u64),
+) -> Result<()> {
+    assert_definition_in(
+        elements,
+        &format!(
+            "{}/src/tests/test_data/{}",
+            project_root_uri(),
+            rel_file_path
+        ),
+        sym_pos,
+        def_pos,
+    )
 }
 
-fn assert_definition(
+fn assert_definition_in(
     elements: &Elements,
-    rel_file_path: &str,
+    file_uri: &str,
     sym_pos: (u64, u64),
     def_pos: (u64, u64),
 ) -> Result<()> {
     let (_range, id) = elements
-        .find_range(
-            &format!(
-                "{}/src/tests/test_data/{}",
-                project_root_uri(),
-                rel_file_path
-            ),
-            sym_pos,
-        )
+        .find_range(file_uri, sym_pos)
         .context("Could not find target range")?;
 
     let def_range = {
@@ -70,3 +2135,1014 @@ fn assert_definition(
 
     Ok(())
 }
+
+mod schema {
+    use serde_json::json;
+
+    use super::helpers::{entry_validator, get_elements};
+    use crate::protocol::types::Language;
+
+    #[test]
+    fn test_emitted_entries_match_schema() {
+        let validator = entry_validator();
+        let entries = get_elements(Language::TypeScript).to_json();
+        assert!(!entries.is_empty());
+        for entry in entries {
+            if let Err(e) = validator.validate(&entry) {
+                panic!("{}: {}", entry, e);
+            }
+        }
+    }
+
+    #[test]
+    fn test_schema_rejects_invalid_entries() {
+        let validator = entry_validator();
+        let missing_end = json!({
+            "id": 1,
+            "type": "vertex",
+            "label": "range",
+            "start": { "line": 0, "character": 0 },
+        });
+        let wrong_element = json!({ "id": 2, "type": "edge", "label": "resultSet" });
+        let unknown_property = json!({
+            "id": 3,
+            "type": "edge",
+            "label": "item",
+            "property": "declarations",
+            "document": 1,
+            "inVs": [1],
+            "outV": 2,
+        });
+
+        for entry in &[missing_end, wrong_element, unknown_property] {
+            assert!(!validator.is_valid(entry), "{}", entry);
+        }
+    }
+}
+
+mod query_dir {
+    use super::{
+        assert_definition_in,
+        helpers::{get_elements_with_opts, test_opts, TestDir, TestsEmitter},
+    };
+    use crate::{error::LsifError, indexer::indexer::Indexer, protocol::types::Language};
+
+    #[test]
+    fn test_custom_query() {
+        let dir = TestDir::new("query-dir");
+        // Only functions are definitions, and they are all exported
+        dir.write(
+            "typescript.scm",
+            "(program) @scope\n\
+             (function_declaration name: (identifier) @definition.exported)\n\
+             (identifier) @reference\n",
+        );
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.query_dir = Some(dir.path().to_path_buf());
+        let elements = get_elements_with_opts(opts);
+
+        let monikers = elements.monikers();
+        assert!(!monikers.is_empty());
+        assert!(monikers.iter().all(|m| m.kind == "exported"));
+        assert!(monikers
+            .iter()
+            .any(|m| m.identifier.ends_with("index.ts:hello")));
+    }
+
+    #[test]
+    fn test_multi_capture_pattern() {
+        let root = TestDir::new("multi-capture");
+        let dir = TestDir::new("multi-capture-queries");
+        // The body of a function is only a scope through the pattern of the function
+        dir.write(
+            "typescript.scm",
+            "(program) @scope\n\
+             (function_declaration\n\
+                 name: (identifier) @definition.scoped\n\
+                 body: (statement_block) @scope)\n\
+             (variable_declarator name: (identifier) @definition.scoped)\n\
+             (identifier) @reference\n",
+        );
+        root.write(
+            "index.ts",
+            "function outer() {\n    let x = 1;\n    return x;\n}\nlet x = 2;\nouter(x);\n",
+        );
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.query_dir = Some(dir.path().to_path_buf());
+        let elements = get_elements_with_opts(opts);
+
+        let file_uri = root.uri("index.ts");
+        assert_definition_in(&elements, &file_uri, (2, 11), (1, 8)).unwrap();
+        assert_definition_in(&elements, &file_uri, (5, 6), (4, 4)).unwrap();
+        assert_definition_in(&elements, &file_uri, (5, 0), (0, 9)).unwrap();
+    }
+
+    #[test]
+    fn test_definition_captured_as_reference() {
+        let root = TestDir::new("self-reference");
+        let dir = TestDir::new("self-reference-queries");
+        // The name of the variable is both a definition and a reference
+        dir.write(
+            "typescript.scm",
+            "(program) @scope\n\
+             (variable_declarator name: (identifier) @definition.scoped)\n\
+             (identifier) @reference\n",
+        );
+        root.write("index.ts", "let x = 1;\nx;\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.query_dir = Some(dir.path().to_path_buf());
+        let elements = get_elements_with_opts(opts);
+
+        let file_uri = root.uri("index.ts");
+        let starts: Vec<_> = elements
+            .range_vertices()
+            .iter()
+            .map(|r| (r.range.start.line, r.range.start.character))
+            .collect();
+        assert_eq!(starts, vec![(0, 4), (1, 0)]);
+        let (_, document_id) = elements.project().unwrap();
+        let document_id = elements.contained_in(document_id)[0];
+        assert_eq!(elements.contained_in(document_id).len(), 2);
+        assert_definition_in(&elements, &file_uri, (1, 0), (0, 4)).unwrap();
+    }
+
+    #[test]
+    fn test_missing_query_file_falls_back_to_embedded_query() {
+        let dir = TestDir::new("query-dir-empty");
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.query_dir = Some(dir.path().to_path_buf());
+        let elements = get_elements_with_opts(opts);
+
+        assert!(elements.monikers().iter().any(|m| m.kind == "local"));
+    }
+
+    #[test]
+    fn test_invalid_custom_query() {
+        let dir = TestDir::new("query-dir-invalid");
+        dir.write("typescript.scm", "(not_a_node) @reference\n");
+
+        let mut opts = test_opts(Language::TypeScript);
+        opts.query_dir = Some(dir.path().to_path_buf());
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        match Indexer::index(opts, emitter).unwrap_err() {
+            LsifError::Query { language, kind, .. } => {
+                assert_eq!(language, Language::TypeScript);
+                assert_eq!(kind, "NodeType");
+            }
+            error => panic!("Unexpected error: {:?}", error),
+        }
+    }
+}
+
+mod capture_names {
+    use crate::{
+        analyzer::ffi::query_for_language, indexer::indexer::get_capture_names,
+        protocol::types::Language,
+    };
+
+    fn capture_names(query_src: &str) -> anyhow::Result<Vec<Option<String>>> {
+        let query = query_for_language(&Language::TypeScript, query_src)?;
+        get_capture_names(&query, query_src)
+    }
+
+    #[test]
+    fn test_capture_before_closing_parenthesis() {
+        let names = capture_names(
+            "(function_declaration name: (identifier) @definition.scoped)(program) @scope",
+        )
+        .unwrap();
+        assert_eq!(
+            names,
+            vec![
+                Some("definition.scoped".to_string()),
+                Some("scope".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiple_captures_per_pattern() {
+        let names = capture_names(
+            "(type_alias_declaration name: (_) @name) @definition.scoped\n\
+             ((identifier) @reference @other)\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names,
+            vec![
+                Some("definition.scoped".to_string()),
+                Some("reference".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_comments_strings_and_patterns_without_capture() {
+        let names = capture_names(
+            "; @definition.exported is not a capture\n\
+             (comment)\n\
+             ((identifier) @reference (#eq? @reference \"@scope\"))\n",
+        )
+        .unwrap();
+        assert_eq!(names, vec![None, Some("reference".to_string())]);
+    }
+
+    #[test]
+    fn test_predicates_and_inherit_comments() {
+        let names = capture_names(
+            "; inherits: ecma\n\
+             ((identifier) @name (#match? @name \"^[A-Z]\") (#set! kind \"@scope\")) @definition.scoped\n\
+             ; inherits: typescript\n\
+             ((identifier) @reference (#not-eq? @reference \"a) @scope\"))\n\
+             ((property_identifier) @reference.write (#any-of? @reference.write \"x\" \"y\"))\n",
+        )
+        .unwrap();
+        assert_eq!(
+            names,
+            vec![
+                Some("definition.scoped".to_string()),
+                Some("reference".to_string()),
+                Some("reference.write".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_unknown_capture() {
+        let error = capture_names("(program) @scope\n(identifier) @definition.unknown\n")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            error.contains("'@definition.unknown' in pattern 1"),
+            "{}",
+            error
+        );
+        assert!(error.contains("byte 30"), "{}", error);
+    }
+}
+
+mod diagnostics {
+    use super::helpers::{entry_validator, get_elements_with_opts, TestDir};
+    use crate::protocol::types::{DiagnosticSeverity, Language};
+
+    /// Creates a project with a file that misses the closing parenthesis of a call, and
+    /// returns the project root and the URI of the file.
+    fn project_with_syntax_error(name: &str) -> (TestDir, String) {
+        let root = TestDir::new(name);
+        root.write("valid.ts", "let x = 1;\n");
+        root.write("invalid.ts", "let y = 2;\nconsole.log(y;\n");
+
+        let uri = root.uri("invalid.ts");
+        (root, uri)
+    }
+
+    #[test]
+    fn test_syntax_error_diagnostic() {
+        let (root, uri) = project_with_syntax_error("diagnostics");
+        let elements = root.index(Language::TypeScript);
+
+        let diagnostics = elements.diagnostics_of(&uri);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        let diagnostic = &diagnostics[0];
+        assert_eq!(diagnostic.severity, Some(DiagnosticSeverity::Error));
+        assert_eq!(diagnostic.message, "Missing `)`");
+        assert_eq!(
+            (
+                diagnostic.range.start.line,
+                diagnostic.range.start.character
+            ),
+            (1, 13)
+        );
+
+        let valid_uri = root.uri("valid.ts");
+        assert!(elements.diagnostics_of(&valid_uri).is_empty());
+
+        let validator = entry_validator();
+        for entry in elements.to_json() {
+            assert!(validator.is_valid(&entry), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn test_no_diagnostics() {
+        let (root, uri) = project_with_syntax_error("no-diagnostics");
+        let mut opts = root.opts(Language::TypeScript);
+        opts.no_diagnostics = true;
+        let elements = get_elements_with_opts(opts);
+
+        assert!(elements.diagnostics_of(&uri).is_empty());
+    }
+
+    #[test]
+    fn test_todo_comments() {
+        let root = TestDir::new("todo-comments");
+        root.write(
+            "todo.ts",
+            "let x = 1;\n// TODO: fix\n/* FIXME later */\n// TODOS are not markers\n",
+        );
+        let uri = root.uri("todo.ts");
+
+        let mut opts = root.opts(Language::TypeScript);
+        assert!(get_elements_with_opts(opts.clone())
+            .diagnostics_of(&uri)
+            .is_empty());
+
+        opts.todo_comments = true;
+        let diagnostics = get_elements_with_opts(opts.clone()).diagnostics_of(&uri);
+        let todos: Vec<_> = diagnostics
+            .iter()
+            .map(|d| {
+                (
+                    d.range.start.line,
+                    d.range.start.character,
+                    d.message.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(todos, vec![(1, 0, "TODO: fix"), (2, 0, "FIXME later")]);
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.severity == Some(DiagnosticSeverity::Information)));
+
+        opts.todo_markers = vec!["FIXME".to_string()];
+        let diagnostics = get_elements_with_opts(opts).diagnostics_of(&uri);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+        assert_eq!(diagnostics[0].message, "FIXME later");
+    }
+}
+
+mod hover {
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::{analyzer::analyzer::MAX_HOVER_LINE_LENGTH, protocol::types::Language};
+
+    #[test]
+    fn test_identical_hovers_share_a_vertex() {
+        let root = TestDir::new("hover");
+        root.write("a.js", "const x = 1;\n");
+        root.write("b.js", "const x = 1;\nconst y = 2;\n");
+
+        let elements = root.index(Language::JavaScript);
+
+        let mut edge_counts: Vec<usize> = elements.hover_results().values().copied().collect();
+        edge_counts.sort_unstable();
+        // `x` in both files, and `y`
+        assert_eq!(edge_counts, vec![1, 2]);
+    }
+
+    #[test]
+    fn test_no_hover() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.no_hover = true;
+        let elements = get_elements_with_opts(opts);
+
+        assert!(elements.hover_results().is_empty());
+        assert!(!elements
+            .to_json()
+            .iter()
+            .any(|e| e["label"] == "textDocument/hover"));
+    }
+
+    #[test]
+    fn test_long_line_is_truncated() {
+        let root = TestDir::new("long-hover");
+        let values: Vec<String> = (0..1000).map(|i| i.to_string()).collect();
+        root.write("a.js", format!("const x = [{}];\n", values.join(",")));
+
+        let elements = root.index(Language::JavaScript);
+
+        let hover = elements.hover_values()[0].clone();
+        assert!(hover.starts_with("identifier x = [0,1,2"), "{}", hover);
+        assert!(hover.ends_with('…'), "{}", hover);
+        assert_eq!(hover.chars().count(), MAX_HOVER_LINE_LENGTH + 1);
+    }
+
+    #[test]
+    fn test_trailing_comments() {
+        let root = TestDir::new("trailing-comment");
+        root.write("a.ts", "const x = 1 // the answer\n// The question\nconst y = 42; // also the answer\nconst z = 0;\n");
+
+        let hovers = root.index(Language::TypeScript).hover_values();
+
+        assert!(hovers.contains(&"identifier x = 1 // the answer".to_string()));
+        assert!(hovers.contains(&"// The question\n// also the answer".to_string()));
+        // A trailing comment does not document the next definition
+        assert!(
+            hovers.contains(&"identifier z = 0;".to_string()),
+            "{:?}",
+            hovers
+        );
+    }
+}
+
+mod auto_language {
+    use super::helpers::{get_elements_with_opts, TestDir};
+    use crate::{
+        cli::{LanguageOverride, LanguageSelection},
+        indexer::indexer::discover_files,
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_mixed_project() {
+        let root = TestDir::new("auto-language");
+        root.write("index.ts", "let x = 1;\nconsole.log(x);\n");
+        root.write(
+            "schema.graphql",
+            "type Query {\n    user: User\n}\n\ntype User {\n    name: String\n}\n",
+        );
+        root.write("notes.txt", "not indexed\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.language = LanguageSelection::Auto;
+        assert_eq!(discover_files(&opts).unwrap().len(), 2);
+        let elements = get_elements_with_opts(opts);
+
+        let mut documents = elements.document_languages();
+        documents.sort();
+        assert_eq!(
+            documents,
+            vec![
+                (root.uri("index.ts"), "typescript".to_string()),
+                (root.uri("schema.graphql"), "graphql".to_string()),
+            ]
+        );
+        // `User` is defined in the GraphQL schema
+        let (_range, id) = elements
+            .find_range(&root.uri("schema.graphql"), (1, 10))
+            .unwrap();
+        let definition = elements.find_definition_ranges(id)[0];
+        assert_eq!((definition.start.line, definition.start.character), (4, 5));
+    }
+
+    #[test]
+    fn test_language_from_path() {
+        assert_eq!(
+            Language::from_path("a/b.tsx".as_ref()),
+            Some(Language::TypeScript)
+        );
+        assert_eq!(
+            Language::from_path("schema.graphql".as_ref()),
+            Some(Language::GraphQL)
+        );
+        // Lua cannot be parsed yet
+        assert_eq!(Language::from_path("init.lua".as_ref()), None);
+        assert_eq!(Language::from_path("Makefile".as_ref()), None);
+    }
+
+    #[test]
+    fn test_language_from_extension() {
+        assert_eq!(Language::from_extension("ts"), Some(Language::TypeScript));
+        assert_eq!(Language::from_extension("tsx"), Some(Language::TypeScript));
+        assert_eq!(Language::from_extension("graphql"), Some(Language::GraphQL));
+        assert_eq!(Language::from_extension("TS"), Some(Language::TypeScript));
+        assert_eq!(Language::from_extension("rs"), None);
+        assert_eq!(Language::from_extension(""), None);
+    }
+
+    #[test]
+    fn test_extension_case_is_ignored() {
+        let root = TestDir::new("extension-case");
+        root.write("index.TS", "let x = 1;\n");
+        root.write("index.js", "let y = 1;\n");
+
+        let opts = root.opts(Language::TypeScript);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("index.TS")]);
+    }
+
+    #[test]
+    fn test_language_override() {
+        let root = TestDir::new("language-override");
+        root.write("flow/typed.js", "let x: number = 1;\nx;\n");
+        root.write("flow/README.md", "# Flow\n");
+        root.write("plain.js", "let y = 1;\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.language = LanguageSelection::Auto;
+        opts.language_override = vec!["flow/**=typescript".parse().unwrap()];
+        let elements = get_elements_with_opts(opts.clone());
+
+        let mut documents = elements.document_languages();
+        documents.sort();
+        assert_eq!(
+            documents,
+            vec![
+                (root.uri("flow/typed.js"), "typescript".to_string()),
+                (root.uri("plain.js"), "javascript".to_string()),
+            ]
+        );
+        assert!(elements
+            .diagnostics_of(&root.uri("flow/typed.js"))
+            .is_empty());
+
+        // The overridden files are the files of their language
+        opts.language = LanguageSelection::Single(Language::TypeScript);
+        assert_eq!(
+            discover_files(&opts).unwrap(),
+            vec![root.join("flow/typed.js")]
+        );
+        opts.language = LanguageSelection::Single(Language::JavaScript);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("plain.js")]);
+
+        assert!("flow/**".parse::<LanguageOverride>().is_err());
+        assert!("flow/**=rust".parse::<LanguageOverride>().is_err());
+        // Lua files cannot be parsed yet
+        assert!("flow/**=lua".parse::<LanguageOverride>().is_err());
+    }
+}
+
+mod byte_offsets {
+    use super::helpers::{get_elements_with_opts, test_opts, TestDir};
+    use crate::protocol::types::Language;
+
+    const SOURCE: &str = "let café = 1;\nlet b = café;\n";
+
+    #[test]
+    fn test_byte_offsets_match_the_source() {
+        let root = TestDir::new("byte-offsets");
+        root.write("a.ts", SOURCE);
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.emit_byte_offsets = true;
+        let elements = get_elements_with_opts(opts);
+
+        let mut ranges: Vec<(usize, &str)> = elements
+            .range_vertices()
+            .into_iter()
+            .map(|r| {
+                let (start, end) = (r.start_byte.unwrap(), r.end_byte.unwrap());
+                (start, &SOURCE[start..end])
+            })
+            .collect();
+        ranges.sort_unstable();
+        assert_eq!(ranges, vec![(4, "café"), (19, "b"), (23, "café")]);
+    }
+
+    #[test]
+    fn test_no_byte_offsets_by_default() {
+        let elements = get_elements_with_opts(test_opts(Language::TypeScript));
+
+        for entry in elements.to_json() {
+            assert!(entry.get("startByte").is_none(), "{}", entry);
+            assert!(entry.get("endByte").is_none(), "{}", entry);
+        }
+    }
+}
+
+mod reexports {
+    use super::{
+        assert_definition_in,
+        helpers::{get_elements_with_opts, Elements, TestDir},
+    };
+    use crate::protocol::types::Language;
+
+    /// Indexes a project where `qux` is re-exported three times from `foo`, with the given
+    /// maximum import depth.
+    fn index_reexport_chain(name: &str, max_import_depth: usize) -> (TestDir, Elements) {
+        let root = TestDir::new(name);
+        root.write("a.ts", "// The original\nexport function foo() {}\n");
+        root.write("b.ts", "export { foo as bar } from './a';\n");
+        root.write("c.ts", "export { bar as baz } from './b';\n");
+        root.write("d.ts", "export { baz as qux } from './c';\n");
+        root.write("e.ts", "import { qux } from './d';\nqux();\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.max_import_depth = max_import_depth;
+        (root, get_elements_with_opts(opts))
+    }
+
+    #[test]
+    fn test_reexport_chain_within_limit() {
+        let (root, elements) = index_reexport_chain("reexports", 3);
+
+        assert_definition_in(&elements, &root.uri("e.ts"), (1, 0), (1, 16)).unwrap();
+    }
+
+    #[test]
+    fn test_reexport_chain_beyond_limit() {
+        let (root, elements) = index_reexport_chain("reexports-limit", 2);
+
+        let resolved = elements
+            .find_range(&root.uri("e.ts"), (1, 0))
+            .is_some_and(|(_, id)| !elements.find_definition_ranges(id).is_empty());
+        assert!(!resolved);
+        // The re-exports closer to the original are still followed
+        assert_definition_in(&elements, &root.uri("d.ts"), (0, 9), (1, 16)).unwrap();
+    }
+}
+
+mod unsupported_language {
+    use super::helpers::{TestDir, TestsEmitter};
+    use crate::{indexer::indexer::Indexer, protocol::types::Language};
+
+    #[test]
+    fn test_unsupported_grammar_is_an_error() {
+        let root = TestDir::new("unsupported-language");
+        root.write("init.lua", "local x = 1\n");
+
+        let opts = root.opts(Language::Lua);
+        let (emitter, _elements_receiver) = TestsEmitter::new();
+        let error = Indexer::index(opts, emitter).unwrap_err().to_string();
+
+        assert!(error.contains("Lua"), "{}", error);
+        assert!(error.contains("tree-sitter-lua"), "{}", error);
+    }
+}
+
+mod java {
+    use super::{assert_definition_in, helpers::TestDir};
+    use crate::protocol::types::Language;
+
+    #[test]
+    fn test_enum_members() {
+        let root = TestDir::new("java-enum");
+        root.write("Main.java", "enum Color { RED, GREEN }\n\nenum Light { RED }\n\nclass Main {\n    Color c = Color.RED;\n    Light l = Light.RED;\n    Object o = GREEN;\n}\n");
+
+        let elements = root.index(Language::Java);
+        let uri = root.uri("Main.java");
+        assert_definition_in(&elements, &uri, (5, 20), (0, 13)).unwrap();
+        assert_definition_in(&elements, &uri, (6, 20), (2, 13)).unwrap();
+        // A member is only found through its enum
+        assert!(assert_definition_in(&elements, &uri, (7, 15), (0, 18)).is_err());
+    }
+}
+
+mod config {
+    use super::helpers::TestDir;
+    use crate::{
+        cli::{LanguageSelection, Opts},
+        indexer::indexer::discover_files,
+        protocol::types::Language,
+    };
+
+    /// Creates a project with TypeScript files, one of them generated, and a JavaScript file.
+    fn create_project(name: &str) -> TestDir {
+        let root = TestDir::new(name);
+        root.write("a.ts", "let a = 1;\n");
+        root.write("generated/b.ts", "let b = 1;\n");
+        root.write("c.js", "let c = 1;\n");
+        root
+    }
+
+    #[test]
+    fn test_command_line_wins_over_config() {
+        let root = create_project("config");
+        root.write(
+            "lsif-os.toml",
+            "language = \"javascript\"\nexclude = [\"generated/**\"]\nno-hover = true\n\
+             threads = 4\n",
+        );
+
+        let opts = Opts::from_config_and_args(&[
+            "zas-lsif-tools",
+            root.path().to_str().unwrap(),
+            "typescript",
+            "--threads",
+            "2",
+        ])
+        .unwrap();
+        assert_eq!(
+            opts.language,
+            LanguageSelection::Single(Language::TypeScript)
+        );
+        assert_eq!(opts.threads, Some(2));
+        // The booleans of the config can only turn options on
+        assert!(opts.no_hover);
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("a.ts")]);
+
+        // The options of the config are used when none are given
+        let opts =
+            Opts::from_config_and_args(&["zas-lsif-tools", root.path().to_str().unwrap()]).unwrap();
+        assert_eq!(opts.threads, Some(4));
+        assert_eq!(discover_files(&opts).unwrap(), vec![root.join("c.js")]);
+    }
+
+    #[test]
+    fn test_explicit_config_file() {
+        let root = create_project("explicit-config");
+        let config_dir = TestDir::new("explicit-config-file");
+        let config = config_dir.write("lsif-os.toml", "exclude = [\"a.ts\", \"*.js\"]\n");
+
+        let opts = Opts::from_config_and_args(&[
+            "zas-lsif-tools",
+            root.path().to_str().unwrap(),
+            "--config",
+            config.to_str().unwrap(),
+            "--exclude",
+            "b.ts",
+        ])
+        .unwrap();
+        assert_eq!(opts.exclude, vec!["b.ts", "a.ts", "*.js"]);
+        assert!(discover_files(&opts).unwrap().is_empty());
+
+        // Unknown options are reported instead of being silently ignored
+        config_dir.write("lsif-os.toml", "jobs = 4\n");
+        let error = Opts::from_config_and_args(&[
+            "zas-lsif-tools",
+            root.path().to_str().unwrap(),
+            "--config",
+            config.to_str().unwrap(),
+        ])
+        .unwrap_err();
+        assert!(format!("{:#}", error).contains("jobs"), "{:#}", error);
+    }
+
+    #[test]
+    fn test_todo_markers() {
+        let root = create_project("todo-markers");
+        root.write(
+            "lsif-os.toml",
+            "todo-comments = true\ntodo-markers = [\"XXX\"]\n",
+        );
+        let root = root.path().to_str().unwrap();
+
+        let opts = Opts::from_config_and_args(&["zas-lsif-tools", root]).unwrap();
+        assert!(opts.todo_comments);
+        assert_eq!(opts.todo_markers, vec!["XXX"]);
+
+        let opts = Opts::from_config_and_args(&["zas-lsif-tools", root, "--todo-markers", "TODO"])
+            .unwrap();
+        assert_eq!(opts.todo_markers, vec!["TODO"]);
+
+        // Todo comments are emitted as diagnostics
+        let mut opts = Opts::from_config_and_args(&["zas-lsif-tools", root]).unwrap();
+        opts.no_diagnostics = true;
+        let error = opts.validate().unwrap_err().to_string();
+        assert_eq!(
+            error,
+            "--todo-comments cannot be used with --no-diagnostics"
+        );
+    }
+}
+
+mod shards {
+    use std::fs;
+
+    use serde_json::Value;
+
+    use super::helpers::{TestDir, TestsEmitter};
+    use crate::{indexer::shards::index_shards, merge::merge_dumps, protocol::types::Language};
+
+    #[test]
+    fn test_one_dump_per_package() {
+        let root = TestDir::new("shards");
+        let out = TestDir::new("shards-out");
+        let out_dir = out.path().to_path_buf();
+        root.write(
+            "pkg-a/package.json",
+            r#"{ "name": "pkg-a", "version": "1.0.0" }"#,
+        );
+        root.write("pkg-a/a.ts", "export function greet() {}\n");
+        root.write(
+            "pkg-b/package.json",
+            r#"{ "name": "pkg-b", "dependencies": { "pkg-a": "1.0.0" } }"#,
+        );
+        root.write("pkg-b/b.ts", "import { greet } from 'pkg-a';\ngreet();\n");
+        // Files at the top level are a package named after the project root
+        root.write("setup.ts", "let x = 1;\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        let shards = index_shards(&opts, &out_dir).unwrap();
+
+        let root_name = root.path().file_name().unwrap().to_string_lossy();
+        let outputs: Vec<_> = shards.iter().map(|shard| shard.output.clone()).collect();
+        assert_eq!(
+            outputs,
+            vec![
+                out_dir.join(format!("{}.json", root_name)),
+                out_dir.join("pkg-a.json"),
+                out_dir.join("pkg-b.json")
+            ]
+        );
+        let documents: Vec<_> = shards.iter().map(|shard| shard.stats.documents).collect();
+        assert_eq!(documents, vec![1, 1, 1]);
+        let monikers = |output, kind: &str| -> Vec<String> {
+            let dump = fs::read_to_string(output).unwrap();
+            let entries: Vec<Value> = dump
+                .lines()
+                .map(|line| serde_json::from_str(line).unwrap())
+                .collect();
+            let projects: Vec<_> = entries.iter().filter(|e| e["label"] == "project").collect();
+            assert_eq!(projects.len(), 1, "{}", dump);
+            entries
+                .iter()
+                .filter(|e| e["label"] == "moniker" && e["kind"] == kind)
+                .map(|e| e["identifier"].as_str().unwrap().to_string())
+                .collect()
+        };
+        // The reference to the other package is an import of its package, which the merge
+        // links to the export
+        assert_eq!(monikers(&outputs[1], "export"), vec!["pkg-a:a.ts:greet"]);
+        assert_eq!(monikers(&outputs[2], "import"), vec!["pkg-a:greet"]);
+        let (mut emitter, _elements_receiver) = TestsEmitter::new();
+        let stats = merge_dumps(&outputs[1..], &mut emitter).unwrap();
+        assert_eq!(stats.linked_monikers, 1);
+
+        opts.out_dir = Some(out_dir.clone());
+        opts.incremental = Some(root.join("cache"));
+        let error = opts.validate().unwrap_err().to_string();
+        assert_eq!(error, "--out-dir cannot be used with --incremental");
+    }
+}
+
+mod test_files {
+    use super::{
+        assert_definition_in,
+        helpers::{get_elements_with_opts, TestDir},
+    };
+    use crate::protocol::types::Language;
+
+    #[test]
+    fn test_tests_as_local() {
+        let root = TestDir::new("tests-as-local");
+        root.write(
+            "add.ts",
+            "export function add(a, b) {\n    return a + b;\n}\n",
+        );
+        root.write("math.test.ts", "export const add = () => {};\n\nadd();\n");
+        root.write("main.ts", "add(1, 2);\n");
+
+        let mut opts = root.opts(Language::TypeScript);
+        opts.treat_tests_as_local = true;
+        let elements = get_elements_with_opts(opts);
+
+        assert_definition_in(&elements, &root.uri("main.ts"), (0, 0), (0, 16)).unwrap();
+        // The test helper is still found from its own file
+        assert_definition_in(&elements, &root.uri("math.test.ts"), (2, 0), (0, 13)).unwrap();
+    }
+}
+
+mod definitions_only {
+    use super::helpers::{get_elements_with_opts, test_opts};
+    use crate::protocol::types::Language;
+
+    #[test]
+    fn test_no_references() {
+        let mut opts = test_opts(Language::TypeScript);
+        opts.definitions_only = true;
+        let entries = get_elements_with_opts(opts).to_json();
+
+        assert!(entries.iter().any(|e| e["label"] == "definitionResult"));
+        assert!(!entries.iter().any(|e| e["label"] == "referenceResult"
+            || e["label"] == "textDocument/references"
+            || e["property"] == "references"));
+    }
+}
+
+mod merge {
+    use std::{collections::HashSet, fs};
+
+    use super::helpers::{test_opts, TestDir, TestsEmitter};
+    use crate::{
+        emitter::{
+            file_emitter::{wait_until_flushed, FileEmitter, OutputFormat},
+            validating_emitter::ValidatingEmitter,
+        },
+        indexer::indexer::Indexer,
+        merge::merge_dumps,
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_merge_dumps() {
+        let root = TestDir::new("merge");
+        root.write(
+            "pkg-a/package.json",
+            r#"{ "name": "pkg-a", "version": "1.0.0" }"#,
+        );
+        root.write(
+            "pkg-a/a.ts",
+            "export function greet() {}\nexport function pad() {}\n",
+        );
+        root.write("pkg-c/c.ts", "export function bye() {}\n");
+        root.write(
+            "pkg-b/package.json",
+            r#"{ "name": "pkg-b", "dependencies": { "pkg-a": "1.0.0", "left-pad": "1.3.0" } }"#,
+        );
+        // Only the import from `pkg-a` refers to `pkg-a`: not the import of a symbol with the
+        // same name from another package, nor the import of a symbol of an unknown package,
+        // which `pkg-c` exports without a package
+        root.write(
+            "pkg-b/b.ts",
+            "import { greet } from 'pkg-a';\nimport { pad } from 'left-pad';\n\
+             greet();\npad();\nbye();\n",
+        );
+
+        let dumps: Vec<_> = ["pkg-a", "pkg-b", "pkg-c"]
+            .iter()
+            .map(|package| {
+                let mut opts = test_opts(Language::TypeScript);
+                opts.project_root = root.join(package);
+                opts.emit_external_monikers = true;
+                let output = root.join(format!("{}.json", package));
+                // The array format is read too
+                let (emitter, signal_receiver) =
+                    FileEmitter::new(fs::File::create(&output).unwrap(), OutputFormat::Array);
+                Indexer::index(opts, emitter).unwrap();
+                wait_until_flushed(&signal_receiver).unwrap();
+                output
+            })
+            .collect();
+
+        let (emitter, elements_receiver) = TestsEmitter::new();
+        let (mut emitter, validation_receiver) = ValidatingEmitter::new(emitter);
+        let stats = merge_dumps(&dumps, &mut emitter).unwrap();
+        validation_receiver.recv().unwrap().unwrap();
+        assert_eq!(stats.dumps, 3);
+        assert_eq!(stats.linked_monikers, 1);
+
+        let entries = elements_receiver.recv().unwrap().to_json();
+        let ids: HashSet<_> = entries.iter().map(|e| e["id"].as_u64().unwrap()).collect();
+        assert_eq!(ids.len(), entries.len());
+        let count = |label: &str| entries.iter().filter(|e| e["label"] == label).count();
+        assert_eq!(count("metaData"), 1);
+        assert_eq!(count("project"), 3);
+
+        // The import of `pkg-b` refers to the export of `pkg-a`
+        let moniker = |identifier: &str| {
+            entries
+                .iter()
+                .find(|e| e["label"] == "moniker" && e["identifier"] == identifier)
+                .unwrap()
+        };
+        let (import, export) = (moniker("pkg-a:greet"), moniker("pkg-a:a.ts:greet"));
+        assert_eq!(import["kind"], "import");
+        assert_eq!(export["kind"], "export");
+        let refers_to = entries.iter().find(|e| e["label"] == "refersTo").unwrap();
+        assert_eq!(refers_to["outV"], import["id"]);
+        assert_eq!(refers_to["inV"], export["id"]);
+    }
+}
+
+mod languages {
+    use crate::{
+        cli::{languages_list, Command},
+        protocol::types::Language,
+    };
+
+    #[test]
+    fn test_languages_list_matches_the_enum() {
+        let args: Vec<String> = vec!["zas-lsif-tools".to_string(), "languages".to_string()];
+        assert!(matches!(
+            Command::from_args_if_any(&args),
+            Some(Command::Languages)
+        ));
+
+        let list = languages_list();
+        let listed: Vec<&str> = list
+            .lines()
+            .filter_map(|line| line.strip_prefix("\t- "))
+            .collect();
+        let expected: Vec<String> = Language::all()
+            .iter()
+            .map(|language| match language {
+                Language::Lua => "Lua (not yet wired up)".to_string(),
+                _ => language.to_string(),
+            })
+            .collect();
+        assert_eq!(listed, expected);
+    }
+
+    #[test]
+    fn test_all_languages() {
+        // Adding a variant does not compile until it is given its position in `all()` here
+        let position = |language: Language| match language {
+            Language::JavaScript => 0,
+            Language::GraphQL => 1,
+            Language::Lua => 2,
+            Language::Java => 3,
+            Language::TypeScript => 4,
+        };
+        const VARIANT_COUNT: usize = 5;
+
+        assert_eq!(Language::all().len(), VARIANT_COUNT);
+        for (i, language) in Language::all().iter().enumerate() {
+            assert_eq!(position(*language), i);
+        }
+    }
+
+    #[test]
+    fn test_language_ids_are_listed() {
+        for language in Language::all() {
+            let mut extensions = language.get_extensions();
+            extensions.extend(
+                ["jsx", "tsx", "vue", "TSX", "txt", ""]
+                    .iter()
+                    .map(|e| e.to_string()),
+            );
+            for extension in extensions {
+                let path = format!("file.{}", extension);
+                let id = language.lsif_language_id(path.as_ref());
+                assert!(
+                    language.lsif_language_ids().contains(&id),
+                    "{} of {}",
+                    id,
+                    path
+                );
+            }
+        }
+    }
+}
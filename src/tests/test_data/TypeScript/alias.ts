@@ -0,0 +1,3 @@
+import { area as size } from './shapes';
+
+size(2);
@@ -0,0 +1,7 @@
+function Button(props: { label: string }) {
+    return <button>{props.label}</button>;
+}
+
+export function App() {
+    return <div><Button label="ok" /><Button label="no"></Button></div>;
+}
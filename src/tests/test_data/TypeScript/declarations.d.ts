@@ -0,0 +1,2 @@
+/** Greets the given person. */
+declare function greet(name: string): void;
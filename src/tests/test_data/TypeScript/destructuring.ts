@@ -0,0 +1,6 @@
+const source = JSON.parse("{}");
+const { a, b } = source;
+const [c, d] = source.list;
+const { e: renamed, f = 5, ...rest } = source;
+const [g = 8, , [h], ...others] = source.list;
+console.log(a, b, c, d, renamed, f, rest, g, h, others);
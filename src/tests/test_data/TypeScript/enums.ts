@@ -0,0 +1,11 @@
+enum Color {
+    Red,
+    Green = 2,
+}
+
+enum Light {
+    Red = "stop",
+}
+
+const favorite: Color = Color.Red;
+const warning = Light.Red + Color.Green;
@@ -0,0 +1,23 @@
+function helper() {
+    return 1;
+}
+
+function main() {
+    return helper() + later();
+
+    function helper() {
+        return 2;
+    }
+}
+
+function later() {
+    return 3;
+}
+
+function withVar(flag) {
+    if (flag) {
+        var hoisted = 1;
+        let scoped = 2;
+    }
+    return hoisted + scoped;
+}
@@ -0,0 +1,2 @@
+/** Adds two numbers. */
+export declare function add(a: number, b: number): number;
@@ -0,0 +1,25 @@
+function identity(value: number) {
+    return value;
+}
+
+let value = 1;
+
+function outer() {
+    return helper();
+
+    function helper() {
+        return 1;
+    }
+}
+
+function helper() {
+    return 2;
+}
+
+let count = 0;
+
+function increment(count: number) {
+    return count + 1;
+}
+
+console.log(count);
@@ -0,0 +1,3 @@
+export function area(r: number) {
+    return 3 * r * r;
+}
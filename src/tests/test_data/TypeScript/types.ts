@@ -0,0 +1,5 @@
+type Point = {
+    x: number;
+    y: number;
+};
+let origin: Point = { x: 0, y: 0 };
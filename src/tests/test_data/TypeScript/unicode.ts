@@ -0,0 +1,3 @@
+// ünïcödé comment, ✓
+let greeting = "héllo";
+console.log(greeting);
@@ -0,0 +1,2 @@
+let defined = 1;
+foo(undefinedSymbol, defined);
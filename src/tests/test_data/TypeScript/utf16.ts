@@ -0,0 +1,2 @@
+let café = "🎉"; let b = café;
+console.log(b, café);
@@ -0,0 +1,186 @@
+use std::process::Command;
+
+fn run(output: &str, args: &[&str]) -> std::process::Output {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let output = std::env::temp_dir().join(output);
+    Command::new(env!("CARGO_BIN_EXE_zas-lsif-tools"))
+        .arg(format!("{}/src/tests/test_data/TypeScript", manifest_dir))
+        .arg("typescript")
+        .arg("-o")
+        .arg(output)
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn test_quiet_has_no_output() {
+    let output = run("lsif-os-quiet-test.lsif", &["--quiet"]);
+
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn test_verbose_logs_timings_and_unresolved_references() {
+    let output = run("lsif-os-verbose-test.lsif", &["-vv"]);
+
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("parsed in"), "{}", stderr);
+    assert!(stderr.contains("Unresolved reference"), "{}", stderr);
+}
+
+#[test]
+fn test_output_in_missing_directory() {
+    let dir = std::env::temp_dir().join("lsif-os-nested-output-test");
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let output = run(
+        "lsif-os-nested-output-test/build/lsif/dump.json",
+        &["--quiet"],
+    );
+
+    assert!(output.status.success(), "{:?}", output);
+    assert!(dir.join("build/lsif/dump.json").is_file());
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_stats_before_project_root() {
+    let manifest_dir = env!("CARGO_MANIFEST_DIR");
+    let dump = std::env::temp_dir().join("lsif-os-stats-test.lsif");
+    // The project root is not taken as the path of the stats
+    let output = Command::new(env!("CARGO_BIN_EXE_zas-lsif-tools"))
+        .arg("--stats")
+        .arg(format!("{}/src/tests/test_data/TypeScript", manifest_dir))
+        .arg("typescript")
+        .arg("-o")
+        .arg(&dump)
+        .arg("--quiet")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("\"documents\""), "{}", stderr);
+
+    std::fs::remove_file(&dump).unwrap();
+}
+
+#[test]
+fn test_unwritable_stats_file() {
+    let output = run(
+        "lsif-os-unwritable-stats-test.lsif",
+        &["--quiet", "--stats-file", "/nonexistent/lsif-os/stats.json"],
+    );
+
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("Could not write the stats file"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn test_missing_project_root() {
+    let output = Command::new(env!("CARGO_BIN_EXE_zas-lsif-tools"))
+        .arg("/nonexistent/lsif-os-project")
+        .arg("typescript")
+        .output()
+        .unwrap();
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(
+            "The project root \"/nonexistent/lsif-os-project\" does not exist or is not a directory"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn test_invalid_flag_combination() {
+    let output = run(
+        "lsif-os-invalid-flags-test.lsif",
+        &["--append", "--output-format", "array"],
+    );
+
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("--append cannot be used with the array output format"),
+        "{}",
+        stderr
+    );
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+}
+
+#[test]
+fn test_invalid_query() {
+    let dir = std::env::temp_dir().join("lsif-os-invalid-query-test");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("typescript.scm"),
+        "(unknown_node) @definition.scoped\n",
+    )
+    .unwrap();
+
+    let output = run(
+        "lsif-os-invalid-query-test.lsif",
+        &["--quiet", "--query-dir", dir.to_str().unwrap()],
+    );
+
+    assert_eq!(output.status.code(), Some(1), "{:?}", output);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error in the query file"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_schema_subcommand() {
+    let output = Command::new(env!("CARGO_BIN_EXE_zas-lsif-tools"))
+        .arg("schema")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let schema: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert!(schema["definitions"].is_object(), "{}", schema);
+}
+
+#[test]
+fn test_merge_subcommand() {
+    let dump = std::env::temp_dir().join("lsif-os-merge-input-test.lsif");
+    let merged = std::env::temp_dir().join("lsif-os-merge-output-test.lsif");
+    assert!(run("lsif-os-merge-input-test.lsif", &["--quiet"])
+        .status
+        .success());
+
+    let output = Command::new(env!("CARGO_BIN_EXE_zas-lsif-tools"))
+        .arg("merge")
+        .arg(&dump)
+        .arg(&dump)
+        .arg("-o")
+        .arg(&merged)
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let lines = |path| std::fs::read_to_string(path).unwrap().lines().count();
+    // The metadata vertex of the second dump is dropped
+    assert_eq!(lines(&merged), 2 * lines(&dump) - 1);
+
+    std::fs::remove_file(&dump).unwrap();
+    std::fs::remove_file(&merged).unwrap();
+}
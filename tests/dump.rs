@@ -0,0 +1,108 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use serde_json::Value;
+use structopt::StructOpt;
+use zas_lsif_tools::{
+    cli::Opts,
+    emitter::file_emitter::{wait_until_flushed, FileEmitter, OutputFormat},
+    indexer::indexer::Indexer,
+    protocol::{
+        graph::LsifGraph,
+        types::{Entry, NumberOrString, Position, Url},
+    },
+};
+
+/// Indexes the project at the given root in the given language with a `FileEmitter`, and
+/// returns the content of the written dump.
+fn write_dump(root: &Path, language: &str, output: &str) -> String {
+    let output = std::env::temp_dir().join(output);
+    let mut opt = Opts::from_iter(&[
+        "zas-lsif-tools",
+        root.to_str().unwrap(),
+        language,
+        "--quiet",
+    ]);
+    opt.canonicalize_paths().unwrap();
+
+    let (emitter, signal_receiver) =
+        FileEmitter::new(fs::File::create(&output).unwrap(), OutputFormat::Ndjson);
+    Indexer::index(opt, emitter).unwrap();
+    wait_until_flushed(&signal_receiver).unwrap();
+
+    let content = fs::read_to_string(&output).unwrap();
+    fs::remove_file(&output).unwrap();
+    content
+}
+
+/// Asserts that each line of the given dump is an `Entry` that serializes back to the same
+/// JSON, that identifiers are unique and that every edge references existing vertices.
+fn assert_well_formed(dump: &str) {
+    let mut ids = HashSet::new();
+    let mut vertex_ids = HashSet::new();
+    let mut edges = vec![];
+    for line in dump.lines() {
+        let entry: Entry = serde_json::from_str(line).unwrap();
+        let json: Value = serde_json::from_str(line).unwrap();
+        assert_eq!(serde_json::to_value(&entry).unwrap(), json, "{}", line);
+
+        let id = match entry.id {
+            NumberOrString::Number(id) => id,
+            NumberOrString::String(_) => panic!("Unexpected string identifier: {}", line),
+        };
+        assert!(ids.insert(id), "Duplicate identifier: {}", line);
+        match json["type"].as_str() {
+            Some("vertex") => {
+                vertex_ids.insert(id);
+            }
+            Some("edge") => edges.push(json),
+            _ => panic!("Unexpected entry type: {}", line),
+        }
+    }
+
+    // The endpoints are checked once all the vertices are known
+    for edge in edges {
+        let mut endpoints = edge["inVs"].as_array().cloned().unwrap_or_default();
+        endpoints.push(edge["outV"].clone());
+        endpoints.extend(edge.get("inV").cloned());
+        for id in endpoints {
+            assert!(vertex_ids.contains(&id.as_u64().unwrap()), "{}", edge);
+        }
+    }
+}
+
+#[test]
+fn test_fixtures_dump_is_well_formed() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/tests/test_data/TypeScript");
+    let dump = write_dump(&root, "typescript", "lsif-os-fixtures-dump-test.lsif");
+
+    assert_well_formed(&dump);
+    assert!(dump.lines().count() > 100);
+}
+
+#[test]
+fn test_unicode_dump_is_well_formed() {
+    let root = std::env::temp_dir().join("lsif-os-unicode-dump-test");
+    let _ = fs::remove_dir_all(&root);
+    fs::create_dir_all(&root).unwrap();
+    fs::write(
+        root.join("café.ts"),
+        "// ✓ ünïcödé\nconst 𝑓 = \"😀\"; const café = 𝑓;\nconsole.log(café, 𝑓);\n",
+    )
+    .unwrap();
+
+    let dump = write_dump(&root, "typescript", "lsif-os-unicode-dump-test.lsif");
+    assert_well_formed(&dump);
+
+    // Positions are in UTF-16 code units, `𝑓` and `😀` are two each
+    let graph = LsifGraph::from_json(&dump).unwrap();
+    let uri = Url::from_file_path(root.canonicalize().unwrap().join("café.ts"))
+        .unwrap()
+        .to_string();
+    let definitions = graph.definitions(&uri, Position::new(2, 12));
+    assert_eq!(definitions[0].range.start, Position::new(1, 23));
+    let definitions = graph.definitions(&uri, Position::new(2, 18));
+    assert_eq!(definitions[0].range.start, Position::new(1, 6));
+    assert!(dump.contains("✓ ünïcödé"));
+
+    fs::remove_dir_all(&root).unwrap();
+}